@@ -0,0 +1,21 @@
+CREATE EXTENSION IF NOT EXISTS vector;
+
+CREATE TABLE savoir_documents (
+    uuid UUID PRIMARY KEY,
+    external_id TEXT NOT NULL,
+    parent_id TEXT,
+    source TEXT,
+    mime_type TEXT,
+    modified_at TIMESTAMPTZ,
+    document JSONB NOT NULL,
+    -- Without dimensions, so that they can change, each size getting its own index.
+    embedding vector NOT NULL,
+    search tsvector GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', coalesce(document->>'name', '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(document->>'content', '')), 'B')
+    ) STORED
+);
+
+CREATE INDEX savoir_documents_source ON savoir_documents (source);
+CREATE INDEX savoir_documents_parent_id ON savoir_documents (parent_id);
+CREATE INDEX savoir_documents_search ON savoir_documents USING GIN (search);
@@ -0,0 +1,4 @@
+CREATE TABLE savoir_settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
@@ -1,7 +1,14 @@
 use serde::Deserialize;
 
+use crate::{document_store::Query, reranker};
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub llm: String,
     pub prompt: String,
+    /// Retrieves the candidates, usually more documents than the reranker keeps.
+    #[serde(default)]
+    pub query: Query,
+    #[serde(default)]
+    pub reranker: reranker::Config,
 }
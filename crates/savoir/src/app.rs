@@ -1,41 +1,111 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
-use anyhow::Result;
-use log::{error, info};
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
 use serde::Deserialize;
 use tokio::sync::{mpsc, Mutex};
-use weaviate_community::WeaviateClient;
 
 use crate::{
     agent,
+    chunking::{self, Chunker},
     conversation::Conversation,
     conversation_store::{in_memory::InMemoryConversationStore, ConversationStore},
-    datasource::{self, Datasource},
-    document::Document,
-    document_store::{self, DocumentStore},
+    datasource::{self, Datasource, Event, Listing, State},
+    document::{markdown, Document},
+    document_store::{self, DocumentStore, Query, ScoredDocument},
+    embedder::cache::{self, EmbeddingCache, Stats},
     integration::{self, Integration},
     interals::AsyncTryFrom,
+    ledger::{self, Entries, Entry, Ledger},
     llm::{self, Llm},
     message::{self, Message},
+    reranker::Reranker,
 };
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     datasources: HashMap<String, datasource::Config>,
     llms: HashMap<String, llm::Config>,
+    #[serde(default)]
     store: document_store::Config,
     agents: HashMap<String, agent::Config>,
     integrations: HashMap<String, integration::Config>,
+    #[serde(default)]
+    ledger: ledger::Config,
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    pub id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: Vec<Failure>,
+    pub deleted: Vec<String>,
+    /// Why the datasource could not be fully read, in which case nothing was deleted.
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} created, {} updated, {} unchanged, {} failed, {} deleted",
+            self.created,
+            self.updated,
+            self.unchanged,
+            self.failed.len(),
+            self.deleted.len()
+        )?;
+
+        match &self.error {
+            Some(error) => write!(f, " (incomplete: {error})"),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct App {
     document_store: Box<dyn DocumentStore>,
+    embedding_cache: Option<cache::Config>,
     datasources: HashMap<String, Arc<Box<dyn Datasource>>>,
+    chunkers: HashMap<String, Box<dyn Chunker>>,
     llms: HashMap<String, Arc<Box<dyn Llm>>>,
     agents: HashMap<String, agent::Config>,
+    rerankers: HashMap<String, Box<dyn Reranker>>,
     integrations: HashMap<String, integration::Config>,
     conversation_store: Arc<Mutex<Box<dyn ConversationStore>>>,
+    ledger: Box<dyn Ledger>,
+    batch_size: usize,
+}
+
+/// Documents waiting to be stored together, with the documents they were split from.
+#[derive(Default)]
+struct Batch {
+    documents: Vec<Document>,
+    pending: Vec<Pending>,
+}
+
+struct Pending {
+    id: String,
+    entry: Entry,
+    created: bool,
+    chunks: Vec<String>,
 }
 
 #[async_trait::async_trait]
@@ -44,14 +114,19 @@ impl AsyncTryFrom<Config> for App {
 
     async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
         let mut datasources: HashMap<String, Arc<Box<dyn Datasource>>> = HashMap::new();
+        let mut chunkers: HashMap<String, Box<dyn Chunker>> = HashMap::new();
         let mut llms: HashMap<String, Arc<Box<dyn Llm>>> = HashMap::new();
 
-        let document_store: Box<dyn DocumentStore> = match value.store {
-            document_store::Config::Weaviate(config) => Box::new(WeaviateClient::try_from(config)?),
-        };
+        let embedding_cache = value
+            .store
+            .embedder()
+            .map(|embedder| embedder.cache.clone())
+            .filter(|cache| cache.enabled);
+        let document_store: Box<dyn DocumentStore> = Box::async_try_from(value.store).await?;
 
         for (name, config) in value.datasources {
-            let datasource: Box<dyn Datasource> = Box::async_try_from(config).await?;
+            let datasource: Box<dyn Datasource> = Box::async_try_from(config.source).await?;
+            chunkers.insert(name.clone(), Box::from(config.chunking));
             datasources.insert(name, Arc::new(datasource));
         }
 
@@ -60,13 +135,25 @@ impl AsyncTryFrom<Config> for App {
             llms.insert(name, Arc::new(llm));
         }
 
+        let mut agents = value.agents;
+        let mut rerankers = HashMap::new();
+        for (name, agent) in agents.iter_mut() {
+            let provider = std::mem::take(&mut agent.reranker.provider);
+            rerankers.insert(name.clone(), provider.build(&llms)?);
+        }
+
         Ok(Self {
             document_store,
+            embedding_cache,
             datasources,
+            chunkers,
             llms,
-            agents: value.agents,
+            agents,
+            rerankers,
             integrations: value.integrations,
             conversation_store: Arc::new(Mutex::new(Box::<InMemoryConversationStore>::default())),
+            ledger: Box::try_from(value.ledger)?,
+            batch_size: value.batch_size.max(1),
         })
     }
 }
@@ -75,6 +162,8 @@ impl AsyncTryFrom<Config> for App {
 enum Error {
     #[error("The {0} {1} does not exist in the configuration")]
     ResourceNotFound(String, String),
+    #[error("The embedding cache is not enabled for this store")]
+    CacheDisabled,
 }
 
 impl App {
@@ -87,6 +176,15 @@ impl App {
         Ok(datasource)
     }
 
+    fn chunker(&self, name: &str) -> Result<&dyn Chunker> {
+        let chunker = self.chunkers.get(name).ok_or(Error::ResourceNotFound(
+            "datasource".to_string(),
+            name.to_string(),
+        ))?;
+
+        Ok(chunker.as_ref())
+    }
+
     pub fn agent(&self, name: &str) -> Result<&agent::Config> {
         let agent = self.agents.get(name).ok_or(Error::ResourceNotFound(
             "agent".to_string(),
@@ -96,6 +194,15 @@ impl App {
         Ok(agent)
     }
 
+    fn reranker(&self, name: &str) -> Result<&dyn Reranker> {
+        let reranker = self.rerankers.get(name).ok_or(Error::ResourceNotFound(
+            "agent".to_string(),
+            name.to_string(),
+        ))?;
+
+        Ok(reranker.as_ref())
+    }
+
     pub fn integration(&self, name: &str) -> Result<&integration::Config> {
         let integration = self.integrations.get(name).ok_or(Error::ResourceNotFound(
             "integration".to_string(),
@@ -114,16 +221,54 @@ impl App {
         Ok(llm)
     }
 
-    pub async fn query(&self, query: &str) -> Result<Vec<Document>> {
+    pub async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
         self.document_store.query(query).await
     }
 
+    pub async fn migrate_store(&self) -> Result<Vec<String>> {
+        self.document_store.migrate().await
+    }
+
+    fn embedding_cache(&self) -> Result<EmbeddingCache> {
+        let config = self.embedding_cache.as_ref().ok_or(Error::CacheDisabled)?;
+
+        EmbeddingCache::open(&config.path)
+    }
+
+    pub fn cache_stats(&self) -> Result<Vec<Stats>> {
+        self.embedding_cache()?.stats()
+    }
+
+    /// Removes the cached vectors unused for `unused_for`, or all the vectors of `model`.
+    pub fn prune_cache(
+        &self,
+        unused_for: Option<chrono::Duration>,
+        model: Option<&str>,
+    ) -> Result<usize> {
+        let unused_since = unused_for
+            .map(|duration| {
+                chrono::Utc::now()
+                    .checked_sub_signed(duration)
+                    .ok_or_else(|| anyhow!("Cannot prune the vectors unused for {duration}"))
+            })
+            .transpose()?;
+
+        self.embedding_cache()?.prune(unused_since, model)
+    }
+
     // TODO: Split & clean that
     pub async fn ask(&self, agent: &str, conversation_id: &str, query: &str) -> Result<String> {
+        let reranker = self.reranker(agent)?;
         let agent = self.agent(agent)?;
         let llm = self.llm(&agent.llm)?;
 
-        let documents = self.document_store.query(query).await?;
+        let request = agent.query.clone().with_text(query);
+        let documents = self.document_store.query(&request).await?;
+
+        let mut documents = reranker.rerank(query, documents).await?;
+        if let Some(top) = agent.reranker.top {
+            documents.truncate(top);
+        }
 
         info!("Found {} documents", documents.len());
 
@@ -156,33 +301,532 @@ impl App {
         Ok(res)
     }
 
-    pub async fn synchronize(&self, name: &str) -> Result<()> {
-        info!("Synchronizing datasource {name}");
-        let datasource = self.datasource(name)?.clone();
-        let (tx, mut rx) = mpsc::channel(32);
+    fn prepare(&self, chunker: &dyn Chunker, document: Document) -> Vec<Document> {
+        let documents = match markdown::is_markdown(&document) {
+            true => markdown::split(document),
+            false => vec![document],
+        };
 
-        tokio::spawn(async move {
-            datasource.stream_documents(tx).await;
-        });
+        documents
+            .into_iter()
+            .flat_map(|document| chunking::chunk(chunker, document))
+            .collect()
+    }
 
-        while let Some(document) = rx.recv().await {
-            info!("Synchronizing document {name}:{}", &document.id);
-            let res = self.document_store.store(&document).await;
-            if let Err(e) = res {
+    async fn store_batch(
+        &self,
+        name: &str,
+        batch: Batch,
+        dry_run: bool,
+        report: &mut SyncReport,
+        updated: &mut HashSet<String>,
+    ) -> Result<()> {
+        let failures = match dry_run {
+            true => Ok(HashMap::new()),
+            false => {
+                info!("Storing {} documents", batch.documents.len());
+                self.document_store.store_batch(&batch.documents).await
+            }
+        };
+
+        for pending in batch.pending {
+            let reason = match &failures {
+                Ok(failures) => pending
+                    .chunks
+                    .iter()
+                    .find_map(|id| failures.get(id))
+                    .cloned(),
+                Err(e) => Some(e.to_string()),
+            };
+
+            if let Some(reason) = reason {
                 error!(
-                    "Error while synchronizing document '{}': {}",
-                    document.id,
-                    e.to_string()
+                    "Error while synchronizing document '{}': {reason}",
+                    pending.id
                 );
+                report.failed.push(Failure {
+                    id: pending.id,
+                    reason,
+                });
+                continue;
+            }
+
+            match pending.created {
+                true => report.created += 1,
+                false => report.updated += 1,
+            };
+            if !dry_run {
+                self.ledger.put(name, &pending.id, pending.entry).await?;
             }
+            updated.insert(pending.id);
         }
 
         Ok(())
     }
 
+    /// Synchronizes a datasource into the document store. `full` ignores the cursor of the
+    /// last synchronization so datasources list every document again.
+    pub async fn synchronize(&self, name: &str, dry_run: bool, full: bool) -> Result<SyncReport> {
+        let report = self.synchronize_documents(name, dry_run, full).await;
+
+        // Documents stored before an error are recorded, not to be stored again.
+        if !dry_run {
+            self.ledger.flush().await?;
+        }
+
+        report
+    }
+
+    async fn synchronize_documents(
+        &self,
+        name: &str,
+        dry_run: bool,
+        full: bool,
+    ) -> Result<SyncReport> {
+        info!("Synchronizing datasource {name}");
+        let datasource = self.datasource(name)?.clone();
+        let chunker = self.chunker(name)?;
+        let known = self.ledger.entries(name).await?;
+        let (tx, mut rx) = mpsc::channel(32);
+
+        // Documents split by another chunker are fetched again to be split anew, listing
+        // the whole datasource rather than its changes.
+        let chunking = chunker.id();
+        let (entries, rechunked): (Entries, Entries) = known
+            .clone()
+            .into_iter()
+            .partition(|(_, entry)| entry.chunking == chunking);
+        if !rechunked.is_empty() {
+            info!("The chunking of {name} changed, splitting its documents again");
+        }
+
+        let state = State {
+            entries,
+            cursor: match full || !rechunked.is_empty() {
+                true => None,
+                false => self.ledger.cursor(name).await?,
+            },
+            failed: self.ledger.failed(name).await?,
+        };
+        let handle = tokio::spawn(async move { datasource.stream_documents(&state, tx).await });
+
+        let mut report = SyncReport::default();
+        let mut seen = HashSet::new();
+        let mut updated = HashSet::new();
+        let mut stored = HashSet::new();
+        let mut deleted = HashSet::new();
+        let mut cursor = None;
+        let mut batch = Batch::default();
+
+        while let Some(event) = rx.recv().await {
+            let mut document = match event {
+                Event::Document(document) => *document,
+                Event::Unchanged(id) => {
+                    report.unchanged += 1;
+                    seen.insert(id);
+                    continue;
+                }
+                Event::Deleted(id) => {
+                    deleted.insert(id);
+                    continue;
+                }
+                Event::Cursor(value) => {
+                    cursor = Some(value);
+                    continue;
+                }
+                Event::Failed(id, reason) => {
+                    error!("Cannot read document '{id}': {reason}");
+                    seen.insert(id.clone());
+                    report.failed.push(Failure { id, reason });
+                    continue;
+                }
+            };
+
+            let id = document.id.clone();
+            seen.insert(id.clone());
+
+            let entry = Entry {
+                modified_at: document.provenance.modified_at,
+                content_hash: document.hash(),
+                chunking: chunking.clone(),
+            };
+
+            document.provenance.source = Some(name.to_string());
+            document.provenance.source_id = Some(id.clone());
+            document.provenance.content_hash = Some(entry.content_hash.clone());
+
+            let previous = known.get(&id);
+            if previous.is_some_and(|previous| {
+                previous.content_hash == entry.content_hash && previous.chunking == entry.chunking
+            }) {
+                report.unchanged += 1;
+                if previous != Some(&entry) && !dry_run {
+                    self.ledger.put(name, &id, entry).await?;
+                }
+                continue;
+            }
+
+            info!("Synchronizing document {name}:{id}");
+            let documents = self.prepare(chunker, document);
+            stored.extend(documents.iter().map(|document| document.id.clone()));
+
+            batch.pending.push(Pending {
+                id,
+                entry,
+                created: previous.is_none(),
+                chunks: documents
+                    .iter()
+                    .map(|document| document.id.clone())
+                    .collect(),
+            });
+            batch.documents.extend(documents);
+
+            if batch.documents.len() >= self.batch_size {
+                let batch = std::mem::take(&mut batch);
+                self.store_batch(name, batch, dry_run, &mut report, &mut updated)
+                    .await?;
+            }
+        }
+
+        if !batch.pending.is_empty() {
+            self.store_batch(name, batch, dry_run, &mut report, &mut updated)
+                .await?;
+        }
+
+        match handle.await {
+            Ok(Ok(listing)) => {
+                // Documents that were not listed or reported deleted have been removed from
+                // the datasource, and chunks that were not stored again belong to a document
+                // that shrunk.
+                let documents = match listing {
+                    Listing::Changes if updated.is_empty() && deleted.is_empty() => vec![],
+                    _ => self.document_store.list(name).await?,
+                };
+
+                for document in documents {
+                    let source_id = document
+                        .provenance
+                        .source_id
+                        .as_ref()
+                        .unwrap_or(&document.id);
+                    let removed = match listing {
+                        Listing::Full => !seen.contains(source_id),
+                        Listing::Changes => deleted.contains(source_id),
+                    };
+                    let stale = updated.contains(source_id) && !stored.contains(&document.id);
+
+                    if !removed && !stale {
+                        continue;
+                    }
+
+                    if !dry_run {
+                        info!("Deleting document {name}:{}", document.id);
+                        self.document_store.delete(&document).await?;
+                        if removed {
+                            self.ledger.remove(name, source_id).await?;
+                        }
+                    }
+                    report.deleted.push(document.id);
+                }
+
+                if !dry_run {
+                    for id in &deleted {
+                        self.ledger.remove(name, id).await?;
+                    }
+                }
+
+                // Failed documents are recorded to be fetched again next time, whatever
+                // changed since the cursor.
+                if !dry_run {
+                    let failed: Vec<String> = report.failed.iter().map(|f| f.id.clone()).collect();
+                    self.ledger.set_failed(name, &failed).await?;
+                    if let Some(cursor) = &cursor {
+                        self.ledger.set_cursor(name, cursor).await?;
+                    }
+                }
+            }
+            Ok(Err(e)) => {
+                warn!("Datasource {name} did not complete, skipping deletions: {e}");
+                report.error = Some(format!("{e:#}"));
+            }
+            Err(e) => {
+                warn!("Datasource {name} did not complete, skipping deletions: {e}");
+                report.error = Some(e.to_string());
+            }
+        }
+
+        Ok(report)
+    }
+
     pub async fn run_integration(self, name: &str) -> Result<()> {
         let integration = self.integration(name)?;
         let integration: Box<dyn Integration> = Box::try_from(integration.clone()).unwrap();
         integration.serve(self).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Mutex as SyncMutex};
+
+    use tokio::sync::mpsc::Sender;
+
+    use crate::{document::Provenance, document_store::sqlite::SqliteStore};
+
+    use super::*;
+
+    /// Reports the documents changed since the cursor, which counts the synchronizations,
+    /// and the failed ones again.
+    #[derive(Debug, Default)]
+    struct Changes {
+        changed: SyncMutex<Vec<String>>,
+        failing: SyncMutex<HashSet<String>>,
+        states: SyncMutex<Vec<(Option<String>, Vec<String>)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Datasource for Arc<Changes> {
+        async fn stream_documents(&self, state: &State, tx: Sender<Event>) -> Result<Listing> {
+            self.states
+                .lock()
+                .unwrap()
+                .push((state.cursor.clone(), state.failed.clone()));
+
+            let mut ids: Vec<String> = self.changed.lock().unwrap().drain(..).collect();
+            ids.extend(state.failed.iter().cloned());
+
+            for id in ids {
+                let event = match self.failing.lock().unwrap().contains(&id) {
+                    true => Event::Failed(id, "unavailable".to_string()),
+                    false => Event::Document(Box::new(Document {
+                        name: id.clone(),
+                        content: format!("Content of {id}"),
+                        id,
+                        url: None,
+                        metadata: Default::default(),
+                        provenance: Provenance::default(),
+                        chunk: None,
+                    })),
+                };
+                let _ = tx.send(event).await;
+            }
+
+            let count = state.cursor.as_deref().map_or(0, |c| c.parse().unwrap());
+            let _ = tx.send(Event::Cursor((count + 1).to_string())).await;
+
+            Ok(Listing::Changes)
+        }
+    }
+
+    /// Stores documents but cannot list them, which fails synchronizations once the
+    /// documents are stored.
+    #[derive(Debug)]
+    struct Unlistable(SqliteStore);
+
+    #[async_trait::async_trait]
+    impl DocumentStore for Unlistable {
+        async fn store(&self, document: &Document) -> Result<()> {
+            self.0.store(document).await
+        }
+
+        async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
+            self.0.query(query).await
+        }
+
+        async fn delete(&self, document: &Document) -> Result<()> {
+            self.0.delete(document).await
+        }
+
+        async fn list(&self, _source: &str) -> Result<Vec<Document>> {
+            anyhow::bail!("the store is unavailable")
+        }
+    }
+
+    fn sqlite(dir: &Path) -> SqliteStore {
+        let store = serde_yaml::from_str(&format!(
+            "{{ path: {}, embedder: {{ type: hashing, dimensions: 8 }} }}",
+            dir.join("store.sqlite").display()
+        ))
+        .unwrap();
+
+        SqliteStore::open(store).unwrap()
+    }
+
+    fn app(dir: &Path, datasource: Box<dyn Datasource>, chunking: chunking::Config) -> App {
+        App {
+            document_store: Box::new(sqlite(dir)),
+            embedding_cache: None,
+            datasources: HashMap::from([("docs".to_string(), Arc::new(datasource))]),
+            chunkers: HashMap::from([("docs".to_string(), Box::from(chunking))]),
+            llms: HashMap::new(),
+            agents: HashMap::new(),
+            rerankers: HashMap::new(),
+            integrations: HashMap::new(),
+            conversation_store: Arc::new(Mutex::new(Box::<InMemoryConversationStore>::default())),
+            ledger: Box::try_from(ledger::Config::Json {
+                path: dir.join("ledger.json"),
+            })
+            .unwrap(),
+            batch_size: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_failed_documents_after_the_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let changes = Arc::new(Changes::default());
+        let app = app(
+            dir.path(),
+            Box::new(changes.clone()),
+            chunking::Config::default(),
+        );
+
+        *changes.changed.lock().unwrap() = vec!["a".to_string(), "b".to_string()];
+        changes.failing.lock().unwrap().insert("b".to_string());
+        let report = app.synchronize("docs", false, false).await.unwrap();
+        assert_eq!((report.created, report.failed.len()), (1, 1));
+        assert_eq!(
+            app.ledger.cursor("docs").await.unwrap().as_deref(),
+            Some("1")
+        );
+        assert_eq!(app.ledger.failed("docs").await.unwrap(), ["b"]);
+
+        // Still failing, the document is kept for the next synchronization.
+        let report = app.synchronize("docs", false, false).await.unwrap();
+        assert_eq!((report.created, report.failed.len()), (0, 1));
+        assert_eq!(app.ledger.failed("docs").await.unwrap(), ["b"]);
+
+        changes.failing.lock().unwrap().clear();
+        let report = app.synchronize("docs", false, false).await.unwrap();
+        assert_eq!((report.created, report.failed.len()), (1, 0));
+        assert!(app.ledger.failed("docs").await.unwrap().is_empty());
+        assert_eq!(
+            app.ledger.cursor("docs").await.unwrap().as_deref(),
+            Some("3")
+        );
+
+        assert_eq!(
+            *changes.states.lock().unwrap(),
+            [
+                (None, vec![]),
+                (Some("1".to_string()), vec!["b".to_string()]),
+                (Some("2".to_string()), vec!["b".to_string()]),
+            ]
+        );
+    }
+
+    async fn filesystem(dir: &Path) -> Box<dyn Datasource> {
+        let config = format!("{{ type: filesystem, paths: [{}] }}", dir.display());
+        Box::async_try_from(serde_yaml::from_str::<datasource::Source>(&config).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn splits_documents_again_when_the_chunking_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("docs");
+        std::fs::create_dir(&files).unwrap();
+        std::fs::write(files.join("guide.txt"), "one two three four five six seven").unwrap();
+
+        let whole = app(dir.path(), filesystem(&files).await, chunking::Config::None);
+        let report = whole.synchronize("docs", false, false).await.unwrap();
+        assert_eq!(report.created, 1);
+        drop(whole);
+
+        let chunking = chunking::Config::Tokens {
+            size: 4,
+            overlap: 0,
+        };
+        let chunked = app(dir.path(), filesystem(&files).await, chunking);
+        let report = chunked.synchronize("docs", false, false).await.unwrap();
+        assert_eq!((report.updated, report.unchanged), (1, 0));
+        assert_eq!(report.deleted, ["guide.txt"]);
+
+        let mut ids: Vec<String> = chunked
+            .document_store
+            .list("docs")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|document| document.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, ["guide.txt:0", "guide.txt:1"]);
+
+        let report = chunked.synchronize("docs", false, false).await.unwrap();
+        assert_eq!((report.updated, report.unchanged), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn chunks_markdown_files_within_their_sections() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("docs");
+        std::fs::create_dir(&files).unwrap();
+        std::fs::write(
+            files.join("guide.md"),
+            "# Setup\n\nInstall the tool.\n\nConfigure the tool.\n\n## Usage\n\nRun it.\n",
+        )
+        .unwrap();
+
+        let app = app(
+            dir.path(),
+            filesystem(&files).await,
+            chunking::Config::Headings { max_tokens: 4 },
+        );
+        app.synchronize("docs", false, false).await.unwrap();
+
+        let mut chunks: Vec<(String, String, String)> = app
+            .document_store
+            .list("docs")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|document| (document.id, document.name, document.content))
+            .collect();
+        chunks.sort();
+        let chunk = |id: &str, name: &str, content: &str| {
+            (id.to_string(), name.to_string(), content.to_string())
+        };
+        assert_eq!(
+            chunks,
+            [
+                chunk(
+                    "guide.md#setup:0",
+                    "guide.md > Setup",
+                    "Setup\n\nInstall the tool."
+                ),
+                chunk(
+                    "guide.md#setup:1",
+                    "guide.md > Setup",
+                    "Configure the tool."
+                ),
+                chunk(
+                    "guide.md#usage",
+                    "guide.md > Setup > Usage",
+                    "Usage\n\nRun it."
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_the_documents_stored_before_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("docs");
+        std::fs::create_dir(&files).unwrap();
+        std::fs::write(files.join("guide.txt"), "Read the guide.").unwrap();
+
+        let failing = App {
+            document_store: Box::new(Unlistable(sqlite(dir.path()))),
+            ..app(dir.path(), filesystem(&files).await, chunking::Config::None)
+        };
+        let error = failing.synchronize("docs", false, false).await.unwrap_err();
+        assert!(error.to_string().contains("unavailable"), "{error}");
+        drop(failing);
+
+        let app = app(dir.path(), filesystem(&files).await, chunking::Config::None);
+        let report = app.synchronize("docs", false, false).await.unwrap();
+        assert_eq!((report.created, report.unchanged), (0, 1));
+    }
+}
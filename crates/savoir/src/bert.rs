@@ -0,0 +1,109 @@
+use std::{fs::File, path::Path};
+
+use anyhow::Result;
+use candle_core::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{Config, DTYPE};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokenizers::{Encoding, PaddingParams, Tokenizer, TruncationParams};
+
+/// A BERT model in a local directory, with its `config.json`, `tokenizer.json` and
+/// `model.safetensors` files, run on the CPU.
+pub struct Checkpoint {
+    pub config: Config,
+    /// The configuration as written, with the keys the BERT configuration ignores.
+    pub raw_config: Value,
+    /// Pads the texts of a batch to the same length, truncated to the positions of the
+    /// model.
+    pub tokenizer: Tokenizer,
+    pub weights: VarBuilder<'static>,
+    /// The name of the directory.
+    pub name: String,
+    /// A short hash of the files, telling the revisions of a model apart.
+    pub revision: String,
+}
+
+/// The files of a checkpoint, all changing the outputs of the model.
+const FILES: [&str; 3] = ["config.json", "tokenizer.json", "model.safetensors"];
+
+fn revision(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    for file in FILES {
+        std::io::copy(&mut File::open(path.join(file))?, &mut hasher)?;
+    }
+
+    let digest = format!("{:x}", hasher.finalize());
+    Ok(digest[..12].to_string())
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw_config: Value =
+            serde_json::from_str(&std::fs::read_to_string(path.join("config.json"))?)?;
+        let config: Config = serde_json::from_value(raw_config.clone())?;
+
+        let mut tokenizer =
+            Tokenizer::from_file(path.join("tokenizer.json")).map_err(anyhow::Error::msg)?;
+        tokenizer
+            .with_padding(Some(PaddingParams::default()))
+            .with_truncation(Some(TruncationParams {
+                max_length: config.max_position_embeddings,
+                ..Default::default()
+            }))
+            .map_err(anyhow::Error::msg)?;
+
+        // The weights are memory mapped rather than read, and must not change while
+        // the model is loaded.
+        let weights = unsafe {
+            VarBuilder::from_mmaped_safetensors(
+                &[path.join("model.safetensors")],
+                DTYPE,
+                &Device::Cpu,
+            )
+        }?;
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+
+        Ok(Self {
+            config,
+            raw_config,
+            tokenizer,
+            weights,
+            name,
+            revision: revision(path)?,
+        })
+    }
+}
+
+/// Threads running a model, one per core by default. Inference runs on them rather than
+/// on the threads of the runtime.
+pub fn thread_pool(name: &'static str, threads: Option<usize>) -> Result<ThreadPool> {
+    let mut pool = ThreadPoolBuilder::new().thread_name(move |i| format!("{name}-{i}"));
+    if let Some(threads) = threads {
+        pool = pool.num_threads(threads);
+    }
+
+    Ok(pool.build()?)
+}
+
+/// The token ids, token type ids and attention mask of a batch of padded encodings.
+pub fn batch(encodings: &[Encoding]) -> candle_core::Result<(Tensor, Tensor, Tensor)> {
+    let tensor = |rows: Vec<&[u32]>| -> candle_core::Result<Tensor> {
+        let rows = rows
+            .into_iter()
+            .map(|row| Tensor::new(row, &Device::Cpu))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        Tensor::stack(&rows, 0)
+    };
+
+    Ok((
+        tensor(encodings.iter().map(Encoding::get_ids).collect())?,
+        tensor(encodings.iter().map(Encoding::get_type_ids).collect())?,
+        tensor(encodings.iter().map(Encoding::get_attention_mask).collect())?,
+    ))
+}
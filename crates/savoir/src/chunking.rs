@@ -0,0 +1,347 @@
+use std::{fmt::Debug, ops::Range};
+
+use serde::Deserialize;
+
+use crate::document::{Chunk, Document};
+
+use self::{
+    headings::HeadingChunker,
+    text::{ParagraphChunker, SentenceChunker},
+    tokens::TokenChunker,
+};
+
+pub mod headings;
+pub mod text;
+pub mod tokens;
+
+fn default_max_tokens() -> usize {
+    256
+}
+
+fn default_overlap() -> usize {
+    32
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "snake_case", tag = "strategy")]
+pub enum Config {
+    #[default]
+    None,
+    Tokens {
+        #[serde(default = "default_max_tokens")]
+        size: usize,
+        #[serde(default = "default_overlap")]
+        overlap: usize,
+    },
+    Sentences {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    Paragraphs {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+    Headings {
+        #[serde(default = "default_max_tokens")]
+        max_tokens: usize,
+    },
+}
+
+pub trait Chunker: Send + Sync + Debug {
+    fn split(&self, content: &str) -> Vec<Range<usize>>;
+
+    /// Identifies how documents are split, recorded in the ledger to split them again
+    /// when the configuration changes.
+    fn id(&self) -> String;
+}
+
+#[derive(Debug)]
+pub struct NoopChunker;
+
+impl Chunker for NoopChunker {
+    fn split(&self, content: &str) -> Vec<Range<usize>> {
+        std::iter::once(0..content.len()).collect()
+    }
+
+    fn id(&self) -> String {
+        "none".to_string()
+    }
+}
+
+impl From<Config> for Box<dyn Chunker> {
+    fn from(value: Config) -> Self {
+        match value {
+            Config::None => Box::new(NoopChunker),
+            Config::Tokens { size, overlap } => Box::new(TokenChunker::new(size, overlap)),
+            Config::Sentences { max_tokens } => Box::new(SentenceChunker::new(max_tokens)),
+            Config::Paragraphs { max_tokens } => Box::new(ParagraphChunker::new(max_tokens)),
+            Config::Headings { max_tokens } => Box::new(HeadingChunker::new(max_tokens)),
+        }
+    }
+}
+
+/// Splits a document into chunks that keep the name, url and metadata of their parent.
+/// A document that fits in a single chunk keeps its own id and content.
+pub fn chunk(chunker: &dyn Chunker, document: Document) -> Vec<Document> {
+    let ranges: Vec<Range<usize>> = chunker
+        .split(&document.content)
+        .into_iter()
+        .map(|range| trim(&document.content, range))
+        .filter(|range| !range.is_empty())
+        .collect();
+
+    if ranges.len() <= 1 {
+        return vec![Document {
+            chunk: Some(Chunk {
+                parent_id: document.id.clone(),
+                position: 0,
+                start: 0,
+                end: document.content.len(),
+            }),
+            ..document
+        }];
+    }
+
+    ranges
+        .into_iter()
+        .enumerate()
+        .map(|(position, range)| Document {
+            id: format!("{}:{position}", document.id),
+            name: document.name.clone(),
+            content: document.content[range.clone()].to_string(),
+            url: document.url.clone(),
+            metadata: document.metadata.clone(),
+            provenance: document.provenance.clone(),
+            chunk: Some(Chunk {
+                parent_id: document.id.clone(),
+                position,
+                start: range.start,
+                end: range.end,
+            }),
+        })
+        .collect()
+}
+
+fn trim(content: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &content[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+
+    start..end.max(start)
+}
+
+/// Approximates tokens as whitespace-separated words and returns their byte ranges.
+pub fn words(content: &str) -> Vec<Range<usize>> {
+    let mut words = vec![];
+    let mut start = None;
+
+    for (i, c) in content.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push(s..i);
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => (),
+        }
+    }
+
+    if let Some(s) = start {
+        words.push(s..content.len());
+    }
+
+    words
+}
+
+/// Groups consecutive units into chunks of at most `max_tokens` words. Units that are
+/// larger than `max_tokens` on their own are split into fixed windows.
+pub fn pack(content: &str, units: Vec<Range<usize>>, max_tokens: usize) -> Vec<Range<usize>> {
+    let mut chunks = vec![];
+    let mut current: Option<(Range<usize>, usize)> = None;
+
+    for unit in units {
+        let count = words(&content[unit.clone()]).len();
+        if count == 0 {
+            continue;
+        }
+
+        if count > max_tokens {
+            chunks.extend(current.take().map(|(range, _)| range));
+            let windows = TokenChunker::new(max_tokens, 0).split(&content[unit.clone()]);
+            chunks.extend(
+                windows
+                    .into_iter()
+                    .map(|w| unit.start + w.start..unit.start + w.end),
+            );
+            continue;
+        }
+
+        current = match current.take() {
+            Some((range, total)) if total + count <= max_tokens => {
+                Some((range.start..unit.end, total + count))
+            }
+            Some((range, _)) => {
+                chunks.push(range);
+                Some((unit, count))
+            }
+            None => Some((unit, count)),
+        };
+    }
+
+    chunks.extend(current.map(|(range, _)| range));
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) const MULTIBYTE: &str =
+        "Ça déménage\u{a0}! Les élèves\u{2003}étudient\u{3000}l'été.\n\n\
+        日本語の文。 Ünïcödé ✓ ok?\n\n# Tête 🎉\n\nFin… vraiment.";
+
+    /// Checks that each range is on character boundaries, and returns the slices.
+    pub(crate) fn slices<'a>(content: &'a str, ranges: &[Range<usize>]) -> Vec<&'a str> {
+        ranges
+            .iter()
+            .map(|range| {
+                content
+                    .get(range.clone())
+                    .unwrap_or_else(|| panic!("{range:?} is not on character boundaries"))
+            })
+            .collect()
+    }
+
+    fn document(content: &str) -> Document {
+        Document {
+            id: "guide.md".to_string(),
+            name: "Guide".to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Default::default(),
+            chunk: None,
+        }
+    }
+
+    fn chunkers() -> Vec<Box<dyn Chunker>> {
+        [
+            Config::None,
+            Config::Tokens {
+                size: 3,
+                overlap: 1,
+            },
+            Config::Sentences { max_tokens: 4 },
+            Config::Paragraphs { max_tokens: 4 },
+            Config::Headings { max_tokens: 4 },
+        ]
+        .into_iter()
+        .map(Box::from)
+        .collect()
+    }
+
+    #[test]
+    fn chunks_are_the_content_between_their_offsets() {
+        let contents = [
+            MULTIBYTE,
+            "  \n# Title\n\nA first paragraph.\n\nA second one, longer than the first.  \n",
+            "one",
+            " \u{a0}\n",
+            "",
+        ];
+
+        for chunker in chunkers() {
+            for content in contents {
+                let chunks = chunk(chunker.as_ref(), document(content));
+
+                for (position, chunk) in chunks.iter().enumerate() {
+                    let range = chunk.chunk.as_ref().unwrap();
+                    assert_eq!(range.parent_id, "guide.md");
+                    assert_eq!(range.position, position);
+                    assert_eq!(
+                        content.get(range.start..range.end),
+                        Some(chunk.content.as_str()),
+                        "{} of {content:?}",
+                        chunker.id()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_chunks_keep_the_document() {
+        let chunks = chunk(&TokenChunker::new(10, 2), document("  A short guide.\n"));
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].id, "guide.md");
+        assert_eq!(chunks[0].content, "  A short guide.\n");
+    }
+
+    #[test]
+    fn chunks_are_trimmed_and_numbered() {
+        let chunks = chunk(
+            &ParagraphChunker::new(2),
+            document("First paragraph.\n\n  \n Second paragraph. \n"),
+        );
+
+        let ids: Vec<_> = chunks.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, ["guide.md:0", "guide.md:1"]);
+        let contents: Vec<_> = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        assert_eq!(contents, ["First paragraph.", "Second paragraph."]);
+        assert!(chunks.iter().all(|chunk| chunk.name == "Guide"));
+    }
+
+    #[test]
+    fn words_are_separated_by_any_whitespace() {
+        let content = "Ça\u{a0}va\u{2003}très\tbien\u{3000}🎉 ";
+
+        assert_eq!(
+            slices(content, &words(content)),
+            ["Ça", "va", "très", "bien", "🎉"]
+        );
+        assert!(words(" \n\u{a0}").is_empty());
+    }
+
+    #[test]
+    fn pack_groups_units_up_to_max_tokens() {
+        let content = "one two. three. four five six. seven";
+        let units = text::sentences(content);
+
+        let chunks = pack(content, units, 3);
+
+        assert_eq!(
+            slices(content, &chunks),
+            ["one two. three.", " four five six.", " seven"]
+        );
+    }
+
+    #[test]
+    fn pack_splits_units_larger_than_max_tokens() {
+        let content = "short. a b c d e. tail";
+        let units = vec![0..6, 6..17, 17..content.len(), 5..5];
+
+        let chunks = pack(content, units, 2);
+
+        assert_eq!(
+            slices(content, &chunks),
+            ["short.", "a b", "c d", "e.", " tail"]
+        );
+        for chunk in slices(content, &chunks) {
+            assert!(words(chunk).len() <= 2, "{chunk:?}");
+        }
+    }
+
+    #[test]
+    fn pack_keeps_multibyte_text_whole() {
+        for max_tokens in 1..6 {
+            let chunks = pack(MULTIBYTE, text::sentences(MULTIBYTE), max_tokens);
+
+            let words: Vec<_> = slices(MULTIBYTE, &chunks)
+                .into_iter()
+                .flat_map(|chunk| slices(chunk, &words(chunk)))
+                .collect();
+            assert_eq!(words, slices(MULTIBYTE, &super::words(MULTIBYTE)));
+        }
+    }
+}
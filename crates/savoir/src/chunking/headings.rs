@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+use super::{pack, text::paragraphs, Chunker};
+
+/// Packs paragraphs without crossing the sections that start at Markdown headings.
+/// Markdown documents are already split into one document per section before they are
+/// chunked, which leaves their paragraphs to pack.
+#[derive(Debug)]
+pub struct HeadingChunker {
+    max_tokens: usize,
+}
+
+impl HeadingChunker {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens: max_tokens.max(1),
+        }
+    }
+}
+
+impl Chunker for HeadingChunker {
+    fn split(&self, content: &str) -> Vec<Range<usize>> {
+        sections(content)
+            .into_iter()
+            .flat_map(|section| {
+                let units = paragraphs(&content[section.clone()])
+                    .into_iter()
+                    .map(|p| section.start + p.start..section.start + p.end)
+                    .collect();
+
+                pack(content, units, self.max_tokens)
+            })
+            .collect()
+    }
+
+    fn id(&self) -> String {
+        format!("headings/{}", self.max_tokens)
+    }
+}
+
+fn sections(content: &str) -> Vec<Range<usize>> {
+    let mut sections = vec![];
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_code = false;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+        }
+
+        let heading = !in_code
+            && trimmed.starts_with('#')
+            && trimmed.trim_start_matches('#').starts_with(' ');
+
+        if heading && offset > start {
+            sections.push(start..offset);
+            start = offset;
+        }
+        offset += line.len();
+    }
+
+    if start < content.len() {
+        sections.push(start..content.len());
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunking::{
+        tests::{slices, MULTIBYTE},
+        words,
+    };
+
+    use super::*;
+
+    const CONTENT: &str = "Intro text.\n\n# Setup\n\nInstall it.\n\n```sh\n# not a heading\nmake\n```\n\n## Usage\n#hashtag\n\nRun it.\n";
+
+    #[test]
+    fn sections_start_at_headings_outside_code() {
+        assert_eq!(
+            slices(CONTENT, &sections(CONTENT)),
+            [
+                "Intro text.\n\n",
+                "# Setup\n\nInstall it.\n\n```sh\n# not a heading\nmake\n```\n\n",
+                "## Usage\n#hashtag\n\nRun it.\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn chunks_do_not_cross_sections() {
+        let chunks = slices(CONTENT, &HeadingChunker::new(100).split(CONTENT));
+
+        assert_eq!(
+            chunks,
+            [
+                "Intro text.\n",
+                "# Setup\n\nInstall it.\n\n```sh\n# not a heading\nmake\n```\n",
+                "## Usage\n#hashtag\n\nRun it.\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn large_sections_are_split() {
+        let chunks = slices(CONTENT, &HeadingChunker::new(3).split(CONTENT));
+
+        assert!(chunks.iter().all(|chunk| words(chunk).len() <= 3));
+        assert_eq!(chunks[0], "Intro text.\n");
+        assert_eq!(chunks[1], "# Setup\n");
+    }
+
+    #[test]
+    fn multibyte_headings_are_split_on_character_boundaries() {
+        let sections = slices(MULTIBYTE, &sections(MULTIBYTE));
+
+        assert_eq!(sections.len(), 2);
+        assert!(sections[1].starts_with("# Tête 🎉"));
+        for max_tokens in 1..6 {
+            slices(MULTIBYTE, &HeadingChunker::new(max_tokens).split(MULTIBYTE));
+        }
+    }
+}
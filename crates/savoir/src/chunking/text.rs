@@ -0,0 +1,172 @@
+use std::ops::Range;
+
+use super::{pack, Chunker};
+
+#[derive(Debug)]
+pub struct SentenceChunker {
+    max_tokens: usize,
+}
+
+impl SentenceChunker {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens: max_tokens.max(1),
+        }
+    }
+}
+
+impl Chunker for SentenceChunker {
+    fn split(&self, content: &str) -> Vec<Range<usize>> {
+        pack(content, sentences(content), self.max_tokens)
+    }
+
+    fn id(&self) -> String {
+        format!("sentences/{}", self.max_tokens)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParagraphChunker {
+    max_tokens: usize,
+}
+
+impl ParagraphChunker {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens: max_tokens.max(1),
+        }
+    }
+}
+
+impl Chunker for ParagraphChunker {
+    fn split(&self, content: &str) -> Vec<Range<usize>> {
+        pack(content, paragraphs(content), self.max_tokens)
+    }
+
+    fn id(&self) -> String {
+        format!("paragraphs/{}", self.max_tokens)
+    }
+}
+
+pub fn sentences(content: &str) -> Vec<Range<usize>> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let boundary = match c {
+            '.' | '!' | '?' => chars.peek().is_none_or(|(_, next)| next.is_whitespace()),
+            '\n' => chars.peek().is_some_and(|(_, next)| *next == '\n'),
+            _ => false,
+        };
+
+        if boundary {
+            let end = i + c.len_utf8();
+            sentences.push(start..end);
+            start = end;
+        }
+    }
+
+    if start < content.len() {
+        sentences.push(start..content.len());
+    }
+
+    sentences
+}
+
+pub fn paragraphs(content: &str) -> Vec<Range<usize>> {
+    let mut paragraphs = vec![];
+    let mut start = 0;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        if line.trim().is_empty() && offset > start {
+            paragraphs.push(start..offset);
+            start = offset + line.len();
+        } else if line.trim().is_empty() {
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+
+    if start < content.len() {
+        paragraphs.push(start..content.len());
+    }
+
+    paragraphs
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunking::tests::{slices, MULTIBYTE};
+
+    use super::*;
+
+    #[test]
+    fn sentences_end_with_punctuation_or_blank_lines() {
+        let content = "Version 1.2 is out! Is it?Yes. A list\n\n- without dots\nends here";
+
+        assert_eq!(
+            slices(content, &sentences(content)),
+            [
+                "Version 1.2 is out!",
+                " Is it?Yes.",
+                " A list\n",
+                "\n- without dots\nends here"
+            ]
+        );
+    }
+
+    #[test]
+    fn paragraphs_are_separated_by_blank_lines() {
+        let content = "\n\nFirst line\nsecond line\n  \n\nSecond paragraph\n\n";
+
+        assert_eq!(
+            slices(content, &paragraphs(content)),
+            ["First line\nsecond line\n", "Second paragraph\n"]
+        );
+        assert!(paragraphs("\n \n").is_empty());
+    }
+
+    #[test]
+    fn multibyte_text_is_split_on_character_boundaries() {
+        assert_eq!(
+            slices(MULTIBYTE, &sentences(MULTIBYTE)),
+            [
+                "Ça déménage\u{a0}!",
+                " Les élèves\u{2003}étudient\u{3000}l'été.",
+                "\n",
+                "\n日本語の文。 Ünïcödé ✓ ok?",
+                "\n",
+                "\n# Tête 🎉\n",
+                "\nFin… vraiment."
+            ]
+        );
+        assert_eq!(slices(MULTIBYTE, &paragraphs(MULTIBYTE)).len(), 4);
+
+        for max_tokens in 1..6 {
+            slices(
+                MULTIBYTE,
+                &SentenceChunker::new(max_tokens).split(MULTIBYTE),
+            );
+            slices(
+                MULTIBYTE,
+                &ParagraphChunker::new(max_tokens).split(MULTIBYTE),
+            );
+        }
+    }
+
+    #[test]
+    fn paragraphs_are_packed_up_to_max_tokens() {
+        let content = "One two.\n\nThree.\n\nFour five six.";
+
+        assert_eq!(
+            slices(content, &ParagraphChunker::new(3).split(content)),
+            ["One two.\n\nThree.\n", "Four five six."]
+        );
+        assert_eq!(
+            slices(content, &ParagraphChunker::new(2).split(content)),
+            ["One two.\n", "Three.\n", "Four five", "six."]
+        );
+    }
+}
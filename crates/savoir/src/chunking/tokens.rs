@@ -0,0 +1,107 @@
+use std::ops::Range;
+
+use super::{words, Chunker};
+
+#[derive(Debug)]
+pub struct TokenChunker {
+    size: usize,
+    overlap: usize,
+}
+
+impl TokenChunker {
+    pub fn new(size: usize, overlap: usize) -> Self {
+        let size = size.max(1);
+
+        Self {
+            size,
+            overlap: overlap.min(size - 1),
+        }
+    }
+}
+
+impl Chunker for TokenChunker {
+    fn split(&self, content: &str) -> Vec<Range<usize>> {
+        let words = words(content);
+        let step = self.size - self.overlap;
+
+        let mut chunks = vec![];
+        let mut start = 0;
+
+        while start < words.len() {
+            let end = (start + self.size).min(words.len());
+            chunks.push(words[start].start..words[end - 1].end);
+
+            if end == words.len() {
+                break;
+            }
+            start += step;
+        }
+
+        chunks
+    }
+
+    fn id(&self) -> String {
+        format!("tokens/{}/{}", self.size, self.overlap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunking::tests::{slices, MULTIBYTE};
+
+    use super::*;
+
+    fn split(size: usize, overlap: usize, content: &str) -> Vec<&str> {
+        slices(content, &TokenChunker::new(size, overlap).split(content))
+    }
+
+    #[test]
+    fn windows_overlap() {
+        let content = "one two  three\nfour five six seven";
+
+        assert_eq!(
+            split(3, 1, content),
+            ["one two  three", "three\nfour five", "five six seven"]
+        );
+        assert_eq!(
+            split(3, 0, content),
+            ["one two  three", "four five six", "seven"]
+        );
+        assert_eq!(split(10, 2, content), [content]);
+        assert!(split(3, 1, " \n").is_empty());
+    }
+
+    #[test]
+    fn overlap_is_kept_smaller_than_the_windows() {
+        let content = "a b c d";
+
+        assert_eq!(split(2, 5, content), ["a b", "b c", "c d"]);
+        assert_eq!(split(0, 0, content), ["a", "b", "c", "d"]);
+        assert_eq!(TokenChunker::new(2, 5).id(), "tokens/2/1");
+    }
+
+    #[test]
+    fn consecutive_windows_share_the_overlap() {
+        let all = words(MULTIBYTE);
+
+        for size in 1..6 {
+            for overlap in 0..size {
+                let chunks = split(size, overlap, MULTIBYTE);
+                let chunks: Vec<Vec<&str>> = chunks
+                    .into_iter()
+                    .map(|chunk| slices(chunk, &words(chunk)))
+                    .collect();
+
+                assert_eq!(chunks[0][0], &MULTIBYTE[all[0].clone()]);
+                assert_eq!(
+                    *chunks.last().unwrap().last().unwrap(),
+                    &MULTIBYTE[all.last().unwrap().clone()]
+                );
+                for pair in chunks.windows(2) {
+                    assert_eq!(pair[0].len(), size);
+                    assert_eq!(pair[0][size - overlap..], pair[1][..overlap]);
+                }
+            }
+        }
+    }
+}
@@ -4,29 +4,78 @@ use anyhow::Error;
 use serde::Deserialize;
 use tokio::sync::mpsc::Sender;
 
-use crate::{document::Document, interals::AsyncTryFrom};
+use crate::{chunking, document::Document, interals::AsyncTryFrom, ledger::Entries};
 
-use self::google::GoogleDatasource;
+use self::{filesystem::FilesystemDatasource, git::GitDatasource, google::GoogleDatasource};
 
+pub mod filesystem;
+mod filter;
+pub mod git;
 pub mod google;
 
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(default)]
+    pub chunking: chunking::Config,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
-pub enum Config {
+pub enum Source {
     Google(google::Config),
+    Filesystem(filesystem::Config),
+    Git(git::Config),
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Document(Box<Document>),
+    Unchanged(String),
+    Deleted(String),
+    Cursor(String),
+    /// The document with the given id could not be read, and why.
+    Failed(String, String),
+}
+
+/// What the last synchronization recorded about a datasource.
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    pub entries: Entries,
+    pub cursor: Option<String>,
+    /// Documents that could not be synchronized, to fetch again along the changes since
+    /// the cursor.
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listing {
+    /// Every document of the datasource was reported, missing ones have been deleted.
+    Full,
+    /// Only the documents that changed since the cursor were reported.
+    Changes,
 }
 
 #[async_trait::async_trait]
 pub trait Datasource: Send + Sync + Debug {
-    async fn stream_documents(&self, tx: Sender<Document>);
+    /// Streams the documents of the datasource. `state` holds the entries recorded by the
+    /// last synchronization so documents that did not change can be reported as
+    /// `Event::Unchanged` without being fetched, and the cursor last sent as
+    /// `Event::Cursor`. An error means the listing is incomplete.
+    async fn stream_documents(&self, state: &State, tx: Sender<Event>) -> Result<Listing, Error>;
 }
 
 #[async_trait::async_trait]
-impl AsyncTryFrom<Config> for Box<dyn Datasource> {
+impl AsyncTryFrom<Source> for Box<dyn Datasource> {
     type Error = Error;
-    async fn async_try_from(value: Config) -> Result<Self, Error> {
-        let datasource = match value {
-            Config::Google(config) => Box::new(GoogleDatasource::async_try_from(config).await?),
+    async fn async_try_from(value: Source) -> Result<Self, Error> {
+        let datasource: Box<dyn Datasource> = match value {
+            Source::Google(config) => Box::new(GoogleDatasource::async_try_from(config).await?),
+            Source::Filesystem(config) => {
+                Box::new(FilesystemDatasource::async_try_from(config).await?)
+            }
+            Source::Git(config) => Box::new(GitDatasource::async_try_from(config).await?),
         };
 
         Ok(datasource)
@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail};
+use chrono::DateTime;
+
+use log::warn;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use walkdir::WalkDir;
+
+use crate::{
+    document::{Document, Metadata, Provenance},
+    interals::AsyncTryFrom,
+};
+
+use super::{filter::PathFilter, Datasource, Event, Listing, State};
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Directories to index. Files are identified by their path in the directory, prefixed
+    /// by the directory as configured when there are several.
+    paths: Vec<PathBuf>,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    follow_symlinks: bool,
+}
+
+#[derive(Debug)]
+pub struct FilesystemDatasource {
+    roots: Vec<Root>,
+    walker: Walker,
+}
+
+/// A directory of the datasource, with the prefix of the ids of its files when files with
+/// the same relative path in another directory must stay apart.
+#[derive(Debug)]
+struct Root {
+    path: PathBuf,
+    prefix: Option<String>,
+}
+
+impl Root {
+    fn id(&self, relative: &str) -> String {
+        match &self.prefix {
+            Some(prefix) if !prefix.is_empty() => format!("{prefix}/{relative}"),
+            _ => relative.to_string(),
+        }
+    }
+}
+
+/// The path as configured, with `/` separators and without `.` components, so that ids
+/// do not depend on the working directory.
+fn prefix(path: &Path) -> String {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .map(|component| match component {
+            Component::RootDir => String::new(),
+            component => component.as_os_str().to_string_lossy().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[derive(Debug, Clone)]
+struct Walker {
+    filter: PathFilter,
+    follow_symlinks: bool,
+}
+
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for FilesystemDatasource {
+    type Error = anyhow::Error;
+
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
+        let mut prefixes: HashMap<String, &PathBuf> = HashMap::new();
+        for path in &value.paths {
+            if let Some(other) = prefixes.insert(prefix(path), path) {
+                bail!(
+                    "the paths {} and {} are the same, their files would have the same ids",
+                    other.display(),
+                    path.display()
+                );
+            }
+        }
+
+        let several = value.paths.len() > 1;
+        Ok(Self {
+            roots: value
+                .paths
+                .into_iter()
+                .map(|path| Root {
+                    prefix: several.then(|| prefix(&path)),
+                    path,
+                })
+                .collect(),
+            walker: Walker {
+                filter: PathFilter::new(&value.include, &value.exclude)?,
+                follow_symlinks: value.follow_symlinks,
+            },
+        })
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    relative: String,
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl Walker {
+    fn walk(&self, root: &Path) -> (Vec<Entry>, bool) {
+        let walker = WalkDir::new(root)
+            .follow_links(self.follow_symlinks)
+            .into_iter()
+            .filter_entry(|e| {
+                e.depth() == 0
+                    || !e.file_type().is_dir()
+                    || !self.filter.is_excluded(&relative_path(root, e.path()))
+            });
+
+        let mut entries = vec![];
+        let mut complete = true;
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Cannot read entry in {}: {e}", root.display());
+                    complete = false;
+                    continue;
+                }
+            };
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let relative = relative_path(root, entry.path());
+            if self.filter.is_included(&relative) {
+                entries.push(Entry {
+                    path: entry.into_path(),
+                    relative,
+                });
+            }
+        }
+
+        (entries, complete)
+    }
+}
+
+#[async_trait::async_trait]
+impl Datasource for FilesystemDatasource {
+    async fn stream_documents(&self, state: &State, tx: Sender<Event>) -> anyhow::Result<Listing> {
+        let mut incomplete = vec![];
+
+        for root in &self.roots {
+            let walker = self.walker.clone();
+            let walk_root = root.path.clone();
+            let (entries, complete) =
+                tokio::task::spawn_blocking(move || walker.walk(&walk_root)).await?;
+
+            if !complete {
+                incomplete.push(root.path.display().to_string());
+            }
+
+            for entry in entries {
+                let id = root.id(&entry.relative);
+                let metadata = tokio::fs::metadata(&entry.path).await.ok();
+                let modified_at = metadata
+                    .as_ref()
+                    .and_then(|m| m.modified().ok())
+                    .map(DateTime::from);
+
+                let unchanged = state
+                    .entries
+                    .get(&id)
+                    .is_some_and(|e| e.modified_at.is_some() && e.modified_at == modified_at);
+
+                if unchanged {
+                    let _ = tx.send(Event::Unchanged(id)).await;
+                    continue;
+                }
+
+                let content = match tokio::fs::read_to_string(&entry.path).await {
+                    Ok(content) => content,
+                    Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                        let reason = "not a UTF-8 text file".to_string();
+                        let _ = tx.send(Event::Failed(id, reason)).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Event::Failed(id, e.to_string())).await;
+                        continue;
+                    }
+                };
+
+                let name = entry
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| entry.relative.clone());
+
+                let url = std::fs::canonicalize(&entry.path)
+                    .ok()
+                    .map(|path| format!("file://{}", path.display()));
+
+                let provenance = Provenance {
+                    mime_type: mime_guess::from_path(&entry.path)
+                        .first()
+                        .map(|mime| mime.to_string()),
+                    created_at: metadata
+                        .as_ref()
+                        .and_then(|m| m.created().ok())
+                        .map(DateTime::from),
+                    modified_at,
+                    ..Default::default()
+                };
+
+                let _ = tx
+                    .send(Event::Document(Box::new(Document {
+                        id,
+                        name,
+                        content,
+                        url,
+                        metadata: Metadata::new(),
+                        provenance,
+                        chunk: None,
+                    })))
+                    .await;
+            }
+        }
+
+        match incomplete.is_empty() {
+            true => Ok(Listing::Full),
+            false => Err(anyhow!("cannot walk {}", incomplete.join(", "))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    async fn datasource(paths: &[PathBuf]) -> anyhow::Result<FilesystemDatasource> {
+        FilesystemDatasource::async_try_from(Config {
+            paths: paths.to_vec(),
+            include: vec![],
+            exclude: vec![],
+            follow_symlinks: false,
+        })
+        .await
+    }
+
+    /// The ids of the documents and of the files that failed, prefixed by `failed`.
+    async fn ids(datasource: &FilesystemDatasource) -> Vec<String> {
+        let (tx, mut rx) = mpsc::channel(32);
+        datasource
+            .stream_documents(&State::default(), tx)
+            .await
+            .unwrap();
+
+        let mut ids = vec![];
+        while let Some(event) = rx.recv().await {
+            match event {
+                Event::Document(document) => ids.push(document.id),
+                Event::Failed(id, _) => ids.push(format!("failed {id}")),
+                _ => {}
+            }
+        }
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn identifies_files_by_their_relative_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("ops")).unwrap();
+        std::fs::write(dir.path().join("ops/deploy.md"), "# Deploy").unwrap();
+
+        // The same directory is configured through another path.
+        let datasource = datasource(&[dir.path().join("ops/..")]).await.unwrap();
+
+        assert_eq!(ids(&datasource).await, ["ops/deploy.md"]);
+    }
+
+    #[tokio::test]
+    async fn reports_files_that_are_not_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("logo.png"), [0x89, 0x50, 0x4e, 0x47, 0xff]).unwrap();
+        std::fs::write(dir.path().join("guide.md"), "# Guide").unwrap();
+
+        let datasource = datasource(&[dir.path().to_path_buf()]).await.unwrap();
+
+        assert_eq!(ids(&datasource).await, ["failed logo.png", "guide.md"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_files_with_the_same_relative_path_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let roots = [dir.path().join("runbooks"), dir.path().join("adr")];
+        for root in &roots {
+            std::fs::create_dir_all(root.join("ops")).unwrap();
+            std::fs::write(root.join("ops/deploy.md"), root.display().to_string()).unwrap();
+        }
+
+        let datasource = datasource(&roots).await.unwrap();
+
+        let root = prefix(dir.path());
+        assert_eq!(
+            ids(&datasource).await,
+            [
+                format!("{root}/adr/ops/deploy.md"),
+                format!("{root}/runbooks/ops/deploy.md")
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixes_are_the_configured_paths() {
+        assert_eq!(prefix(Path::new("./runbooks/")), "runbooks");
+        assert_eq!(prefix(Path::new("docs/./adr")), "docs/adr");
+        assert_eq!(prefix(Path::new("/srv/docs")), "/srv/docs");
+        assert_eq!(prefix(Path::new(".")), "");
+    }
+
+    #[tokio::test]
+    async fn rejects_the_same_path_twice() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+
+        assert!(datasource(&[docs.clone(), docs.join(".")]).await.is_err());
+        assert!(
+            datasource(&[dir.path().join("a/docs"), dir.path().join("b/docs")])
+                .await
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid glob pattern '{0}': {1}")]
+    InvalidGlob(String, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|e| Error::InvalidGlob(pattern.clone(), e.to_string()))?;
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map_err(|e| Error::InvalidGlob(patterns.join(", "), e.to_string()))
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, Error> {
+        let include = match include.is_empty() {
+            true => None,
+            false => Some(build_glob_set(include)?),
+        };
+
+        Ok(Self {
+            include,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.is_match(path)
+    }
+
+    pub fn is_included(&self, path: &str) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        };
+
+        included && !self.is_excluded(path)
+    }
+}
@@ -0,0 +1,399 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{TimeZone, Utc};
+
+use git2::{build::RepoBuilder, Commit, ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use log::{info, warn};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    document::{content_hash, Document, Metadata, Provenance},
+    interals::AsyncTryFrom,
+    ledger::Entries,
+};
+
+use super::{filter::PathFilter, Datasource, Event, Listing, State};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot open git repository {0}: {1}")]
+    OpenRepository(String, String),
+    #[error("cannot fetch git repository {0}: {1}")]
+    FetchRepository(String, String),
+    #[error("cannot resolve git reference {0}: {1}")]
+    ResolveReference(String, String),
+    #[error("cannot read git tree: {0}")]
+    ReadTree(String),
+}
+
+fn default_reference() -> String {
+    "HEAD".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    path: PathBuf,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default = "default_reference")]
+    reference: String,
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    web_url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GitDatasource {
+    config: Config,
+    filter: PathFilter,
+}
+
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for GitDatasource {
+    type Error = anyhow::Error;
+
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
+        let filter = PathFilter::new(&value.include, &value.exclude)?;
+
+        Ok(Self {
+            config: value,
+            filter,
+        })
+    }
+}
+
+fn open_repository(path: &Path, url: Option<&str>) -> Result<Repository, Error> {
+    let display = path.display().to_string();
+
+    let Some(url) = url else {
+        return Repository::open(path).map_err(|e| Error::OpenRepository(display, e.to_string()));
+    };
+
+    if !path.exists() {
+        info!("Cloning {url} into {display}");
+        return RepoBuilder::new()
+            .bare(true)
+            .clone(url, path)
+            .map_err(|e| Error::FetchRepository(url.to_string(), e.to_string()));
+    }
+
+    let repository =
+        Repository::open(path).map_err(|e| Error::OpenRepository(display, e.to_string()))?;
+
+    info!("Fetching {url}");
+    repository
+        .remote_anonymous(url)
+        .and_then(|mut remote| {
+            remote.fetch(
+                &[
+                    "+refs/heads/*:refs/remotes/origin/*",
+                    "+refs/tags/*:refs/tags/*",
+                ],
+                None,
+                None,
+            )
+        })
+        .map_err(|e| Error::FetchRepository(url.to_string(), e.to_string()))?;
+
+    Ok(repository)
+}
+
+fn resolve_reference<'a>(repository: &'a Repository, reference: &str) -> Result<Commit<'a>, Error> {
+    let remote = format!("refs/remotes/origin/{reference}");
+    let object = repository
+        .revparse_single(&remote)
+        .or_else(|_| repository.revparse_single(reference))
+        .map_err(|e| Error::ResolveReference(reference.to_string(), e.to_string()))?;
+
+    object
+        .peel_to_commit()
+        .map_err(|e| Error::ResolveReference(reference.to_string(), e.to_string()))
+}
+
+impl GitDatasource {
+    fn url(&self, commit: &str, path: &str) -> Option<String> {
+        self.config.web_url.as_ref().map(|template| {
+            template
+                .replace("{commit}", commit)
+                .replace("{reference}", &self.config.reference)
+                .replace("{path}", path)
+        })
+    }
+
+    /// Reads the files of the tree, reporting those whose content matches their ledger
+    /// entry as unchanged.
+    fn read_documents(&self, known: &Entries, tx: &Sender<Event>) -> Result<(), Error> {
+        let repository = open_repository(&self.config.path, self.config.url.as_deref())?;
+        let commit = resolve_reference(&repository, &self.config.reference)?;
+        let sha = commit.id().to_string();
+        let modified_at = Utc.timestamp_opt(commit.time().seconds(), 0).single();
+        let tree = commit.tree().map_err(|e| Error::ReadTree(e.to_string()))?;
+
+        info!("Indexing {} at {sha}", self.config.reference);
+
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() != Some(ObjectType::Blob) {
+                return TreeWalkResult::Ok;
+            }
+
+            let Some(name) = entry.name() else {
+                return TreeWalkResult::Ok;
+            };
+
+            let path = format!("{dir}{name}");
+            if !self.filter.is_included(&path) {
+                return TreeWalkResult::Ok;
+            }
+
+            let blob = match entry.to_object(&repository).and_then(|o| o.peel_to_blob()) {
+                Ok(blob) => blob,
+                Err(e) => {
+                    return match tx.blocking_send(Event::Failed(path, e.to_string())) {
+                        Ok(_) => TreeWalkResult::Ok,
+                        Err(_) => TreeWalkResult::Abort,
+                    };
+                }
+            };
+
+            if blob.is_binary() {
+                return TreeWalkResult::Ok;
+            }
+
+            let hash = content_hash(blob.content());
+            if known
+                .get(&path)
+                .is_some_and(|entry| entry.content_hash == hash)
+            {
+                return match tx.blocking_send(Event::Unchanged(path)) {
+                    Ok(_) => TreeWalkResult::Ok,
+                    Err(_) => TreeWalkResult::Abort,
+                };
+            }
+
+            let content = match std::str::from_utf8(blob.content()) {
+                Ok(content) => content.to_string(),
+                Err(e) => {
+                    warn!("Skipping {path}: {e}");
+                    return TreeWalkResult::Ok;
+                }
+            };
+
+            let document = Document {
+                url: self.url(&sha, &path),
+                name: name.to_string(),
+                content,
+                metadata: Metadata::new(),
+                provenance: Provenance {
+                    mime_type: mime_guess::from_path(&path)
+                        .first()
+                        .map(|mime| mime.to_string()),
+                    modified_at,
+                    ..Default::default()
+                },
+                chunk: None,
+                id: path,
+            };
+
+            match tx.blocking_send(Event::Document(Box::new(document))) {
+                Ok(_) => TreeWalkResult::Ok,
+                Err(_) => TreeWalkResult::Abort,
+            }
+        })
+        .map_err(|e| Error::ReadTree(e.to_string()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Datasource for GitDatasource {
+    async fn stream_documents(&self, state: &State, tx: Sender<Event>) -> anyhow::Result<Listing> {
+        let datasource = self.clone();
+        let known = state.entries.clone();
+        tokio::task::spawn_blocking(move || datasource.read_documents(&known, &tx)).await??;
+
+        Ok(Listing::Full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{IndexAddOption, Oid, Signature};
+    use tokio::sync::mpsc;
+
+    use crate::ledger::Entry;
+
+    use super::*;
+
+    /// Writes the files into the work tree of the repository and commits them on main.
+    fn commit(repository: &Repository, files: &[(&str, &str)]) -> Oid {
+        let workdir = repository.workdir().unwrap();
+        for (path, content) in files {
+            let path = workdir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let mut index = repository.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = repository.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let signature = Signature::now("Savoir", "savoir@example.org").unwrap();
+        let parent = repository
+            .find_reference("refs/heads/main")
+            .and_then(|reference| reference.peel_to_commit())
+            .ok();
+        let parents: Vec<&Commit> = parent.iter().collect();
+
+        repository
+            .commit(
+                Some("refs/heads/main"),
+                &signature,
+                &signature,
+                "Update docs",
+                &tree,
+                &parents,
+            )
+            .unwrap()
+    }
+
+    /// The documents read, and the ids of the unchanged ones.
+    async fn stream_since(
+        datasource: &GitDatasource,
+        state: &State,
+    ) -> (Vec<Document>, Vec<String>) {
+        let (tx, mut rx) = mpsc::channel(32);
+        let listing = datasource.stream_documents(state, tx).await.unwrap();
+        assert_eq!(listing, Listing::Full);
+
+        let mut documents = vec![];
+        let mut unchanged = vec![];
+        while let Some(event) = rx.recv().await {
+            match event {
+                Event::Document(document) => documents.push(*document),
+                Event::Unchanged(id) => unchanged.push(id),
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+        documents.sort_by(|a, b| a.id.cmp(&b.id));
+        unchanged.sort();
+        (documents, unchanged)
+    }
+
+    async fn stream(datasource: &GitDatasource) -> Vec<Document> {
+        stream_since(datasource, &State::default()).await.0
+    }
+
+    /// What a synchronization records about the documents.
+    fn state(documents: &[Document]) -> State {
+        let entries = documents
+            .iter()
+            .map(|document| {
+                let entry = Entry {
+                    modified_at: document.provenance.modified_at,
+                    content_hash: document.hash(),
+                    chunking: "none".to_string(),
+                };
+                (document.id.clone(), entry)
+            })
+            .collect();
+
+        State {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_files_with_the_recorded_content_as_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit(
+            &repository,
+            &[("docs/guide.md", "# Guide"), ("docs/faq.md", "# FAQ")],
+        );
+
+        let datasource = GitDatasource::async_try_from(Config {
+            path: dir.path().to_path_buf(),
+            url: None,
+            reference: "main".to_string(),
+            include: vec![],
+            exclude: vec![],
+            web_url: None,
+        })
+        .await
+        .unwrap();
+
+        let documents = stream(&datasource).await;
+        assert_eq!(documents.len(), 2);
+
+        commit(&repository, &[("docs/guide.md", "# Guide\n\nUpdated.")]);
+        let (documents, unchanged) = stream_since(&datasource, &state(&documents)).await;
+
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["docs/guide.md"]);
+        assert_eq!(unchanged, ["docs/faq.md"]);
+    }
+
+    #[tokio::test]
+    async fn follows_the_remote_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let origin = Repository::init(dir.path().join("origin")).unwrap();
+        origin.set_head("refs/heads/main").unwrap();
+        let first = commit(
+            &origin,
+            &[
+                ("docs/guide.md", "# Guide"),
+                ("docs/drafts/plan.md", "# Plan"),
+                ("src/main.rs", "fn main() {}"),
+            ],
+        );
+
+        let datasource = GitDatasource::async_try_from(Config {
+            path: dir.path().join("clone"),
+            url: Some(format!("file://{}", dir.path().join("origin").display())),
+            reference: "main".to_string(),
+            include: vec!["docs/**/*.md".to_string()],
+            exclude: vec!["**/drafts/**".to_string()],
+            web_url: Some("https://git.example.org/docs/blob/{commit}/{path}".to_string()),
+        })
+        .await
+        .unwrap();
+
+        let documents = stream(&datasource).await;
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].id, "docs/guide.md");
+        assert_eq!(documents[0].name, "guide.md");
+        assert_eq!(
+            documents[0].url.as_deref(),
+            Some(format!("https://git.example.org/docs/blob/{first}/docs/guide.md").as_str())
+        );
+
+        let second = commit(
+            &origin,
+            &[
+                ("docs/guide.md", "# Guide\n\nUpdated."),
+                ("docs/faq.md", "# FAQ"),
+                ("docs/drafts/ideas.md", "# Ideas"),
+            ],
+        );
+
+        let documents = stream(&datasource).await;
+        let ids: Vec<&str> = documents.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["docs/faq.md", "docs/guide.md"]);
+        assert_eq!(documents[1].content, "# Guide\n\nUpdated.");
+        for document in &documents {
+            assert_eq!(
+                document.url,
+                Some(format!(
+                    "https://git.example.org/docs/blob/{second}/{}",
+                    document.id
+                ))
+            );
+        }
+    }
+}
@@ -1,25 +1,164 @@
-use anyhow::Error;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    future::Future,
+    time::Duration,
+};
+
+use anyhow::Result;
 use futures_util::StreamExt;
 use google_drive3::{
-    hyper::{self, body},
+    api::{Change, File},
+    hyper::{self, body, StatusCode},
     hyper_rustls, oauth2, DriveHub,
 };
+use log::warn;
 use serde::Deserialize;
+use thiserror::Error;
 use tokio::sync::mpsc::Sender;
 
-use crate::{document::Document, interals::AsyncTryFrom};
+use crate::{
+    document::{Document, Metadata, Provenance},
+    interals::AsyncTryFrom,
+    ledger::Entries,
+};
+
+use super::{Datasource, Event, Listing, State};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot read service account key {0}: {1}")]
+    ReadServiceAccount(String, String),
+    #[error("cannot authenticate with the service account: {0}")]
+    Authenticate(String),
+    #[error("cannot list files: {0}")]
+    ListFiles(String),
+    #[error("cannot list changes: {0}")]
+    ListChanges(String),
+    #[error("cannot get file: {0}")]
+    GetFile(String),
+    #[error("cannot export file: {0}")]
+    ExportFile(String),
+    #[error("exported file is not valid UTF-8")]
+    InvalidContent,
+}
+
+const RETRIES: u32 = 5;
 
-use super::Datasource;
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+const FIELDS: &str = "nextPageToken, files(id, name, mimeType, createdTime, modifiedTime, \
+                      lastModifyingUser, owners, webViewLink)";
+
+const CHANGE_FIELDS: &str = "nextPageToken, newStartPageToken, changes(fileId, removed, \
+                             file(id, name, mimeType, createdTime, modifiedTime, \
+                             lastModifyingUser, owners, webViewLink, trashed, parents, driveId))";
+
+const FILE_FIELDS: &str = "id, name, mimeType, createdTime, modifiedTime, lastModifyingUser, \
+                           owners, webViewLink, trashed, parents, driveId";
+
+// Key of the page token following the changes of every drive, as opposed to a single
+// shared drive.
+const ALL_DRIVES: &str = "allDrives";
+
+/// Changes API page tokens by drive, stored as the datasource cursor.
+type PageTokens = BTreeMap<String, String>;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     service_account: String,
     subject: Option<String>,
+    #[serde(default)]
+    drives: Vec<String>,
+    #[serde(default)]
+    folders: Vec<String>,
+    #[serde(default)]
+    changes: bool,
 }
 
 pub struct GoogleDatasource {
     client: DriveHub<hyper_rustls::HttpsConnector<hyper::client::HttpConnector>>,
-    // config: Config,
+    drives: Vec<String>,
+    folders: Vec<String>,
+    changes: bool,
+}
+
+// Docs are exported as Markdown to keep their headings, and Sheets as CSV, which only
+// contains their first sheet.
+fn export_mime_type(mime_type: &str) -> Option<&'static str> {
+    match mime_type {
+        "application/vnd.google-apps.document" => Some("text/markdown"),
+        "application/vnd.google-apps.spreadsheet" => Some("text/csv"),
+        "application/vnd.google-apps.presentation" => Some("text/plain"),
+        _ => None,
+    }
+}
+
+fn supported_mime_types() -> String {
+    [
+        "application/vnd.google-apps.document",
+        "application/vnd.google-apps.spreadsheet",
+        "application/vnd.google-apps.presentation",
+    ]
+    .iter()
+    .map(|mime_type| format!("mimeType = '{mime_type}'"))
+    .collect::<Vec<_>>()
+    .join(" or ")
+}
+
+fn is_not_found(error: &google_drive3::Error) -> bool {
+    match error {
+        google_drive3::Error::Failure(res) => res.status() == StatusCode::NOT_FOUND,
+        google_drive3::Error::BadRequest(value) => value["error"]["code"].as_u64() == Some(404),
+        _ => false,
+    }
+}
+
+fn is_transient(error: &google_drive3::Error) -> bool {
+    let retryable =
+        |status: StatusCode| status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+
+    match error {
+        google_drive3::Error::HttpError(_) | google_drive3::Error::Io(_) => true,
+        google_drive3::Error::Failure(res) => retryable(res.status()),
+        google_drive3::Error::BadRequest(value) => {
+            let error = &value["error"];
+            let rate_limited = error["errors"].as_array().into_iter().flatten().any(|e| {
+                matches!(
+                    e["reason"].as_str(),
+                    Some("rateLimitExceeded" | "userRateLimitExceeded")
+                )
+            });
+
+            rate_limited
+                || error["code"]
+                    .as_u64()
+                    .and_then(|code| StatusCode::from_u16(code as u16).ok())
+                    .is_some_and(retryable)
+        }
+        _ => false,
+    }
+}
+
+/// Runs a Drive call, retrying with an exponential backoff when it failed because of
+/// the network, rate limiting or a server error.
+async fn retry<T, F, Fut>(mut call: F) -> Result<T, google_drive3::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, google_drive3::Error>>,
+{
+    let mut attempt = 0;
+
+    loop {
+        match call().await {
+            Err(e) if attempt < RETRIES && is_transient(&e) => {
+                let delay = Duration::from_millis(500 * 2u64.pow(attempt));
+                warn!("Google Drive request failed, retrying in {delay:?}: {e}");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
 }
 
 impl std::fmt::Debug for GoogleDatasource {
@@ -30,19 +169,22 @@ impl std::fmt::Debug for GoogleDatasource {
 
 #[async_trait::async_trait]
 impl AsyncTryFrom<Config> for GoogleDatasource {
-    type Error = Error;
+    type Error = anyhow::Error;
 
     async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
-        let service_account = oauth2::read_service_account_key(value.service_account)
+        let service_account = oauth2::read_service_account_key(&value.service_account)
             .await
-            .unwrap();
+            .map_err(|e| Error::ReadServiceAccount(value.service_account.clone(), e.to_string()))?;
 
         let mut auth = oauth2::ServiceAccountAuthenticator::builder(service_account);
-        if let Some(subject) = value.subject {
+        if let Some(subject) = &value.subject {
             auth = auth.subject(subject);
         }
 
-        let auth = auth.build().await.unwrap();
+        let auth = auth
+            .build()
+            .await
+            .map_err(|e| Error::Authenticate(e.to_string()))?;
 
         let client = DriveHub::new(
             hyper::Client::builder().build(
@@ -54,53 +196,847 @@ impl AsyncTryFrom<Config> for GoogleDatasource {
             ),
             auth,
         );
-        Ok(Self { client })
+        Ok(Self {
+            client,
+            drives: value.drives,
+            folders: value.folders,
+            changes: value.changes,
+        })
     }
 }
 
 impl GoogleDatasource {
-    async fn export(&self, id: &str) -> String {
-        let data = self
-            .client
-            .files()
-            .export(id, "text/plain")
-            .doit()
+    async fn list(&self, q: &str, drive: Option<&str>) -> Result<Vec<File>, Error> {
+        let mut files = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let (_, res) = retry(|| {
+                let mut call = self
+                    .client
+                    .files()
+                    .list()
+                    .supports_all_drives(true)
+                    .include_items_from_all_drives(true)
+                    .page_size(1000)
+                    .q(q)
+                    .param("fields", FIELDS);
+
+                call = match drive {
+                    Some(drive) => call.corpora("drive").drive_id(drive),
+                    None => call.corpora("allDrives"),
+                };
+
+                if let Some(page_token) = &page_token {
+                    call = call.page_token(page_token);
+                }
+
+                call.doit()
+            })
             .await
-            .unwrap();
+            .map_err(|e| Error::ListFiles(e.to_string()))?;
 
-        let bytes = body::to_bytes(data.into_body()).await.unwrap();
-        String::from_utf8(bytes.into_iter().collect()).unwrap()
+            files.extend(res.files.unwrap_or_default());
+
+            page_token = res.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(files)
     }
-}
 
-#[async_trait::async_trait]
-impl Datasource for GoogleDatasource {
-    async fn stream_documents(&self, tx: Sender<Document>) {
-        let (_, res) = self
-            .client
-            .files()
-            .list()
-            .corpora("allDrives")
-            .supports_all_drives(true)
-            .include_items_from_all_drives(true)
-            .q("mimeType = 'application/vnd.google-apps.document'")
-            .doit()
+    /// Walks the configured folders and their subfolders, returning the files they contain
+    /// when `with_files` is set and the ids of every folder visited.
+    async fn walk_folders(&self, with_files: bool) -> Result<(Vec<File>, HashSet<String>), Error> {
+        let mut files = vec![];
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<String> = self.folders.iter().cloned().collect();
+
+        while let Some(folder) = queue.pop_front() {
+            if !visited.insert(folder.clone()) {
+                continue;
+            }
+
+            let q = match with_files {
+                true => format!(
+                    "'{folder}' in parents and trashed = false and \
+                     (mimeType = '{FOLDER_MIME_TYPE}' or {})",
+                    supported_mime_types()
+                ),
+                false => format!(
+                    "'{folder}' in parents and trashed = false and \
+                     mimeType = '{FOLDER_MIME_TYPE}'"
+                ),
+            };
+
+            for file in self.list(&q, None).await? {
+                match file.mime_type.as_deref() {
+                    Some(FOLDER_MIME_TYPE) => queue.extend(file.id),
+                    _ => files.push(file),
+                }
+            }
+        }
+
+        Ok((files, visited))
+    }
+
+    async fn list_files(&self) -> Result<Vec<File>, Error> {
+        let q = format!("trashed = false and ({})", supported_mime_types());
+
+        if self.drives.is_empty() && self.folders.is_empty() {
+            return self.list(&q, None).await;
+        }
+
+        let mut files = vec![];
+        for drive in &self.drives {
+            files.extend(self.list(&q, Some(drive)).await?);
+        }
+        files.extend(self.walk_folders(true).await?.0);
+
+        let mut ids = HashSet::new();
+        files.retain(|file| file.id.as_ref().is_some_and(|id| ids.insert(id.clone())));
+
+        Ok(files)
+    }
+
+    fn page_token_keys(&self) -> Vec<String> {
+        let mut keys = self.drives.clone();
+        if self.drives.is_empty() || !self.folders.is_empty() {
+            keys.push(ALL_DRIVES.to_string());
+        }
+
+        keys
+    }
+
+    /// Parses the stored cursor, unless it was recorded for other drives than the
+    /// configured ones.
+    fn page_tokens(&self, cursor: Option<&str>) -> Option<PageTokens> {
+        let tokens: PageTokens = serde_json::from_str(cursor?).ok()?;
+        let mut keys = self.page_token_keys();
+        keys.sort();
+
+        match tokens.keys().eq(keys.iter()) {
+            true => Some(tokens),
+            false => None,
+        }
+    }
+
+    async fn start_page_tokens(&self) -> Result<PageTokens, Error> {
+        let mut tokens = PageTokens::new();
+
+        for key in self.page_token_keys() {
+            let (_, res) = retry(|| {
+                let mut call = self
+                    .client
+                    .changes()
+                    .get_start_page_token()
+                    .supports_all_drives(true);
+
+                if key != ALL_DRIVES {
+                    call = call.drive_id(&key);
+                }
+
+                call.doit()
+            })
+            .await
+            .map_err(|e| Error::ListChanges(e.to_string()))?;
+
+            let token = res
+                .start_page_token
+                .ok_or_else(|| Error::ListChanges(format!("missing start page token for {key}")))?;
+            tokens.insert(key, token);
+        }
+
+        Ok(tokens)
+    }
+
+    async fn list_changes(
+        &self,
+        drive: &str,
+        mut page_token: String,
+    ) -> Result<(Vec<Change>, String), Error> {
+        let mut changes = vec![];
+
+        loop {
+            let (_, res) = retry(|| {
+                let mut call = self
+                    .client
+                    .changes()
+                    .list(&page_token)
+                    .supports_all_drives(true)
+                    .include_items_from_all_drives(true)
+                    .include_removed(true)
+                    .page_size(1000)
+                    .param("fields", CHANGE_FIELDS);
+
+                if drive != ALL_DRIVES {
+                    call = call.drive_id(drive);
+                }
+
+                call.doit()
+            })
             .await
-            .unwrap();
+            .map_err(|e| Error::ListChanges(e.to_string()))?;
 
-        tokio_stream::iter(res.files.unwrap())
-            .for_each_concurrent(8, |f| async {
-                let id = f.id.unwrap();
-                let content = self.export(&id).await;
+            changes.extend(res.changes.unwrap_or_default());
+
+            if let Some(token) = res.new_start_page_token {
+                return Ok((changes, token));
+            }
+
+            page_token = res.next_page_token.ok_or_else(|| {
+                Error::ListChanges(format!("missing page token for changes of {drive}"))
+            })?;
+        }
+    }
+
+    /// Gets a file by id, `None` when it was deleted.
+    async fn get_file(&self, id: &str) -> Result<Option<File>, Error> {
+        let res = retry(|| {
+            self.client
+                .files()
+                .get(id)
+                .supports_all_drives(true)
+                .param("fields", FILE_FIELDS)
+                .doit()
+        })
+        .await;
+
+        match res {
+            Ok((_, file)) => Ok(Some(file)),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(Error::GetFile(e.to_string())),
+        }
+    }
+
+    /// Fetches the changes since `tokens` and the files that failed last time, returning
+    /// the files to index, the ids of the files that were removed or left the configured
+    /// drives and folders, and the tokens to resume from.
+    async fn changes(
+        &self,
+        tokens: PageTokens,
+        known: &Entries,
+        failed: &[String],
+    ) -> Result<(Vec<File>, Vec<String>, PageTokens), Error> {
+        let folders = match self.folders.is_empty() {
+            true => HashSet::new(),
+            false => self.walk_folders(false).await?.1,
+        };
+
+        let mut changes = HashMap::new();
+        let mut next = PageTokens::new();
+        for (drive, token) in tokens {
+            let (list, token) = self.list_changes(&drive, token).await?;
+            for change in list {
+                if let Some(id) = change.file_id.clone() {
+                    changes.insert(id, change);
+                }
+            }
+            next.insert(drive, token);
+        }
+
+        for id in failed {
+            if changes.contains_key(id) {
+                continue;
+            }
+            let file = self.get_file(id).await?;
+            let change = Change {
+                file_id: Some(id.clone()),
+                removed: Some(file.is_none()),
+                file,
+                ..Default::default()
+            };
+            changes.insert(id.clone(), change);
+        }
+
+        let mut files = vec![];
+        let mut removed = vec![];
+        for (id, change) in changes {
+            let file = match change.file {
+                Some(file) if change.removed != Some(true) && file.trashed != Some(true) => file,
+                _ => {
+                    removed.push(id);
+                    continue;
+                }
+            };
+
+            let supported = file
+                .mime_type
+                .as_deref()
+                .and_then(export_mime_type)
+                .is_some();
+
+            let in_drives = file
+                .drive_id
+                .as_ref()
+                .is_some_and(|drive| self.drives.contains(drive));
+
+            let in_folders = file
+                .parents
+                .as_ref()
+                .is_some_and(|parents| parents.iter().any(|parent| folders.contains(parent)));
+
+            let in_scope =
+                (self.drives.is_empty() && self.folders.is_empty()) || in_drives || in_folders;
+
+            match (supported, in_scope) {
+                (true, true) => files.push(file),
+                _ if known.contains_key(&id) => removed.push(id),
+                _ => {}
+            }
+        }
+
+        Ok((files, removed, next))
+    }
+
+    async fn stream_files(&self, files: Vec<File>, known: &Entries, tx: &Sender<Event>) {
+        tokio_stream::iter(files)
+            .for_each_concurrent(8, |f| async move {
+                let Some(id) = f.id else {
+                    return;
+                };
+
+                let unchanged = known
+                    .get(&id)
+                    .is_some_and(|e| e.modified_at.is_some() && e.modified_at == f.modified_time);
+
+                if unchanged {
+                    let _ = tx.send(Event::Unchanged(id)).await;
+                    return;
+                }
+
+                let Some(export_mime_type) = f.mime_type.as_deref().and_then(export_mime_type)
+                else {
+                    return;
+                };
+
+                let content = match self.export(&id, export_mime_type).await {
+                    Ok(content) => content,
+                    Err(e) => {
+                        let _ = tx.send(Event::Failed(id, e.to_string())).await;
+                        return;
+                    }
+                };
+
+                let author = f
+                    .last_modifying_user
+                    .or_else(|| f.owners.and_then(|owners| owners.into_iter().next()))
+                    .and_then(|user| user.display_name.or(user.email_address));
                 let _ = tx
-                    .send(Document {
+                    .send(Event::Document(Box::new(Document {
+                        name: f.name.unwrap_or_else(|| id.clone()),
                         id,
-                        name: f.name.unwrap(),
                         content,
-                        url: None,
-                    })
+                        url: f.web_view_link,
+                        metadata: Metadata::new(),
+                        provenance: Provenance {
+                            mime_type: f.mime_type,
+                            author,
+                            created_at: f.created_time,
+                            modified_at: f.modified_time,
+                            ..Default::default()
+                        },
+                        chunk: None,
+                    })))
                     .await;
             })
-            .await
+            .await;
+    }
+
+    async fn export(&self, id: &str, mime_type: &str) -> Result<String, Error> {
+        let bytes = retry(|| async {
+            let res = self.client.files().export(id, mime_type).doit().await?;
+            body::to_bytes(res.into_body())
+                .await
+                .map_err(google_drive3::Error::HttpError)
+        })
+        .await
+        .map_err(|e| Error::ExportFile(e.to_string()))?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidContent)
+    }
+}
+
+#[async_trait::async_trait]
+impl Datasource for GoogleDatasource {
+    async fn stream_documents(&self, state: &State, tx: Sender<Event>) -> Result<Listing> {
+        if !self.changes {
+            let files = self.list_files().await?;
+            self.stream_files(files, &state.entries, &tx).await;
+            return Ok(Listing::Full);
+        }
+
+        if let Some(tokens) = self.page_tokens(state.cursor.as_deref()) {
+            let (files, removed, tokens) =
+                self.changes(tokens, &state.entries, &state.failed).await?;
+
+            for id in removed {
+                let _ = tx.send(Event::Deleted(id)).await;
+            }
+            self.stream_files(files, &state.entries, &tx).await;
+            let _ = tx
+                .send(Event::Cursor(serde_json::to_string(&tokens)?))
+                .await;
+
+            return Ok(Listing::Changes);
+        }
+
+        // The tokens are taken before listing so changes made during the listing are
+        // fetched again by the next synchronization.
+        let tokens = self.start_page_tokens().await?;
+        let files = self.list_files().await?;
+        self.stream_files(files, &state.entries, &tx).await;
+        let _ = tx
+            .send(Event::Cursor(serde_json::to_string(&tokens)?))
+            .await;
+
+        Ok(Listing::Full)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        extract::{Query, State as AxumState},
+        http::{Method, Uri},
+        response::{IntoResponse, Response},
+        Json, Router,
+    };
+    use google_drive3::client::NoToken;
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc;
+
+    use crate::ledger::Entry;
+
+    use super::*;
+
+    const DOCUMENT: &str = "application/vnd.google-apps.document";
+    const SPREADSHEET: &str = "application/vnd.google-apps.spreadsheet";
+    const PRESENTATION: &str = "application/vnd.google-apps.presentation";
+
+    /// Files of the stand-in Drive and the requests it received.
+    #[derive(Default)]
+    struct Drive {
+        files: Vec<Value>,
+        /// Files per page of listings.
+        page_size: usize,
+        /// Ids and mime types of the exported files.
+        exports: Vec<(String, String)>,
+        /// Paths of the listing requests.
+        listings: Vec<String>,
+        /// Start page tokens of the changes by drive, the empty one for every drive.
+        start_page_tokens: HashMap<String, String>,
+        /// Pages of changes by page token.
+        changes: HashMap<String, Value>,
+        /// Statuses answered to the first requests of a path, before it succeeds.
+        failures: HashMap<String, VecDeque<u16>>,
+    }
+
+    type Shared = Arc<Mutex<Drive>>;
+
+    async fn handle(
+        AxumState(state): AxumState<Shared>,
+        method: Method,
+        uri: Uri,
+        Query(params): Query<HashMap<String, String>>,
+    ) -> Response {
+        let mut state = state.lock().unwrap();
+        let path = uri.path().trim_start_matches("/drive/v3");
+        let not_found = (
+            hyper::StatusCode::NOT_FOUND,
+            Json(json!({ "error": { "code": 404, "message": "not found" } })),
+        );
+
+        let failure = state.failures.get_mut(path).and_then(VecDeque::pop_front);
+        if let Some(code) = failure {
+            let reason = match code {
+                429 => "rateLimitExceeded",
+                403 => "exportSizeLimitExceeded",
+                _ => "backendError",
+            };
+            let error = json!({
+                "error": { "code": code, "message": reason, "errors": [{ "reason": reason }] }
+            });
+            let status = hyper::StatusCode::from_u16(code).unwrap();
+            return (status, Json(error)).into_response();
+        }
+
+        match (
+            method.as_str(),
+            path.split('/').collect::<Vec<_>>().as_slice(),
+        ) {
+            ("GET", ["", "files"]) => {
+                state.listings.push(params["q"].clone());
+                let files = list(&state, &params);
+                let start: usize = params.get("pageToken").map_or(0, |t| t.parse().unwrap());
+                let end = (start + state.page_size).min(files.len());
+                let mut res = json!({ "files": files[start..end] });
+                if end < files.len() {
+                    res["nextPageToken"] = Value::from(end.to_string());
+                }
+                Json(res).into_response()
+            }
+            ("GET", ["", "files", id]) => match state.files.iter().find(|file| file["id"] == *id) {
+                Some(file) => Json(file.clone()).into_response(),
+                None => not_found.into_response(),
+            },
+            ("GET", ["", "changes", "startPageToken"]) => {
+                let drive = params.get("driveId").cloned().unwrap_or_default();
+                let token = &state.start_page_tokens[&drive];
+                Json(json!({ "startPageToken": token })).into_response()
+            }
+            ("GET", ["", "changes"]) => match state.changes.get(&params["pageToken"]) {
+                Some(page) => Json(page.clone()).into_response(),
+                None => not_found.into_response(),
+            },
+            ("GET", ["", "files", id, "export"]) => {
+                state
+                    .exports
+                    .push((id.to_string(), params["mimeType"].clone()));
+                format!("Content of {id}").into_response()
+            }
+            _ => not_found.into_response(),
+        }
+    }
+
+    /// The files matching the query of a listing, only those in a folder with
+    /// `'<folder>' in parents`, and in a drive when one is given.
+    fn list(state: &Drive, params: &HashMap<String, String>) -> Vec<Value> {
+        let q = &params["q"];
+        let parent = q.strip_prefix('\'').and_then(|q| q.split('\'').next());
+
+        state
+            .files
+            .iter()
+            .filter(|file| {
+                let mime_type = file["mimeType"].as_str().unwrap();
+                q.contains(&format!("mimeType = '{mime_type}'"))
+                    && parent.is_none_or(|parent| file["parents"][0] == parent)
+                    && params
+                        .get("driveId")
+                        .is_none_or(|drive| file["driveId"] == drive.as_str())
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn serve(files: Vec<Value>) -> (String, Shared) {
+        let state = Shared::new(Mutex::new(Drive {
+            files,
+            page_size: 100,
+            ..Default::default()
+        }));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new().fallback(handle).with_state(state.clone());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (host, state)
+    }
+
+    fn file(id: &str, mime_type: &str, parent: &str) -> Value {
+        json!({
+            "id": id,
+            "name": format!("{id} name"),
+            "mimeType": mime_type,
+            "parents": [parent],
+            "driveId": "drive",
+            "modifiedTime": "2024-01-02T03:04:05Z",
+            "webViewLink": format!("https://docs.google.com/{id}"),
+        })
+    }
+
+    fn datasource(host: &str, folders: &[&str], changes: bool) -> GoogleDatasource {
+        let mut client = DriveHub::new(
+            hyper::Client::builder().build(
+                hyper_rustls::HttpsConnectorBuilder::new()
+                    .with_native_roots()
+                    .https_or_http()
+                    .enable_http1()
+                    .build(),
+            ),
+            NoToken,
+        );
+        client.base_url(format!("{host}/drive/v3/"));
+
+        GoogleDatasource {
+            client,
+            drives: vec![],
+            folders: folders.iter().map(|folder| folder.to_string()).collect(),
+            changes,
+        }
+    }
+
+    async fn stream(datasource: &GoogleDatasource, state: &State) -> (Listing, Vec<Event>) {
+        let (tx, mut rx) = mpsc::channel(100);
+        let listing = datasource.stream_documents(state, tx).await.unwrap();
+
+        let mut events = vec![];
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+
+        (listing, events)
+    }
+
+    /// The events, in a stable order as files are exported concurrently.
+    fn describe(events: &[Event]) -> Vec<String> {
+        let mut events: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                Event::Document(document) => format!("document {}", document.id),
+                Event::Unchanged(id) => format!("unchanged {id}"),
+                Event::Deleted(id) => format!("deleted {id}"),
+                Event::Cursor(cursor) => format!("cursor {cursor}"),
+                Event::Failed(id, _) => format!("failed {id}"),
+            })
+            .collect();
+        events.sort();
+
+        events
+    }
+
+    #[tokio::test]
+    async fn lists_every_page_of_files() {
+        let files = ["a", "b", "c", "d", "e"]
+            .map(|id| file(id, DOCUMENT, "root"))
+            .to_vec();
+        let (host, state) = serve(files);
+        state.lock().unwrap().page_size = 2;
+
+        let (listing, events) = stream(&datasource(&host, &[], false), &State::default()).await;
+
+        assert_eq!(listing, Listing::Full);
+        assert_eq!(
+            describe(&events),
+            ["a", "b", "c", "d", "e"].map(|id| format!("document {id}"))
+        );
+        assert_eq!(state.lock().unwrap().listings.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn walks_folders_recursively() {
+        let (host, state) = serve(vec![
+            file("a", DOCUMENT, "root"),
+            file("sub", FOLDER_MIME_TYPE, "root"),
+            file("b", SPREADSHEET, "sub"),
+            file("deeper", FOLDER_MIME_TYPE, "sub"),
+            file("c", PRESENTATION, "deeper"),
+            file("elsewhere", DOCUMENT, "other"),
+        ]);
+
+        let (_, events) = stream(&datasource(&host, &["root"], false), &State::default()).await;
+
+        assert_eq!(
+            describe(&events),
+            ["document a", "document b", "document c"]
+        );
+        let listings = state.lock().unwrap().listings.clone();
+        assert_eq!(listings.len(), 3);
+        assert!(listings[2].starts_with("'deeper' in parents"));
+    }
+
+    #[tokio::test]
+    async fn exports_docs_sheets_and_slides() {
+        let (host, state) = serve(vec![
+            file("doc", DOCUMENT, "root"),
+            file("sheet", SPREADSHEET, "root"),
+            file("slides", PRESENTATION, "root"),
+        ]);
+
+        let (_, events) = stream(&datasource(&host, &[], false), &State::default()).await;
+
+        let mut exports = state.lock().unwrap().exports.clone();
+        exports.sort();
+        assert_eq!(
+            exports,
+            [
+                ("doc".to_string(), "text/markdown".to_string()),
+                ("sheet".to_string(), "text/csv".to_string()),
+                ("slides".to_string(), "text/plain".to_string()),
+            ]
+        );
+
+        for event in events {
+            let Event::Document(document) = event else {
+                panic!("unexpected event {event:?}");
+            };
+            assert_eq!(document.name, format!("{} name", document.id));
+            assert_eq!(document.content, format!("Content of {}", document.id));
+            assert_eq!(
+                document.url,
+                Some(format!("https://docs.google.com/{}", document.id))
+            );
+            assert!(document.provenance.modified_at.is_some());
+        }
+    }
+
+    fn entries(ids: &[&str]) -> Entries {
+        ids.iter()
+            .map(|id| {
+                let entry = Entry {
+                    modified_at: None,
+                    content_hash: String::new(),
+                    chunking: "none".to_string(),
+                };
+                (id.to_string(), entry)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn follows_the_changes_since_the_stored_page_token() {
+        let (host, state) = serve(vec![
+            file("a", DOCUMENT, "root"),
+            file("b", DOCUMENT, "root"),
+        ]);
+        state
+            .lock()
+            .unwrap()
+            .start_page_tokens
+            .insert(String::new(), "10".to_string());
+        let datasource = datasource(&host, &[], true);
+
+        // Without a cursor, every file is listed after taking the start page token.
+        let (listing, events) = stream(&datasource, &State::default()).await;
+        assert_eq!(listing, Listing::Full);
+        assert_eq!(
+            describe(&events),
+            [r#"cursor {"allDrives":"10"}"#, "document a", "document b"]
+        );
+
+        state.lock().unwrap().changes = HashMap::from([
+            (
+                "10".to_string(),
+                json!({
+                    "changes": [{ "fileId": "a", "removed": false, "file": file("a", DOCUMENT, "root") }],
+                    "nextPageToken": "11",
+                }),
+            ),
+            (
+                "11".to_string(),
+                json!({
+                    "changes": [{ "fileId": "b", "removed": true }],
+                    "newStartPageToken": "12",
+                }),
+            ),
+        ]);
+        let listings = state.lock().unwrap().listings.len();
+
+        let (listing, events) = stream(
+            &datasource,
+            &State {
+                entries: entries(&["a", "b"]),
+                cursor: Some(r#"{"allDrives":"10"}"#.to_string()),
+                failed: vec![],
+            },
+        )
+        .await;
+
+        assert_eq!(listing, Listing::Changes);
+        assert_eq!(
+            describe(&events),
+            [r#"cursor {"allDrives":"12"}"#, "deleted b", "document a"]
+        );
+        assert_eq!(state.lock().unwrap().listings.len(), listings);
+    }
+
+    #[tokio::test]
+    async fn fetches_failed_files_again_with_the_changes() {
+        let (host, state) = serve(vec![file("a", DOCUMENT, "root")]);
+        state.lock().unwrap().changes = HashMap::from([(
+            "12".to_string(),
+            json!({ "changes": [], "newStartPageToken": "12" }),
+        )]);
+
+        let (_, events) = stream(
+            &datasource(&host, &[], true),
+            &State {
+                entries: entries(&["gone"]),
+                cursor: Some(r#"{"allDrives":"12"}"#.to_string()),
+                failed: vec!["a".to_string(), "gone".to_string()],
+            },
+        )
+        .await;
+
+        assert_eq!(
+            describe(&events),
+            [r#"cursor {"allDrives":"12"}"#, "deleted gone", "document a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_a_page_token_per_drive() {
+        let (host, state) = serve(vec![file("a", DOCUMENT, "root")]);
+        state.lock().unwrap().start_page_tokens = HashMap::from([
+            ("drive".to_string(), "1".to_string()),
+            ("other".to_string(), "2".to_string()),
+        ]);
+        let mut datasource = datasource(&host, &[], true);
+        datasource.drives = vec!["drive".to_string(), "other".to_string()];
+
+        // A cursor recorded for other drives is ignored, every file is listed again.
+        let (listing, events) = stream(
+            &datasource,
+            &State {
+                cursor: Some(r#"{"allDrives":"10"}"#.to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        assert_eq!(listing, Listing::Full);
+        assert_eq!(
+            describe(&events),
+            [r#"cursor {"drive":"1","other":"2"}"#, "document a"]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limited_and_failing_requests() {
+        let (host, state) = serve(vec![file("a", DOCUMENT, "root")]);
+        state.lock().unwrap().failures = HashMap::from([
+            ("/files".to_string(), VecDeque::from([429])),
+            ("/files/a/export".to_string(), VecDeque::from([503])),
+        ]);
+
+        let (_, events) = stream(&datasource(&host, &[], false), &State::default()).await;
+
+        assert_eq!(describe(&events), ["document a"]);
+        assert!(state
+            .lock()
+            .unwrap()
+            .failures
+            .values()
+            .all(VecDeque::is_empty));
+    }
+
+    #[tokio::test]
+    async fn reports_files_that_cannot_be_exported() {
+        let (host, state) = serve(vec![
+            file("a", DOCUMENT, "root"),
+            file("b", DOCUMENT, "root"),
+        ]);
+        state.lock().unwrap().failures =
+            HashMap::from([("/files/b/export".to_string(), VecDeque::from([403]))]);
+
+        let (listing, events) = stream(&datasource(&host, &[], false), &State::default()).await;
+
+        assert_eq!(listing, Listing::Full);
+        assert_eq!(describe(&events), ["document a", "failed b"]);
+        // Failures that a retry cannot fix are not retried, the next export would succeed.
+        let exports = state.lock().unwrap().exports.clone();
+        assert_eq!(exports, [("a".to_string(), "text/markdown".to_string())]);
     }
 }
@@ -1,17 +1,114 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+pub mod markdown;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    DateTime(DateTime<Utc>),
+    String(String),
+    List(Vec<MetadataValue>),
+}
+
+pub type Metadata = HashMap<String, MetadataValue>;
+
+impl MetadataValue {
+    // Nested objects have no typed equivalent and are kept as their JSON representation.
+    pub fn from_json(value: Value) -> Option<Self> {
+        let value = match value {
+            Value::Null => return None,
+            Value::Bool(b) => Self::Boolean(b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => Self::Integer(i),
+                None => Self::Float(n.as_f64()?),
+            },
+            Value::String(s) => match DateTime::parse_from_rfc3339(&s) {
+                Ok(date) => Self::DateTime(date.with_timezone(&Utc)),
+                Err(_) => Self::String(s),
+            },
+            Value::Array(a) => Self::List(a.into_iter().filter_map(Self::from_json).collect()),
+            Value::Object(_) => Self::String(value.to_string()),
+        };
+
+        Some(value)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    pub source: Option<String>,
+    pub source_id: Option<String>,
+    pub mime_type: Option<String>,
+    pub author: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub content_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chunk {
+    pub parent_id: String,
+    pub position: usize,
+    #[serde(rename = "start_offset")]
+    pub start: usize,
+    #[serde(rename = "end_offset")]
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
     #[serde(rename = "external_id")]
     pub id: String,
     pub name: String,
     pub content: String,
     pub url: Option<String>,
+    #[serde(default)]
+    pub metadata: Metadata,
+    #[serde(flatten)]
+    pub provenance: Provenance,
+    #[serde(flatten)]
+    pub chunk: Option<Chunk>,
 }
 
 impl Document {
     pub fn uuid(&self) -> Uuid {
         Uuid::new_v5(&Uuid::NAMESPACE_OID, self.id.as_bytes())
     }
+
+    pub fn hash(&self) -> String {
+        content_hash(self.content.as_bytes())
+    }
+}
+
+/// The hash of the content of a document, recorded to detect the documents that changed.
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
 }
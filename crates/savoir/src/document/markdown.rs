@@ -0,0 +1,616 @@
+use std::collections::{HashMap, HashSet};
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use serde_json::Value;
+
+use super::{Document, Metadata, MetadataValue};
+
+const EXTENSIONS: [&str; 4] = [".md", ".markdown", ".mdx", ".mdown"];
+
+// Google Docs are exported as Markdown.
+const MIME_TYPES: [&str; 2] = ["text/markdown", "application/vnd.google-apps.document"];
+
+#[derive(Debug, Default)]
+pub struct Markdown {
+    pub front_matter: Metadata,
+    pub sections: Vec<Section>,
+}
+
+#[derive(Debug, Default)]
+pub struct Section {
+    pub headings: Vec<String>,
+    pub anchor: Option<String>,
+    pub content: String,
+}
+
+pub fn is_markdown(document: &Document) -> bool {
+    let name = document.name.to_lowercase();
+    let id = document.id.to_lowercase();
+
+    EXTENSIONS
+        .iter()
+        .any(|ext| name.ends_with(ext) || id.ends_with(ext))
+        || document
+            .provenance
+            .mime_type
+            .as_deref()
+            .is_some_and(|mime_type| MIME_TYPES.contains(&mime_type))
+}
+
+fn is_mdx(document: &Document) -> bool {
+    [&document.name, &document.id]
+        .iter()
+        .any(|name| name.to_lowercase().ends_with(".mdx"))
+}
+
+/// Splits a Markdown document into one document per section, each identified and
+/// linked by its heading anchor, with the front-matter copied into the metadata.
+pub fn split(document: Document) -> Vec<Document> {
+    let markdown = parse(&document.content, is_mdx(&document));
+
+    let title = markdown
+        .front_matter
+        .get("title")
+        .and_then(MetadataValue::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| document.name.clone());
+
+    let mut provenance = document.provenance.clone();
+    if provenance.author.is_none() {
+        provenance.author = markdown
+            .front_matter
+            .get("author")
+            .and_then(MetadataValue::as_str)
+            .map(str::to_string);
+    }
+
+    markdown
+        .sections
+        .into_iter()
+        .filter(|section| {
+            let content = section.content.trim();
+            !content.is_empty() && Some(content) != section.headings.last().map(String::as_str)
+        })
+        .map(|section| {
+            let mut metadata = document.metadata.clone();
+            metadata.extend(markdown.front_matter.clone());
+
+            let (id, url) = match &section.anchor {
+                Some(anchor) => {
+                    metadata.insert("anchor".to_string(), MetadataValue::from(anchor.as_str()));
+                    (
+                        format!("{}#{anchor}", document.id),
+                        document.url.as_ref().map(|url| format!("{url}#{anchor}")),
+                    )
+                }
+                None => (document.id.clone(), document.url.clone()),
+            };
+
+            let name = match section.headings.is_empty() {
+                true => title.clone(),
+                false => format!("{title} > {}", section.headings.join(" > ")),
+            };
+
+            if !section.headings.is_empty() {
+                let headings = section.headings.into_iter().map(MetadataValue::from);
+                metadata.insert(
+                    "section".to_string(),
+                    MetadataValue::List(headings.collect()),
+                );
+            }
+
+            Document {
+                id,
+                name,
+                content: section.content.trim().to_string(),
+                url,
+                metadata,
+                provenance: provenance.clone(),
+                chunk: None,
+            }
+        })
+        .collect()
+}
+
+/// Parses Markdown, or MDX whose import and export statements are left out.
+pub fn parse(content: &str, mdx: bool) -> Markdown {
+    let (front_matter, body) = front_matter(content);
+    let body = strip_noise(body, mdx);
+
+    let mut sections = vec![Section::default()];
+    let mut headings: Vec<(HeadingLevel, String)> = vec![];
+    let mut anchors = Anchors::default();
+
+    let mut heading: Option<(HeadingLevel, Option<String>, String)> = None;
+    let mut image_depth = 0;
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_HEADING_ATTRIBUTES;
+
+    for event in Parser::new_ext(&body, options) {
+        let text = match (&mut heading, event) {
+            (_, Event::Start(Tag::Heading(level, id, _))) => {
+                heading = Some((level, id.map(str::to_string), String::new()));
+                continue;
+            }
+            (_, Event::End(Tag::Heading(..))) => {
+                let Some((level, id, title)) = heading.take() else {
+                    continue;
+                };
+                let title = title.trim().to_string();
+
+                headings.retain(|(l, _)| *l < level);
+                headings.push((level, title.clone()));
+
+                let anchor = anchors.next(id.unwrap_or_else(|| slugify(&title)));
+                let headings = headings.iter().map(|(_, title)| title.clone()).collect();
+
+                sections.push(Section {
+                    content: format!("{title}\n\n"),
+                    headings,
+                    anchor: Some(anchor),
+                });
+                continue;
+            }
+            (Some((_, _, title)), Event::Text(text) | Event::Code(text)) => {
+                title.push_str(&text);
+                continue;
+            }
+            (_, Event::Start(Tag::Image(..))) => {
+                image_depth += 1;
+                continue;
+            }
+            (_, Event::End(Tag::Image(..))) => {
+                image_depth -= 1;
+                continue;
+            }
+            (_, Event::Text(_)) if image_depth > 0 => continue,
+            (_, Event::Text(text)) => text.to_string(),
+            (_, Event::Code(code)) => format!("`{code}`"),
+            (_, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))) => {
+                format!("```{lang}\n")
+            }
+            (_, Event::Start(Tag::CodeBlock(_))) => "```\n".to_string(),
+            (_, Event::End(Tag::CodeBlock(_))) => "```\n\n".to_string(),
+            (_, Event::Start(Tag::Item)) => "- ".to_string(),
+            (_, Event::End(Tag::Item)) => "\n".to_string(),
+            (_, Event::End(Tag::List(_))) => "\n".to_string(),
+            (_, Event::End(Tag::Paragraph)) => "\n\n".to_string(),
+            (_, Event::End(Tag::TableCell)) => " | ".to_string(),
+            (_, Event::End(Tag::TableHead | Tag::TableRow)) => "\n".to_string(),
+            (_, Event::End(Tag::Table(_))) => "\n".to_string(),
+            (_, Event::SoftBreak) => " ".to_string(),
+            (_, Event::HardBreak) => "\n".to_string(),
+            (_, Event::TaskListMarker(true)) => "[x] ".to_string(),
+            (_, Event::TaskListMarker(false)) => "[ ] ".to_string(),
+            _ => continue,
+        };
+
+        if let Some(section) = sections.last_mut() {
+            section.content.push_str(&text);
+        }
+    }
+
+    Markdown {
+        front_matter,
+        sections,
+    }
+}
+
+fn front_matter(content: &str) -> (Metadata, &str) {
+    let content = content.trim_start_matches('\u{feff}');
+
+    for delimiter in ["---", "+++"] {
+        let Some(rest) = content.strip_prefix(delimiter) else {
+            continue;
+        };
+        let Some(rest) = rest
+            .strip_prefix('\n')
+            .or_else(|| rest.strip_prefix("\r\n"))
+        else {
+            continue;
+        };
+
+        let end = rest
+            .match_indices(&format!("\n{delimiter}"))
+            .map(|(i, _)| i)
+            .next();
+
+        let Some(end) = end else {
+            continue;
+        };
+
+        let raw = rest[..end].trim_end_matches('\r');
+        let body = rest[end + 1 + delimiter.len()..].trim_start_matches(['\r', '\n']);
+
+        let value = match delimiter {
+            "---" => serde_yaml::from_str::<Value>(raw).ok(),
+            _ => toml::from_str::<toml::Value>(raw).ok().map(toml_to_json),
+        };
+
+        // Anything but a mapping, like text between two thematic breaks, is content.
+        let metadata = match value {
+            Some(Value::Object(map)) => map
+                .into_iter()
+                .filter_map(|(k, v)| Some((k, MetadataValue::from_json(v)?)))
+                .collect(),
+            _ if raw.trim().is_empty() => Metadata::new(),
+            _ => continue,
+        };
+
+        return (metadata, body);
+    }
+
+    (Metadata::new(), content)
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::from(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::from(b),
+        toml::Value::Datetime(d) => Value::from(d.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => {
+            Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+        }
+    }
+}
+
+/// Removes the syntax extensions of Obsidian, Docusaurus and mdBook that CommonMark
+/// would otherwise render as literal text, and the leading imports and exports of MDX.
+fn strip_noise(body: &str, mdx: bool) -> String {
+    let mut lines = vec![];
+    let mut fence: Option<&str> = None;
+    let mut leading = mdx;
+    let mut statement = false;
+
+    for line in body.lines() {
+        let trimmed = line.trim_start();
+
+        if leading {
+            // Statements start at the beginning of a line, and braces spread them over
+            // several lines.
+            if statement || line.starts_with("import ") || line.starts_with("export ") {
+                statement =
+                    line.contains('{') && !line.contains('}') || statement && !line.contains('}');
+                continue;
+            }
+            if !trimmed.is_empty() {
+                leading = false;
+            }
+        }
+
+        match fence {
+            Some(opener) => {
+                if closes(trimmed, opener) {
+                    fence = None;
+                }
+                lines.push(line.to_string());
+                continue;
+            }
+            None => {
+                if let Some(opener) = opens(trimmed) {
+                    fence = Some(opener);
+                    lines.push(line.to_string());
+                    continue;
+                }
+            }
+        }
+
+        if trimmed.starts_with(":::") || (trimmed.starts_with("{{#") && trimmed.ends_with("}}")) {
+            continue;
+        }
+
+        lines.push(strip_wikilinks(line));
+    }
+
+    lines.join("\n")
+}
+
+/// The backticks or tildes opening a fenced code block.
+fn opens(line: &str) -> Option<&str> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(marker).len();
+
+    (length >= 3).then(|| &line[..length])
+}
+
+/// Whether the line closes the fence of the opener: the same character, at least as
+/// many times, and nothing else.
+fn closes(line: &str, opener: &str) -> bool {
+    opens(line)
+        .is_some_and(|marker| marker.starts_with(opener) && line[marker.len()..].trim().is_empty())
+}
+
+fn strip_wikilinks(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start..].find("]]") else {
+            break;
+        };
+
+        let embed = rest[..start].ends_with('!');
+        result.push_str(&rest[..start - usize::from(embed)]);
+
+        if !embed {
+            let link = &rest[start + 2..start + end];
+            let text = link.rsplit('|').next().unwrap_or(link);
+            result.push_str(text.split('#').next().unwrap_or(text));
+        }
+
+        rest = &rest[start + end + 2..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+pub fn slugify(title: &str) -> String {
+    title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Anchors {
+    seen: HashSet<String>,
+    counts: HashMap<String, usize>,
+}
+
+impl Anchors {
+    fn next(&mut self, slug: String) -> String {
+        let slug = match slug.is_empty() {
+            true => "section".to_string(),
+            false => slug,
+        };
+
+        let mut anchor = slug.clone();
+        while self.seen.contains(&anchor) {
+            let count = self.counts.entry(slug.clone()).or_default();
+            *count += 1;
+            anchor = format!("{slug}-{count}");
+        }
+
+        self.seen.insert(anchor.clone());
+        anchor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Provenance;
+
+    use super::*;
+
+    fn anchors(markdown: &Markdown) -> Vec<&str> {
+        markdown
+            .sections
+            .iter()
+            .filter_map(|section| section.anchor.as_deref())
+            .collect()
+    }
+
+    fn content(markdown: &Markdown) -> String {
+        markdown
+            .sections
+            .iter()
+            .map(|section| section.content.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn nests_sections_under_their_headings() {
+        let markdown = parse(
+            "Intro\n\n# Guide\n\n## Install\n\nRun it.\n\n### On `Linux`\n\nUse apt.\n\n## Usage\n\nCall it.", false,
+        );
+
+        let headings: Vec<Vec<&str>> = markdown
+            .sections
+            .iter()
+            .map(|section| section.headings.iter().map(String::as_str).collect())
+            .collect();
+        assert_eq!(
+            headings,
+            [
+                vec![],
+                vec!["Guide"],
+                vec!["Guide", "Install"],
+                vec!["Guide", "Install", "On Linux"],
+                vec!["Guide", "Usage"],
+            ]
+        );
+        assert_eq!(markdown.sections[0].content.trim(), "Intro");
+        assert_eq!(markdown.sections[3].content.trim(), "On Linux\n\nUse apt.");
+    }
+
+    #[test]
+    fn uses_explicit_anchors_and_slugs() {
+        let markdown = parse(
+            "# Getting Started!\n\ntext\n\n## Set up {#setup}\n\ntext",
+            false,
+        );
+
+        assert_eq!(anchors(&markdown), ["getting-started", "setup"]);
+    }
+
+    #[test]
+    fn numbers_duplicate_slugs() {
+        let markdown = parse(
+            "# FAQ\n\ntext\n\n# FAQ\n\ntext\n\n# FAQ-1\n\ntext\n\n# FAQ\n\ntext\n\n# ?\n\ntext",
+            false,
+        );
+
+        assert_eq!(
+            anchors(&markdown),
+            ["faq", "faq-1", "faq-1-1", "faq-2", "section"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_text_of_wikilinks() {
+        assert_eq!(
+            strip_wikilinks(
+                "See [[Deploy]], [[Runbook#Rollback|the rollback]] and [[Ops#Alerts]]."
+            ),
+            "See Deploy, the rollback and Ops."
+        );
+        assert_eq!(strip_wikilinks("![[diagram.png]] below"), " below");
+        assert_eq!(strip_wikilinks("unclosed [[link"), "unclosed [[link");
+    }
+
+    #[test]
+    fn drops_admonition_fences_and_mdx_statements() {
+        let markdown = parse(
+            "import Tabs from '@theme/Tabs';\n\n:::warning\n\nBack up first.\n\n:::\n\n{{#include footer.md}}\n\n```\n:::kept in code\n```",
+            true,
+        );
+
+        let content = &markdown.sections[0].content;
+        assert!(content.contains("Back up first."));
+        assert!(content.contains(":::kept in code"));
+        assert!(!content.contains("import"));
+        assert!(!content.contains(":::warning"));
+        assert!(!content.contains("{{#include"));
+    }
+
+    #[test]
+    fn reads_yaml_and_toml_front_matter() {
+        let yaml = parse(
+            "---\ntitle: Deploy\ntags: [ops, k8s]\n---\n# Steps\n\ntext",
+            false,
+        );
+        assert_eq!(yaml.front_matter["title"], MetadataValue::from("Deploy"));
+        assert_eq!(
+            yaml.front_matter["tags"],
+            MetadataValue::List(vec!["ops".into(), "k8s".into()])
+        );
+        assert_eq!(anchors(&yaml), ["steps"]);
+
+        let toml = parse(
+            "\u{feff}+++\r\ntitle = \"Deploy\"\r\nweight = 3\r\n+++\r\n\r\ntext",
+            false,
+        );
+        assert_eq!(toml.front_matter["title"], MetadataValue::from("Deploy"));
+        assert_eq!(toml.front_matter["weight"], MetadataValue::Integer(3));
+        assert_eq!(toml.sections[0].content.trim(), "text");
+
+        let empty = parse("---\n---\ntext", false);
+        assert!(empty.front_matter.is_empty());
+        assert_eq!(empty.sections[0].content.trim(), "text");
+    }
+
+    #[test]
+    fn keeps_blocks_that_are_not_front_matter() {
+        // Thematic breaks around a paragraph are YAML scalars, not mappings.
+        let markdown = parse("---\nFirst paragraph\n---\nSecond paragraph", false);
+        assert!(markdown.front_matter.is_empty());
+        let text = content(&markdown);
+        assert!(text.contains("First paragraph"));
+        assert!(text.contains("Second paragraph"));
+
+        let invalid = parse("+++\nnot = [toml\n+++\ntext", false);
+        assert!(invalid.front_matter.is_empty());
+        assert!(content(&invalid).contains("not = [toml"));
+
+        let unclosed = parse("---\ntitle: Deploy\n\ntext", false);
+        assert!(unclosed.front_matter.is_empty());
+        assert!(content(&unclosed).contains("title: Deploy"));
+    }
+
+    #[test]
+    fn only_strips_the_leading_statements_of_mdx() {
+        let mdx =
+            "import {\n  Tabs,\n  TabItem,\n} from '@theme/Tabs';\nexport const toc = [];\n\n\
+            # Modules\n\nimport the module first.\n\n    import os\n\nexport it after.";
+
+        let text = content(&parse(mdx, true));
+        assert!(!text.contains("Tabs"));
+        assert!(!text.contains("toc"));
+        for kept in ["import the module first.", "import os", "export it after."] {
+            assert!(text.contains(kept), "{kept} is missing from {text:?}");
+        }
+
+        let markdown = "import the module, then export the results.\n\n```python\nimport os\n```";
+        let text = content(&parse(markdown, false));
+        assert!(text.contains("import the module, then export the results."));
+        assert!(text.contains("import os"));
+    }
+
+    #[test]
+    fn code_blocks_end_with_their_own_fence() {
+        let markdown = parse(
+            "````md\n```\n:::note\n```\n[[kept]]\n````\n\n~~~\n```\n~~~\n\n:::tip\n\n[[Deploy]] text",
+            false,
+        );
+
+        let text = content(&markdown);
+        assert!(text.contains(":::note"));
+        assert!(text.contains("[[kept]]"));
+        assert!(!text.contains(":::tip"));
+        assert!(text.contains("Deploy text"));
+        assert!(!text.contains("[[Deploy]]"));
+    }
+
+    #[test]
+    fn detects_mdx_documents() {
+        let document = |id: &str| Document {
+            id: id.to_string(),
+            name: "Guide".to_string(),
+            content: "import Tabs from '@theme/Tabs';\n\nUse the tabs.".to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Default::default(),
+            chunk: None,
+        };
+
+        let mdx = split(document("docs/guide.MDX"));
+        assert_eq!(mdx[0].content, "Use the tabs.");
+
+        let markdown = split(document("docs/guide.md"));
+        assert!(markdown[0].content.contains("Tabs"));
+    }
+
+    #[test]
+    fn detects_markdown_by_extension_or_mime_type() {
+        let document = |id: &str, mime_type: Option<&str>| Document {
+            id: id.to_string(),
+            name: "Guide".to_string(),
+            content: String::new(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Provenance {
+                mime_type: mime_type.map(str::to_string),
+                ..Default::default()
+            },
+            chunk: None,
+        };
+
+        assert!(is_markdown(&document("docs/guide.Markdown", None)));
+        assert!(is_markdown(&document(
+            "1a2b3c",
+            Some("application/vnd.google-apps.document")
+        )));
+        assert!(!is_markdown(&document(
+            "docs/guide.txt",
+            Some("text/plain")
+        )));
+        assert!(!is_markdown(&document(
+            "1a2b3c",
+            Some("application/vnd.google-apps.presentation")
+        )));
+    }
+}
@@ -1,20 +1,244 @@
-use std::fmt::Debug;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
 
 use anyhow::Result;
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::document::Document;
+use crate::{document::Document, embedder, interals::AsyncTryFrom};
 
+use self::{
+    elasticsearch::ElasticsearchStore, postgres::PostgresStore, qdrant::QdrantStore,
+    sqlite::SqliteStore, weaviate::WeaviateStore,
+};
+
+pub mod elasticsearch;
+pub mod hybrid;
+pub mod postgres;
+pub mod qdrant;
+pub mod sqlite;
 pub mod weaviate;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum Config {
     Weaviate(weaviate::Config),
+    Elasticsearch(elasticsearch::Config),
+    Sqlite(sqlite::Config),
+    Qdrant(qdrant::Config),
+    Postgres(postgres::Config),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::Sqlite(sqlite::Config::default())
+    }
+}
+
+impl Config {
+    /// The embedder computing the vectors, for stores that do not vectorize documents
+    /// themselves.
+    pub fn embedder(&self) -> Option<&embedder::Config> {
+        match self {
+            Self::Sqlite(config) => Some(&config.embedder),
+            Self::Qdrant(config) => Some(&config.embedder),
+            Self::Postgres(config) => Some(&config.embedder),
+            Self::Weaviate(_) | Self::Elasticsearch(_) => None,
+        }
+    }
+}
+
+fn default_limit() -> usize {
+    5
+}
+
+/// A query on the document store. The text is set for each question while the other
+/// parameters usually come from the configuration of an agent.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Query {
+    #[serde(skip)]
+    pub text: String,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// Minimum similarity, between 0 and 1, of the returned documents.
+    #[serde(default)]
+    pub min_score: Option<f64>,
+    #[serde(default)]
+    pub filters: Filters,
+    /// Fields of the documents to return, all of them when empty.
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Merges a keyword search with the vector search instead of the default search of
+    /// the store.
+    #[serde(default)]
+    pub hybrid: Option<hybrid::Config>,
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            limit: default_limit(),
+            min_score: None,
+            filters: Filters::default(),
+            fields: vec![],
+            hybrid: None,
+        }
+    }
+}
+
+impl Query {
+    pub fn new(text: &str) -> Self {
+        Self::default().with_text(text)
+    }
+
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Filters {
+    /// Only return documents from these datasources.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    #[serde(default)]
+    pub modified_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoredDocument {
+    #[serde(flatten)]
+    pub document: Document,
+    /// Similarity with the query, higher is better.
+    pub score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+    /// The part of the content that matches the query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub highlight: Option<String>,
+}
+
+const HIGHLIGHT_LENGTH: usize = 300;
+
+/// Finds the sentence of `content` sharing the most words with `text`, for stores that
+/// do not highlight matches themselves.
+pub fn highlight(content: &str, text: &str) -> Option<String> {
+    let terms: HashSet<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| term.chars().count() > 2)
+        .map(str::to_lowercase)
+        .collect();
+
+    let (matches, sentence) = content
+        .split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .map(|sentence| {
+            let matches = sentence
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| terms.contains(&word.to_lowercase()))
+                .count();
+            (matches, sentence)
+        })
+        .max_by_key(|(matches, _)| *matches)?;
+
+    if matches == 0 {
+        return None;
+    }
+
+    Some(sentence.chars().take(HIGHLIGHT_LENGTH).collect())
+}
+
+/// Properties needed to build a document, returned whatever fields were requested.
+pub const REQUIRED_PROPERTIES: [&str; 2] = ["external_id", "name"];
+
+/// Builds a document from the properties returned by a store, keeping the requested
+/// fields, all of them when empty. Missing content is left empty.
+pub fn document_from_properties(mut value: Value, fields: &[String]) -> Result<Document> {
+    if let Value::Object(properties) = &mut value {
+        properties.retain(|name, value| {
+            !value.is_null()
+                && (fields.is_empty()
+                    || REQUIRED_PROPERTIES.contains(&name.as_str())
+                    || fields.contains(name))
+        });
+        properties
+            .entry("content")
+            .or_insert_with(|| Value::from(""));
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The text of a document given to embedders, for stores computing the vectors.
+pub fn embedding_text(document: &Document) -> String {
+    format!("{}\n{}", document.name, document.content)
+}
+
+/// Sends a request to the HTTP API of a store, returning its JSON body or why it failed.
+pub async fn send(request: RequestBuilder) -> Result<Value, String> {
+    let res = request.send().await.map_err(|e| e.to_string())?;
+
+    let status = res.status();
+    let body = res.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!("status {status} received: {body}"));
+    }
+
+    serde_json::from_str(&body).map_err(|e| e.to_string())
 }
 
 #[async_trait::async_trait]
 pub trait DocumentStore: Debug + Send + Sync {
     async fn store(&self, document: &Document) -> Result<()>;
-    async fn query(&self, query: &str) -> Result<Vec<Document>>;
+
+    /// Stores the documents, returning why each document that could not be stored
+    /// failed, by document id.
+    async fn store_batch(&self, documents: &[Document]) -> Result<HashMap<String, String>> {
+        let mut failures = HashMap::new();
+        for document in documents {
+            if let Err(e) = self.store(document).await {
+                failures.insert(document.id.clone(), e.to_string());
+            }
+        }
+
+        Ok(failures)
+    }
+
+    async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>>;
+    async fn delete(&self, document: &Document) -> Result<()>;
+    async fn list(&self, source: &str) -> Result<Vec<Document>>;
+
+    /// Applies the additive changes between the schema of the store and the expected
+    /// one, returning a description of each change.
+    async fn migrate(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for Box<dyn DocumentStore> {
+    type Error = anyhow::Error;
+
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
+        let store: Box<dyn DocumentStore> = match value {
+            Config::Weaviate(config) => Box::new(WeaviateStore::async_try_from(config).await?),
+            Config::Elasticsearch(config) => {
+                Box::new(ElasticsearchStore::async_try_from(config).await?)
+            }
+            Config::Sqlite(config) => Box::new(SqliteStore::open(config)?),
+            Config::Qdrant(config) => Box::new(QdrantStore::async_try_from(config).await?),
+            Config::Postgres(config) => Box::new(PostgresStore::async_try_from(config).await?),
+        };
+
+        Ok(store)
+    }
 }
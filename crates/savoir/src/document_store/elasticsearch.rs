@@ -0,0 +1,876 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::{info, warn};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::{document::Document, interals::AsyncTryFrom};
+
+use super::{
+    document_from_properties, highlight, hybrid, send, DocumentStore, Filters, Query,
+    ScoredDocument, REQUIRED_PROPERTIES,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot reach elasticsearch: {0}")]
+    Request(String),
+    #[error("cannot create elasticsearch index: {0}")]
+    CreateIndex(String),
+    #[error("cannot store elasticsearch document: {0}")]
+    StoreDocument(String),
+    #[error("cannot query elasticsearch documents: {0}")]
+    QueryDocument(String),
+    #[error("cannot delete elasticsearch document: {0}")]
+    DeleteDocument(String),
+    #[error("cannot list elasticsearch documents: {0}")]
+    ListDocuments(String),
+    #[error("cannot migrate elasticsearch index: {0}")]
+    MigrateIndex(String),
+}
+
+fn default_index() -> String {
+    "savoir".to_string()
+}
+
+fn default_num_candidates() -> usize {
+    100
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Flavor {
+    #[default]
+    Elasticsearch,
+    Opensearch,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    host: String,
+    #[serde(default = "default_index")]
+    index: String,
+    #[serde(default)]
+    flavor: Flavor,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    knn: Option<Knn>,
+}
+
+/// Dense vectors computed by the cluster itself, with an ingest pipeline filling the
+/// `embedding` field and a deployed model embedding the queries.
+#[derive(Deserialize, Debug)]
+pub struct Knn {
+    model_id: String,
+    dimensions: usize,
+    #[serde(default)]
+    pipeline: Option<String>,
+    #[serde(default = "default_num_candidates")]
+    num_candidates: usize,
+}
+
+#[derive(Debug)]
+pub struct ElasticsearchStore {
+    http: reqwest::Client,
+    host: String,
+    index: String,
+    flavor: Flavor,
+    credentials: Option<(String, Option<String>)>,
+    knn: Option<Knn>,
+}
+
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for ElasticsearchStore {
+    type Error = Error;
+
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
+        let store = Self {
+            http: reqwest::Client::new(),
+            host: value.host.trim_end_matches('/').to_string(),
+            index: value.index,
+            flavor: value.flavor,
+            credentials: value.username.map(|username| (username, value.password)),
+            knn: value.knn,
+        };
+        store.bootstrap().await?;
+
+        Ok(store)
+    }
+}
+
+const PAGE_SIZE: usize = 100;
+
+const VECTOR_FIELD: &str = "embedding";
+
+const HIGHLIGHT_LENGTH: usize = 300;
+
+/// The searches run by a query, both of them unless it is hybrid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Search {
+    Combined,
+    Keyword,
+    Vector,
+}
+
+/// BM25 scores are unbounded, they are mapped to 0..1 to be comparable with `min_score`.
+fn normalize(score: f64) -> f64 {
+    score / (1.0 + score)
+}
+
+fn filter(filters: &Filters) -> Vec<Value> {
+    let mut clauses = vec![];
+
+    if !filters.sources.is_empty() {
+        clauses.push(json!({ "terms": { "source": filters.sources } }));
+    }
+    if !filters.mime_types.is_empty() {
+        clauses.push(json!({ "terms": { "mime_type": filters.mime_types } }));
+    }
+    if let Some(modified_after) = filters.modified_after {
+        clauses.push(json!({ "range": { "modified_at": { "gt": modified_after.to_rfc3339() } } }));
+    }
+
+    clauses
+}
+
+impl ElasticsearchStore {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}/{}{path}", self.host, self.index));
+
+        match &self.credentials {
+            Some((username, password)) => request.basic_auth(username, password.as_ref()),
+            None => request,
+        }
+    }
+
+    // Writes go through the pipeline computing the embeddings, when there is one.
+    fn write(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.request(method, path);
+
+        match self.knn.as_ref().and_then(|knn| knn.pipeline.as_ref()) {
+            Some(pipeline) => request.query(&[("pipeline", pipeline)]),
+            None => request,
+        }
+    }
+
+    fn properties(&self) -> Map<String, Value> {
+        let mut properties = Map::new();
+
+        for name in ["external_id", "url", "source", "source_id", "mime_type"] {
+            properties.insert(name.into(), json!({ "type": "keyword" }));
+        }
+        for name in ["content_hash", "parent_id"] {
+            properties.insert(name.into(), json!({ "type": "keyword", "index": false }));
+        }
+        for name in ["name", "content", "author"] {
+            properties.insert(name.into(), json!({ "type": "text" }));
+        }
+        for name in ["created_at", "modified_at"] {
+            properties.insert(name.into(), json!({ "type": "date" }));
+        }
+        for name in ["position", "start_offset", "end_offset"] {
+            properties.insert(name.into(), json!({ "type": "integer" }));
+        }
+
+        // Metadata keys are free-form, they are kept in the source without being mapped.
+        properties.insert(
+            "metadata".into(),
+            json!({ "type": "object", "enabled": false }),
+        );
+
+        if let Some(knn) = &self.knn {
+            let vector = match self.flavor {
+                Flavor::Elasticsearch => json!({
+                    "type": "dense_vector",
+                    "dims": knn.dimensions,
+                    "index": true,
+                    "similarity": "cosine",
+                }),
+                Flavor::Opensearch => json!({
+                    "type": "knn_vector",
+                    "dimension": knn.dimensions,
+                    "method": { "name": "hnsw", "space_type": "cosinesimil", "engine": "lucene" },
+                }),
+            };
+            properties.insert(VECTOR_FIELD.into(), vector);
+        }
+
+        properties
+    }
+
+    /// Creates the index with its mapping when it does not exist.
+    async fn bootstrap(&self) -> Result<bool, Error> {
+        let res = self
+            .request(Method::HEAD, "")
+            .send()
+            .await
+            .map_err(|e| Error::Request(e.to_string()))?;
+
+        match res.status() {
+            StatusCode::NOT_FOUND => {}
+            status if status.is_success() => return Ok(false),
+            status => return Err(Error::Request(format!("status {status} received"))),
+        }
+
+        info!("Creating elasticsearch index {}", self.index);
+
+        let mut body = json!({ "mappings": { "properties": self.properties() } });
+        if self.knn.is_some() && self.flavor == Flavor::Opensearch {
+            body["settings"] = json!({ "index": { "knn": true } });
+        }
+
+        send(self.request(Method::PUT, "").json(&body))
+            .await
+            .map_err(Error::CreateIndex)?;
+
+        Ok(true)
+    }
+
+    fn search_body(&self, query: &Query, search: Search, size: usize) -> Value {
+        let text_query = json!({
+            "multi_match": { "query": query.text, "fields": ["name^2", "content"] }
+        });
+        let filter = filter(&query.filters);
+
+        let mut body = json!({
+            "size": size,
+            "highlight": {
+                "fields": {
+                    "content": { "fragment_size": HIGHLIGHT_LENGTH, "number_of_fragments": 1 }
+                }
+            },
+        });
+
+        if search != Search::Vector {
+            body["query"] = json!({ "bool": { "should": [text_query], "filter": filter } });
+        }
+
+        if !query.fields.is_empty() {
+            let fields: Vec<&str> = REQUIRED_PROPERTIES
+                .into_iter()
+                .chain(query.fields.iter().map(String::as_str))
+                .collect();
+            body["_source"] = json!(fields);
+        } else if self.knn.is_some() {
+            body["_source"] = json!({ "excludes": [VECTOR_FIELD] });
+        }
+
+        let Some(knn) = self.knn.as_ref().filter(|_| search != Search::Keyword) else {
+            return body;
+        };
+
+        match self.flavor {
+            Flavor::Elasticsearch => {
+                body["knn"] = json!({
+                    "field": VECTOR_FIELD,
+                    "k": size,
+                    "num_candidates": knn.num_candidates.max(size),
+                    "filter": filter,
+                    "query_vector_builder": {
+                        "text_embedding": { "model_id": knn.model_id, "model_text": query.text }
+                    },
+                });
+            }
+            Flavor::Opensearch => {
+                let neural = json!({
+                    "neural": {
+                        VECTOR_FIELD: {
+                            "query_text": query.text,
+                            "model_id": knn.model_id,
+                            "k": knn.num_candidates.max(size),
+                        }
+                    }
+                });
+
+                body["query"] = match search {
+                    Search::Vector => json!({ "bool": { "must": [neural], "filter": filter } }),
+                    _ => json!({
+                        "bool": { "should": [text_query, neural], "filter": filter }
+                    }),
+                };
+            }
+        }
+
+        body
+    }
+
+    async fn search(
+        &self,
+        query: &Query,
+        search: Search,
+        size: usize,
+    ) -> Result<Vec<ScoredDocument>> {
+        let res = send(
+            self.request(Method::POST, "/_search")
+                .json(&self.search_body(query, search, size)),
+        )
+        .await
+        .map_err(Error::QueryDocument)?;
+
+        let hits = match res.pointer("/hits/hits") {
+            Some(Value::Array(hits)) => hits.clone(),
+            _ => return Err(Error::QueryDocument(format!("unexpected response: {res}")).into()),
+        };
+
+        hits.into_iter()
+            .map(|hit| {
+                let document = document_from_properties(hit["_source"].clone(), &[])?;
+                let highlighted = hit
+                    .pointer("/highlight/content/0")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+
+                Ok(ScoredDocument {
+                    highlight: highlighted.or_else(|| highlight(&document.content, &query.text)),
+                    score: normalize(hit["_score"].as_f64().unwrap_or_default()),
+                    distance: None,
+                    document,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for ElasticsearchStore {
+    async fn store(&self, document: &Document) -> Result<()> {
+        // Indexing under the deterministic id replaces the previous version.
+        send(
+            self.write(Method::PUT, &format!("/_doc/{}", document.uuid()))
+                .json(document),
+        )
+        .await
+        .map_err(Error::StoreDocument)?;
+
+        Ok(())
+    }
+
+    async fn store_batch(&self, documents: &[Document]) -> Result<HashMap<String, String>> {
+        let mut body = String::new();
+        for document in documents {
+            body.push_str(&json!({ "index": { "_id": document.uuid() } }).to_string());
+            body.push('\n');
+            body.push_str(&serde_json::to_string(document)?);
+            body.push('\n');
+        }
+
+        let res = send(
+            self.write(Method::POST, "/_bulk")
+                .header("Content-Type", "application/x-ndjson")
+                .body(body),
+        )
+        .await
+        .map_err(Error::StoreDocument)?;
+
+        let items = res["items"].as_array().cloned().unwrap_or_default();
+        if items.len() != documents.len() {
+            warn!(
+                "{} results returned for {} documents",
+                items.len(),
+                documents.len()
+            );
+        }
+
+        // Items are returned in the order of the operations, documents without one may
+        // not have been stored.
+        let failures = documents
+            .iter()
+            .enumerate()
+            .filter_map(|(i, document)| {
+                let reason = match items.get(i) {
+                    Some(item) => {
+                        let error = item["index"].get("error")?;
+                        let reason = error["reason"].as_str().map(str::to_string);
+                        reason.unwrap_or_else(|| error.to_string())
+                    }
+                    None => "no result returned".to_string(),
+                };
+                Some((document.id.clone(), reason))
+            })
+            .collect();
+
+        Ok(failures)
+    }
+
+    /// Hybrid queries run the BM25 and the kNN searches separately and fuse their
+    /// rankings, and fall back to BM25 alone when the index has no vectors.
+    async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
+        let mut documents = match (&query.hybrid, &self.knn) {
+            (Some(config), Some(_)) => {
+                let candidates = hybrid::candidates(query);
+                let keyword = self.search(query, Search::Keyword, candidates).await?;
+                let vector = self.search(query, Search::Vector, candidates).await?;
+                return Ok(hybrid::fuse(config, query, keyword, vector));
+            }
+            (Some(_), None) => {
+                warn!(
+                    "Index {} has no vectors, hybrid queries only use BM25",
+                    self.index
+                );
+                self.search(query, Search::Keyword, query.limit).await?
+            }
+            (None, _) => self.search(query, Search::Combined, query.limit).await?,
+        };
+
+        if let Some(min_score) = query.min_score {
+            documents.retain(|document| document.score >= min_score);
+        }
+
+        Ok(documents)
+    }
+
+    async fn delete(&self, document: &Document) -> Result<()> {
+        let res = self
+            .request(Method::DELETE, &format!("/_doc/{}", document.uuid()))
+            .send()
+            .await
+            .map_err(|e| Error::DeleteDocument(e.to_string()))?;
+
+        let status = res.status();
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            let body = res.text().await.unwrap_or_default();
+            return Err(Error::DeleteDocument(format!("status {status} received: {body}")).into());
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, source: &str) -> Result<Vec<Document>> {
+        let mut documents = vec![];
+        let mut after: Option<Value> = None;
+
+        loop {
+            let mut body = json!({
+                "size": PAGE_SIZE,
+                "query": { "term": { "source": source } },
+                "sort": [{ "external_id": "asc" }],
+                "_source": { "excludes": [VECTOR_FIELD] },
+            });
+
+            if let Some(after) = &after {
+                body["search_after"] = after.clone();
+            }
+
+            let res = send(self.request(Method::POST, "/_search").json(&body))
+                .await
+                .map_err(Error::ListDocuments)?;
+
+            let hits = res
+                .pointer("/hits/hits")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+
+            after = hits.last().map(|hit| hit["sort"].clone());
+
+            let count = hits.len();
+            for hit in hits {
+                documents.push(document_from_properties(hit["_source"].clone(), &[])?);
+            }
+
+            if count < PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(documents)
+    }
+
+    async fn migrate(&self) -> Result<Vec<String>> {
+        if self.bootstrap().await? {
+            return Ok(vec![format!("created index {}", self.index)]);
+        }
+
+        let res = send(self.request(Method::GET, "/_mapping"))
+            .await
+            .map_err(Error::MigrateIndex)?;
+
+        // The response is keyed by the concrete index name, which differs for aliases.
+        let existing = res
+            .as_object()
+            .and_then(|indices| indices.values().next())
+            .and_then(|index| index.pointer("/mappings/properties"))
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default();
+
+        let missing: Map<String, Value> = self
+            .properties()
+            .into_iter()
+            .filter(|(name, _)| !existing.contains_key(name))
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let changes = missing
+            .keys()
+            .map(|name| format!("added property {name}"))
+            .collect();
+
+        send(
+            self.request(Method::PUT, "/_mapping")
+                .json(&json!({ "properties": missing })),
+        )
+        .await
+        .map_err(Error::MigrateIndex)?;
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{Method, StatusCode, Uri},
+        Json, Router,
+    };
+
+    use crate::document::Provenance;
+
+    use super::*;
+
+    /// The index of the stand-in server, and the bodies of the searches it received.
+    #[derive(Default)]
+    struct Elasticsearch {
+        mappings: Option<Map<String, Value>>,
+        documents: HashMap<String, Value>,
+        searches: Vec<Value>,
+        // Results left out of the responses to bulk requests.
+        dropped_items: usize,
+    }
+
+    type Shared = Arc<Mutex<Elasticsearch>>;
+
+    /// Scores documents with the number of words of the query they contain.
+    fn matches(document: &Value, text: &str) -> usize {
+        let content = format!("{} {}", document["name"], document["content"]).to_lowercase();
+        text.split_whitespace()
+            .filter(|word| content.contains(&word.to_lowercase()))
+            .count()
+    }
+
+    fn search(state: &mut Elasticsearch, body: &Value) -> Value {
+        let mut documents: Vec<Value> = state.documents.values().cloned().collect();
+
+        if let Some(source) = body.pointer("/query/term/source") {
+            documents.retain(|document| document["source"] == *source);
+            documents.sort_by_key(|document| document["external_id"].as_str().map(str::to_string));
+            if let Some(after) = body["search_after"].as_array() {
+                documents.retain(|document| document["external_id"].as_str() > after[0].as_str());
+            }
+            documents.truncate(body["size"].as_u64().unwrap() as usize);
+
+            let hits: Vec<_> = documents
+                .into_iter()
+                .map(|document| json!({ "_source": document, "sort": [document["external_id"]] }))
+                .collect();
+            return json!({ "hits": { "hits": hits } });
+        }
+
+        let text = body
+            .pointer("/query/bool/should/0/multi_match/query")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if let Some(sources) = body.pointer("/query/bool/filter/0/terms/source") {
+            let sources = sources.as_array().unwrap();
+            documents.retain(|document| sources.contains(&document["source"]));
+        }
+        documents.retain(|document| matches(document, text) > 0);
+        documents.sort_by_key(|document| std::cmp::Reverse(matches(document, text)));
+
+        let hits: Vec<_> = documents
+            .into_iter()
+            .map(|document| {
+                let mut source = document.clone();
+                if let Some(fields) = body["_source"].as_array() {
+                    source
+                        .as_object_mut()
+                        .unwrap()
+                        .retain(|name, _| fields.contains(&Value::from(name.as_str())));
+                }
+                json!({
+                    "_score": matches(&document, text) as f64,
+                    "_source": source,
+                    "highlight": { "content": [format!("<em>{}</em>", document["content"].as_str().unwrap())] },
+                })
+            })
+            .collect();
+        json!({ "hits": { "hits": hits } })
+    }
+
+    async fn handle(
+        State(state): State<Shared>,
+        method: Method,
+        uri: Uri,
+        body: Bytes,
+    ) -> (StatusCode, Json<Value>) {
+        let mut state = state.lock().unwrap();
+        let path = uri.path().trim_start_matches("/savoir").to_string();
+        let json: Value = serde_json::from_slice(&body).unwrap_or_default();
+
+        let not_found = (StatusCode::NOT_FOUND, Json(json!({ "found": false })));
+        let ok = |body: Value| (StatusCode::OK, Json(body));
+
+        match (method.as_str(), path.as_str()) {
+            ("HEAD", "") if state.mappings.is_none() => not_found,
+            ("HEAD", "") => ok(json!({})),
+            ("PUT", "") => {
+                let properties = json.pointer("/mappings/properties").unwrap();
+                state.mappings = Some(properties.as_object().unwrap().clone());
+                ok(json!({ "acknowledged": true }))
+            }
+            ("GET", "/_mapping") => ok(json!({
+                "savoir-000001": { "mappings": { "properties": state.mappings } }
+            })),
+            ("PUT", "/_mapping") => {
+                let properties = json["properties"].as_object().unwrap().clone();
+                state.mappings.as_mut().unwrap().extend(properties);
+                ok(json!({ "acknowledged": true }))
+            }
+            ("PUT", path) if path.starts_with("/_doc/") => {
+                state.documents.insert(path[6..].to_string(), json);
+                ok(json!({ "result": "created" }))
+            }
+            ("DELETE", path) if path.starts_with("/_doc/") => {
+                match state.documents.remove(&path[6..]) {
+                    Some(_) => ok(json!({ "result": "deleted" })),
+                    None => not_found,
+                }
+            }
+            ("POST", "/_bulk") => {
+                let lines: Vec<Value> = std::str::from_utf8(&body)
+                    .unwrap()
+                    .lines()
+                    .map(|line| serde_json::from_str(line).unwrap())
+                    .collect();
+                let mut items = vec![];
+                for operation in lines.chunks(2) {
+                    let id = operation[0]["index"]["_id"].as_str().unwrap().to_string();
+                    let item = match operation[1]["content"].as_str() {
+                        Some("") => json!({ "index": { "_id": id, "status": 400, "error": {
+                            "type": "mapper_parsing_exception", "reason": "empty content"
+                        } } }),
+                        _ => {
+                            state.documents.insert(id.clone(), operation[1].clone());
+                            json!({ "index": { "_id": id, "status": 201 } })
+                        }
+                    };
+                    items.push(item);
+                }
+                let kept = items.len().saturating_sub(state.dropped_items);
+                items.truncate(kept);
+                ok(json!({ "errors": true, "items": items }))
+            }
+            ("POST", "/_search") => {
+                let response = search(&mut state, &json);
+                state.searches.push(json);
+                ok(response)
+            }
+            _ => not_found,
+        }
+    }
+
+    fn serve() -> (String, Shared) {
+        let state = Shared::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new().fallback(handle).with_state(state.clone());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (host, state)
+    }
+
+    async fn store(host: &str) -> ElasticsearchStore {
+        let config = format!("{{ host: '{host}/' }}");
+        ElasticsearchStore::async_try_from(serde_yaml::from_str(&config).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn document(id: &str, source: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            name: id.to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Provenance {
+                source: Some(source.to_string()),
+                ..Default::default()
+            },
+            chunk: None,
+        }
+    }
+
+    fn ids(documents: Vec<Document>) -> Vec<String> {
+        documents.into_iter().map(|document| document.id).collect()
+    }
+
+    #[tokio::test]
+    async fn creates_the_index_and_stores_documents() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+
+        let mappings = state.lock().unwrap().mappings.clone().unwrap();
+        assert_eq!(mappings["source"], json!({ "type": "keyword" }));
+        assert!(!mappings.contains_key(VECTOR_FIELD));
+
+        store
+            .store(&document("onboarding", "docs", "Read the handbook."))
+            .await
+            .unwrap();
+        let documents = [
+            document("deploy", "docs", "Run the pipeline."),
+            document("holidays", "hr", "Book them early."),
+        ];
+        assert!(store.store_batch(&documents).await.unwrap().is_empty());
+        // Storing a document again replaces it.
+        store.store(&documents[0]).await.unwrap();
+
+        assert_eq!(state.lock().unwrap().documents.len(), 3);
+        assert_eq!(
+            ids(store.list("docs").await.unwrap()),
+            ["deploy", "onboarding"]
+        );
+        assert_eq!(ids(store.list("hr").await.unwrap()), ["holidays"]);
+    }
+
+    #[tokio::test]
+    async fn reports_failed_and_missing_bulk_results() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        state.lock().unwrap().dropped_items = 1;
+
+        let documents = [
+            document("onboarding", "docs", "Read the handbook."),
+            document("empty", "docs", ""),
+            document("deploy", "docs", "Run the pipeline."),
+        ];
+        let failures = store.store_batch(&documents).await.unwrap();
+
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures["empty"], "empty content");
+        assert_eq!(failures["deploy"], "no result returned");
+        assert_eq!(
+            ids(store.list("docs").await.unwrap()),
+            ["deploy", "onboarding"]
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_with_filters_and_normalized_scores() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        let documents = [
+            document("onboarding", "docs", "Read the handbook on your first day."),
+            document("deploy", "docs", "Deploy on your first day."),
+            document("holidays", "hr", "Book your first holidays early."),
+        ];
+        store.store_batch(&documents).await.unwrap();
+
+        let mut query = Query::new("deploy first day");
+        query.filters.sources = vec!["docs".to_string()];
+        let found = store.query(&query).await.unwrap();
+
+        let ids: Vec<_> = found.iter().map(|d| d.document.id.as_str()).collect();
+        assert_eq!(ids, ["deploy", "onboarding"]);
+        assert_eq!(found[0].score, 0.75);
+        assert_eq!(
+            found[0].highlight.as_deref(),
+            Some("<em>Deploy on your first day.</em>")
+        );
+
+        query.min_score = Some(0.7);
+        query.fields = vec!["source".to_string()];
+        let found = store.query(&query).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].document.content, "");
+        assert_eq!(found[0].document.provenance.source.as_deref(), Some("docs"));
+
+        let search = state.lock().unwrap().searches.pop().unwrap();
+        assert_eq!(search["size"], 5);
+        assert_eq!(search["_source"], json!(["external_id", "name", "source"]));
+        assert_eq!(
+            search.pointer("/query/bool/filter/0"),
+            Some(&json!({ "terms": { "source": ["docs"] } }))
+        );
+    }
+
+    #[tokio::test]
+    async fn deletes_documents_whether_they_exist_or_not() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        let onboarding = document("onboarding", "docs", "Read the handbook.");
+        store.store(&onboarding).await.unwrap();
+
+        store.delete(&onboarding).await.unwrap();
+        store.delete(&onboarding).await.unwrap();
+
+        assert!(state.lock().unwrap().documents.is_empty());
+    }
+
+    #[tokio::test]
+    async fn lists_every_page() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        let documents: Vec<_> = (0..PAGE_SIZE + 20)
+            .map(|i| document(&format!("{i:03}"), "docs", "text"))
+            .collect();
+        store.store_batch(&documents).await.unwrap();
+
+        let listed = ids(store.list("docs").await.unwrap());
+
+        assert_eq!(listed, ids(documents));
+        let searches = &state.lock().unwrap().searches;
+        assert_eq!(searches.len(), 2);
+        assert_eq!(
+            searches[1]["search_after"],
+            json!([format!("{:03}", PAGE_SIZE - 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn migrate_adds_the_missing_properties() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        state
+            .lock()
+            .unwrap()
+            .mappings
+            .as_mut()
+            .unwrap()
+            .retain(|name, _| name != "author" && name != "metadata");
+
+        let mut changes = store.migrate().await.unwrap();
+        changes.sort();
+
+        assert_eq!(
+            changes,
+            ["added property author", "added property metadata"]
+        );
+        assert!(store.migrate().await.unwrap().is_empty());
+        let mappings = state.lock().unwrap().mappings.clone().unwrap();
+        assert_eq!(mappings["author"], json!({ "type": "text" }));
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::{Query, ScoredDocument};
+
+// Number of candidates retrieved by each of the keyword and vector searches, for each
+// returned document.
+const CANDIDATES_FACTOR: usize = 4;
+
+// Rank constant of the reciprocal rank fusion, the one of the original paper and of
+// Weaviate's ranked fusion.
+pub const RRF_K: f64 = 60.0;
+
+fn default_alpha() -> f64 {
+    0.5
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Fusion {
+    /// Reciprocal rank fusion, only the positions of the documents in each search count.
+    #[default]
+    Rrf,
+    /// Sum of the scores of each search, scaled to 0..1 within the search.
+    Weighted,
+}
+
+/// Runs a keyword search and a vector search, and merges their results.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    #[serde(default)]
+    pub fusion: Fusion,
+    /// Weight of the vector search, from 0 for keywords only to 1 for vectors only.
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fusion: Fusion::default(),
+            alpha: default_alpha(),
+        }
+    }
+}
+
+/// Number of documents each search retrieves for the fusion.
+pub fn candidates(query: &Query) -> usize {
+    query.limit * CANDIDATES_FACTOR
+}
+
+/// Merges two rankings of ids, best first with the score given by their search, into
+/// scores between 0 and 1, best first.
+pub fn fuse_scores(
+    config: &Config,
+    keyword: &[(String, f64)],
+    vector: &[(String, f64)],
+) -> Vec<(String, f64)> {
+    let alpha = config.alpha.clamp(0.0, 1.0);
+    let mut scores: Vec<(String, f64)> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for (ranking, weight) in [(keyword, 1.0 - alpha), (vector, alpha)] {
+        let (min, max) = ranking
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(min, max), (_, score)| {
+                (min.min(*score), max.max(*score))
+            });
+
+        for (rank, (id, score)) in ranking.iter().enumerate() {
+            // Scaled so that a document ranked first by both searches scores 1.
+            let contribution = match config.fusion {
+                Fusion::Rrf => (RRF_K + 1.0) / (RRF_K + rank as f64 + 1.0),
+                Fusion::Weighted if max > min => (score - min) / (max - min),
+                Fusion::Weighted => 1.0,
+            };
+
+            let position = *positions.entry(id.clone()).or_insert_with(|| {
+                scores.push((id.clone(), 0.0));
+                scores.len() - 1
+            });
+            scores[position].1 += weight * contribution;
+        }
+    }
+
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scores
+}
+
+/// Merges the documents found by a keyword search and by a vector search, keeping the
+/// highlight of the keyword search and the distance of the vector search.
+pub fn fuse(
+    config: &Config,
+    query: &Query,
+    keyword: Vec<ScoredDocument>,
+    vector: Vec<ScoredDocument>,
+) -> Vec<ScoredDocument> {
+    let ranking = |documents: &[ScoredDocument]| -> Vec<(String, f64)> {
+        documents
+            .iter()
+            .map(|document| (document.document.uuid().to_string(), document.score))
+            .collect()
+    };
+    let scores = fuse_scores(config, &ranking(&keyword), &ranking(&vector));
+
+    let mut documents: HashMap<String, ScoredDocument> = HashMap::new();
+    for document in keyword.into_iter().chain(vector) {
+        let uuid = document.document.uuid().to_string();
+        match documents.get_mut(&uuid) {
+            Some(found) => found.distance = found.distance.or(document.distance),
+            None => {
+                documents.insert(uuid, document);
+            }
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter(|(_, score)| query.min_score.is_none_or(|min_score| *score >= min_score))
+        .take(query.limit)
+        .filter_map(|(uuid, score)| {
+            let mut document = documents.remove(&uuid)?;
+            document.score = score;
+            Some(document)
+        })
+        .collect()
+}
+
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Ranks documents with BM25 on their name and content, for stores without a keyword
+/// index. The statistics of the terms come from the given documents only, usually the
+/// candidates of a vector search.
+pub fn keyword_search(documents: &[ScoredDocument], text: &str) -> Vec<ScoredDocument> {
+    let query_terms = terms(text);
+
+    // Names count twice, like the boost of the keyword searches of the other stores.
+    let texts: Vec<Vec<String>> = documents
+        .iter()
+        .map(|document| {
+            let name = terms(&document.document.name);
+            let mut words = name.clone();
+            words.extend(name);
+            words.extend(terms(&document.document.content));
+            words
+        })
+        .collect();
+
+    let count = texts.len() as f64;
+    let average_length = texts.iter().map(Vec::len).sum::<usize>() as f64 / count.max(1.0);
+
+    let mut scored: Vec<ScoredDocument> = documents
+        .iter()
+        .zip(&texts)
+        .filter_map(|(document, text)| {
+            let length = text.len() as f64;
+            let score: f64 = query_terms
+                .iter()
+                .map(|term| {
+                    let frequency = text.iter().filter(|t| *t == term).count() as f64;
+                    if frequency == 0.0 {
+                        return 0.0;
+                    }
+
+                    let matching = texts.iter().filter(|text| text.contains(term)).count() as f64;
+                    let idf = (1.0 + (count - matching + 0.5) / (matching + 0.5)).ln();
+                    idf * frequency * (BM25_K1 + 1.0)
+                        / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * length / average_length))
+                })
+                .sum();
+
+            (score > 0.0).then(|| ScoredDocument {
+                score: score / (1.0 + score),
+                ..document.clone()
+            })
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Document;
+
+    use super::*;
+
+    fn ranking(ids: &[(&str, f64)]) -> Vec<(String, f64)> {
+        ids.iter()
+            .map(|(id, score)| (id.to_string(), *score))
+            .collect()
+    }
+
+    fn ids(scores: &[(String, f64)]) -> Vec<&str> {
+        scores.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    fn score(scores: &[(String, f64)], id: &str) -> f64 {
+        scores.iter().find(|(found, _)| found == id).unwrap().1
+    }
+
+    fn config(fusion: Fusion, alpha: f64) -> Config {
+        Config { fusion, alpha }
+    }
+
+    fn document(id: &str, name: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            name: name.to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Default::default(),
+            chunk: None,
+        }
+    }
+
+    fn scored(document: Document, score: f64) -> ScoredDocument {
+        ScoredDocument {
+            document,
+            score,
+            distance: None,
+            highlight: None,
+        }
+    }
+
+    #[test]
+    fn rrf_counts_positions_only() {
+        let keyword = ranking(&[("a", 100.0), ("b", 1.0)]);
+        let vector = ranking(&[("b", 0.9), ("c", 0.8)]);
+
+        let scores = fuse_scores(&config(Fusion::Rrf, 0.5), &keyword, &vector);
+
+        assert_eq!(ids(&scores), ["b", "a", "c"]);
+        let second = (RRF_K + 1.0) / (RRF_K + 2.0);
+        assert!((scores[0].1 - 0.5 * (1.0 + second)).abs() < 1e-9);
+        assert!((scores[1].1 - 0.5).abs() < 1e-9);
+        assert!((scores[2].1 - 0.5 * second).abs() < 1e-9);
+    }
+
+    #[test]
+    fn weighted_fusion_scales_the_scores_of_each_search() {
+        let keyword = ranking(&[("a", 100.0), ("b", 1.0), ("d", 0.0)]);
+        let vector = ranking(&[("b", 0.9), ("c", 0.8)]);
+
+        let scores = fuse_scores(&config(Fusion::Weighted, 0.5), &keyword, &vector);
+
+        assert_eq!(ids(&scores), ["b", "a", "d", "c"]);
+        assert!((score(&scores, "b") - 0.505).abs() < 1e-9);
+        assert!((score(&scores, "a") - 0.5).abs() < 1e-9);
+        assert_eq!(score(&scores, "d"), 0.0);
+        assert_eq!(score(&scores, "c"), 0.0);
+
+        // Far behind a in the keyword search, b falls behind it, unlike with RRF.
+        let vector = ranking(&[("c", 0.9), ("b", 0.85), ("e", 0.1)]);
+        let rrf = fuse_scores(&config(Fusion::Rrf, 0.5), &keyword, &vector);
+        let weighted = fuse_scores(&config(Fusion::Weighted, 0.5), &keyword, &vector);
+        assert_eq!(ids(&rrf)[0], "b");
+        assert_eq!(ids(&weighted)[..3], ["a", "c", "b"]);
+        assert!((score(&weighted, "b") - 0.47375).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_single_document_scores_fully() {
+        let scores = fuse_scores(
+            &config(Fusion::Weighted, 0.5),
+            &ranking(&[("a", 3.0)]),
+            &ranking(&[("a", 0.2)]),
+        );
+
+        assert_eq!(scores, ranking(&[("a", 1.0)]));
+    }
+
+    #[test]
+    fn alpha_selects_one_of_the_searches() {
+        let keyword = ranking(&[("a", 3.0), ("b", 2.0), ("c", 1.0)]);
+        let vector = ranking(&[("c", 0.9), ("b", 0.8), ("d", 0.7)]);
+
+        for fusion in [Fusion::Rrf, Fusion::Weighted] {
+            let scores = fuse_scores(&config(fusion, 0.0), &keyword, &vector);
+            assert_eq!(ids(&scores)[..2], ["a", "b"], "{fusion:?}");
+            assert_eq!(score(&scores, "d"), 0.0, "{fusion:?}");
+
+            let scores = fuse_scores(&config(fusion, 1.0), &keyword, &vector);
+            assert_eq!(ids(&scores)[..2], ["c", "b"], "{fusion:?}");
+            assert_eq!(score(&scores, "a"), 0.0, "{fusion:?}");
+
+            // Out of range weights are clamped.
+            assert_eq!(
+                fuse_scores(&config(fusion, 2.0), &keyword, &vector),
+                fuse_scores(&config(fusion, 1.0), &keyword, &vector)
+            );
+        }
+    }
+
+    #[test]
+    fn fuse_merges_documents_found_by_both_searches() {
+        let both = document("both", "Both", "found twice");
+        let keyword = vec![
+            ScoredDocument {
+                highlight: Some("found".to_string()),
+                ..scored(both.clone(), 4.0)
+            },
+            scored(document("keyword", "Keyword", "words"), 2.0),
+        ];
+        let vector = vec![
+            ScoredDocument {
+                distance: Some(0.1),
+                ..scored(both, 0.9)
+            },
+            scored(document("vector", "Vector", "meaning"), 0.8),
+        ];
+        let query = Query {
+            limit: 10,
+            ..Default::default()
+        };
+
+        let documents = fuse(&Config::default(), &query, keyword, vector);
+
+        let ids: Vec<_> = documents.iter().map(|d| d.document.id.as_str()).collect();
+        assert_eq!(ids, ["both", "keyword", "vector"]);
+        assert_eq!(documents[0].score, 1.0);
+        assert_eq!(documents[0].highlight.as_deref(), Some("found"));
+        assert_eq!(documents[0].distance, Some(0.1));
+    }
+
+    #[test]
+    fn fuse_drops_documents_under_the_min_score() {
+        let keyword = vec![
+            scored(document("a", "A", "a"), 2.0),
+            scored(document("b", "B", "b"), 1.0),
+        ];
+        let vector = vec![scored(document("a", "A", "a"), 0.9)];
+
+        let query = |min_score, limit| Query {
+            limit,
+            min_score,
+            ..Default::default()
+        };
+        let ids = |query: Query| -> Vec<String> {
+            fuse(
+                &config(Fusion::Weighted, 0.5),
+                &query,
+                keyword.clone(),
+                vector.clone(),
+            )
+            .into_iter()
+            .map(|document| document.document.id)
+            .collect()
+        };
+
+        // b is last of the keyword search and missing from the vector search.
+        assert_eq!(ids(query(None, 10)), ["a", "b"]);
+        assert_eq!(ids(query(Some(0.0), 10)), ["a", "b"]);
+        assert_eq!(ids(query(Some(0.5), 10)), ["a"]);
+        assert_eq!(ids(query(None, 1)), ["a"]);
+    }
+
+    #[test]
+    fn keyword_search_ranks_matching_documents() {
+        let documents = vec![
+            scored(document("unrelated", "Holidays", "Book them early."), 0.9),
+            scored(
+                document("content", "Setup", "Install the deploy tool, then deploy."),
+                0.8,
+            ),
+            scored(document("name", "Deploy", "Run the pipeline."), 0.7),
+        ];
+
+        let found = keyword_search(&documents, "How to DEPLOY?");
+
+        let ids: Vec<_> = found.iter().map(|d| d.document.id.as_str()).collect();
+        assert_eq!(ids, ["name", "content"]);
+        assert!(found
+            .iter()
+            .all(|document| document.score > 0.0 && document.score < 1.0));
+        assert!(keyword_search(&documents, "kubernetes").is_empty());
+        assert!(keyword_search(&[], "deploy").is_empty());
+    }
+}
@@ -0,0 +1,632 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use log::info;
+use serde::Deserialize;
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    postgres::PgPoolOptions,
+    PgPool, Row,
+};
+use thiserror::Error;
+
+use crate::{
+    document::Document,
+    embedder::{self, Embedder},
+    interals::AsyncTryFrom,
+};
+
+use super::{
+    document_from_properties, embedding_text, highlight, hybrid, DocumentStore, Query,
+    ScoredDocument,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot connect to postgres: {0}")]
+    Connect(String),
+    #[error("cannot create embedder: {0}")]
+    CreateEmbedder(String),
+    #[error("cannot migrate postgres database: {0}")]
+    Migrate(String),
+}
+
+fn default_max_connections() -> u32 {
+    5
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    url: String,
+    #[serde(default = "default_max_connections")]
+    max_connections: u32,
+    #[serde(default)]
+    pub embedder: embedder::Config,
+}
+
+#[derive(Debug)]
+pub struct PostgresStore {
+    pool: PgPool,
+    embedder: Box<dyn Embedder>,
+}
+
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for PostgresStore {
+    type Error = Error;
+
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
+        let pool = PgPoolOptions::new()
+            .max_connections(value.max_connections)
+            .connect(&value.url)
+            .await
+            .map_err(|e| Error::Connect(e.to_string()))?;
+
+        let store = Self {
+            pool,
+            embedder: <Box<dyn Embedder>>::try_from(value.embedder)
+                .map_err(|e| Error::CreateEmbedder(e.to_string()))?,
+        };
+
+        for migration in store.run_migrations().await? {
+            info!("Applied postgres migration {migration}");
+        }
+
+        Ok(store)
+    }
+}
+
+/// Formats a vector as a pgvector literal, cast by the queries.
+fn vector_literal(vector: &[f32]) -> String {
+    let values: Vec<String> = vector.iter().map(f32::to_string).collect();
+    format!("[{}]", values.join(","))
+}
+
+// The filters of a query, repeated in each search so that the vector search can
+// scan the index of its dimensions.
+const FILTER: &str = "(cardinality($4::text[]) = 0 OR source = ANY($4))
+    AND (cardinality($5::text[]) = 0 OR mime_type = ANY($5))
+    AND ($6::timestamptz IS NULL OR modified_at > $6)";
+
+/// The query gathering the candidates of both searches, with the vector `$1`, the text
+/// `$2`, the number of candidates `$3`, and the sources, MIME types and modification
+/// date `$4` to `$6` of the filters.
+fn query_sql(dimensions: usize) -> String {
+    format!(
+        "WITH q AS (
+            SELECT $1::text::vector({dimensions}) AS embedding,
+                websearch_to_tsquery('simple', $2) AS text
+        ),
+        candidates AS (
+            (SELECT uuid FROM savoir_documents
+                WHERE vector_dims(embedding) = {dimensions} AND {FILTER}
+                ORDER BY embedding::vector({dimensions}) <=> $1::text::vector({dimensions})
+                LIMIT $3)
+            UNION
+            (SELECT uuid FROM savoir_documents
+                WHERE search @@ websearch_to_tsquery('simple', $2) AND {FILTER}
+                ORDER BY ts_rank_cd(search, websearch_to_tsquery('simple', $2), 32) DESC
+                LIMIT $3)
+        )
+        SELECT d.document,
+            CASE WHEN vector_dims(d.embedding) = {dimensions}
+                THEN 1 - (d.embedding::vector({dimensions}) <=> q.embedding)
+                ELSE 0
+            END::float8 AS similarity,
+            ts_rank_cd(d.search, q.text, 32)::float8 AS rank
+        FROM savoir_documents d JOIN candidates USING (uuid), q"
+    )
+}
+
+impl PostgresStore {
+    /// Applies the pending migrations and creates the vector index for the configured
+    /// dimensions, returning the description of the applied migrations.
+    async fn run_migrations(&self) -> Result<Vec<String>, Error> {
+        let mut connection = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| Error::Connect(e.to_string()))?;
+
+        connection
+            .ensure_migrations_table()
+            .await
+            .map_err(|e| Error::Migrate(e.to_string()))?;
+        let applied: HashSet<i64> = connection
+            .list_applied_migrations()
+            .await
+            .map_err(|e| Error::Migrate(e.to_string()))?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect();
+
+        drop(connection);
+
+        MIGRATOR
+            .run(&self.pool)
+            .await
+            .map_err(|e| Error::Migrate(e.to_string()))?;
+
+        // The partial index only covers the embeddings of the configured size, which
+        // the queries select.
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS savoir_documents_embedding_{dimensions}
+             ON savoir_documents USING hnsw ((embedding::vector({dimensions})) vector_cosine_ops)
+             WHERE vector_dims(embedding) = {dimensions}",
+            dimensions = self.embedder.dimensions()
+        ))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| Error::Migrate(e.to_string()))?;
+
+        Ok(MIGRATOR
+            .iter()
+            .filter(|migration| !applied.contains(&migration.version))
+            .map(|migration| migration.description.to_string())
+            .collect())
+    }
+
+    /// The embedder that computed the stored vectors.
+    async fn indexed_embedder(&self) -> Result<Option<String>> {
+        let embedder =
+            sqlx::query_scalar("SELECT value FROM savoir_settings WHERE key = 'embedder'")
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(embedder)
+    }
+
+    /// The embedder that computed the stored vectors, failing when it is not the
+    /// configured one until the store is migrated.
+    async fn check_indexed_embedder(&self) -> Result<Option<String>> {
+        let indexed = self.indexed_embedder().await?;
+        if let Some(indexed) = &indexed {
+            self.embedder.check(indexed)?;
+        }
+
+        Ok(indexed)
+    }
+
+    async fn set_indexed_embedder(&self) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO savoir_settings (key, value) VALUES ('embedder', $1)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        )
+        .bind(self.embedder.id())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for PostgresStore {
+    async fn store(&self, document: &Document) -> Result<()> {
+        self.store_batch(std::slice::from_ref(document)).await?;
+
+        Ok(())
+    }
+
+    /// Stores the batch with a single statement, which fails as a whole.
+    async fn store_batch(&self, documents: &[Document]) -> Result<HashMap<String, String>> {
+        let indexed = self.check_indexed_embedder().await?;
+
+        let mut uuids = vec![];
+        let mut external_ids = vec![];
+        let mut parent_ids = vec![];
+        let mut sources = vec![];
+        let mut mime_types = vec![];
+        let mut modified_at = vec![];
+        let mut contents = vec![];
+        let texts: Vec<String> = documents.iter().map(embedding_text).collect();
+        let embeddings: Vec<String> = self
+            .embedder
+            .embed(&texts)
+            .await?
+            .iter()
+            .map(|vector| vector_literal(vector))
+            .collect();
+
+        for document in documents {
+            uuids.push(document.uuid().to_string());
+            external_ids.push(document.id.clone());
+            parent_ids.push(document.chunk.as_ref().map(|chunk| chunk.parent_id.clone()));
+            sources.push(document.provenance.source.clone());
+            mime_types.push(document.provenance.mime_type.clone());
+            modified_at.push(document.provenance.modified_at);
+            contents.push(serde_json::to_string(document)?);
+        }
+
+        sqlx::query(
+            "INSERT INTO savoir_documents
+                (uuid, external_id, parent_id, source, mime_type, modified_at, document, embedding)
+             SELECT uuid::uuid, external_id, parent_id, source, mime_type, modified_at,
+                document::jsonb, embedding::vector
+             FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[],
+                $6::timestamptz[], $7::text[], $8::text[])
+                AS t (uuid, external_id, parent_id, source, mime_type, modified_at, document, embedding)
+             ON CONFLICT (uuid) DO UPDATE SET
+                external_id = excluded.external_id,
+                parent_id = excluded.parent_id,
+                source = excluded.source,
+                mime_type = excluded.mime_type,
+                modified_at = excluded.modified_at,
+                document = excluded.document,
+                embedding = excluded.embedding",
+        )
+        .bind(uuids)
+        .bind(external_ids)
+        .bind(parent_ids)
+        .bind(sources)
+        .bind(mime_types)
+        .bind(modified_at)
+        .bind(contents)
+        .bind(embeddings)
+        .execute(&self.pool)
+        .await?;
+
+        if indexed.is_none() {
+            self.set_indexed_embedder().await?;
+        }
+
+        Ok(HashMap::new())
+    }
+
+    /// Gathers candidates from the vector index and from the full-text index, and
+    /// scores them with the mean of their cosine similarity with the query and of their
+    /// full-text rank, both between 0 and 1, or with the fusion of both rankings for
+    /// hybrid queries.
+    async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
+        self.check_indexed_embedder().await?;
+
+        let vector = self.embedder.embed_one(&query.text).await?;
+
+        let candidates = hybrid::candidates(query);
+        let sql = query_sql(self.embedder.dimensions());
+
+        let rows = sqlx::query(&sql)
+            .bind(vector_literal(&vector))
+            .bind(&query.text)
+            .bind(candidates as i64)
+            .bind(&query.filters.sources)
+            .bind(&query.filters.mime_types)
+            .bind(query.filters.modified_after)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut found = vec![];
+        for row in rows {
+            let similarity: f64 = row.try_get("similarity")?;
+            let rank: f64 = row.try_get("rank")?;
+            let document = document_from_properties(row.try_get("document")?, &query.fields)?;
+
+            found.push((
+                ScoredDocument {
+                    highlight: highlight(&document.content, &query.text),
+                    distance: Some(1.0 - similarity),
+                    score: 0.0,
+                    document,
+                },
+                similarity,
+                rank,
+            ));
+        }
+
+        // Each search ranks the candidates it gathered, the others come after them.
+        if let Some(config) = &query.hybrid {
+            let ranking = |mut ranking: Vec<ScoredDocument>| {
+                ranking.sort_by(|a, b| b.score.total_cmp(&a.score));
+                ranking.truncate(candidates);
+                ranking
+            };
+
+            let keyword = ranking(
+                found
+                    .iter()
+                    .filter(|(_, _, rank)| *rank > 0.0)
+                    .map(|(document, _, rank)| ScoredDocument {
+                        score: *rank,
+                        ..document.clone()
+                    })
+                    .collect(),
+            );
+            let vector = ranking(
+                found
+                    .iter()
+                    .map(|(document, similarity, _)| ScoredDocument {
+                        score: *similarity,
+                        ..document.clone()
+                    })
+                    .collect(),
+            );
+            return Ok(hybrid::fuse(config, query, keyword, vector));
+        }
+
+        let mut documents = vec![];
+        for (mut document, similarity, rank) in found {
+            // The rank is normalized by the database to rank / (rank + 1).
+            document.score = (similarity.max(0.0) + rank) / 2.0;
+            if query
+                .min_score
+                .is_some_and(|min_score| document.score < min_score)
+            {
+                continue;
+            }
+
+            documents.push(document);
+        }
+
+        documents.sort_by(|a, b| b.score.total_cmp(&a.score));
+        documents.truncate(query.limit);
+
+        Ok(documents)
+    }
+
+    async fn delete(&self, document: &Document) -> Result<()> {
+        sqlx::query("DELETE FROM savoir_documents WHERE uuid = $1::text::uuid")
+            .bind(document.uuid().to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, source: &str) -> Result<Vec<Document>> {
+        let rows = sqlx::query("SELECT document FROM savoir_documents WHERE source = $1")
+            .bind(source)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| document_from_properties(row.try_get("document")?, &[]))
+            .collect()
+    }
+
+    /// Applies the pending migrations and embeds the stored documents again when the
+    /// embedder changed.
+    async fn migrate(&self) -> Result<Vec<String>> {
+        let mut changes = self.run_migrations().await?;
+
+        let indexed = self.indexed_embedder().await?;
+        if indexed.is_none() || indexed == Some(self.embedder.id()) {
+            return Ok(changes);
+        }
+
+        let rows = sqlx::query("SELECT uuid::text, document FROM savoir_documents")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut uuids = vec![];
+        let mut texts = vec![];
+        for row in &rows {
+            uuids.push(row.try_get::<String, _>(0)?);
+            texts.push(embedding_text(&document_from_properties(
+                row.try_get(1)?,
+                &[],
+            )?));
+        }
+        let vectors = self.embedder.embed(&texts).await?;
+
+        let mut transaction = self.pool.begin().await?;
+        for (uuid, vector) in uuids.into_iter().zip(vectors) {
+            sqlx::query(
+                "UPDATE savoir_documents SET embedding = $1::text::vector WHERE uuid = $2::text::uuid",
+            )
+            .bind(vector_literal(&vector))
+            .bind(uuid)
+            .execute(&mut *transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        self.set_indexed_embedder().await?;
+
+        changes.push(format!(
+            "embedded {} documents with {}",
+            rows.len(),
+            self.embedder.id()
+        ));
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Provenance;
+
+    use super::*;
+
+    // Database, with pgvector installed, the tests run against. They are skipped when it
+    // is not set.
+    const URL_VARIABLE: &str = "SAVOIR_TEST_POSTGRES_URL";
+
+    // Creating the extension concurrently fails, the other statements are in the schema
+    // of each test.
+    static EXTENSION: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// A store in a schema of its own, emptied first, so that tests run side by side.
+    async fn store(schema: &str, dimensions: usize) -> Option<PostgresStore> {
+        let Ok(url) = std::env::var(URL_VARIABLE) else {
+            eprintln!("{URL_VARIABLE} is not set, skipping");
+            return None;
+        };
+
+        let pool = PgPool::connect(&url).await.unwrap();
+        {
+            let _lock = EXTENSION.lock().await;
+            sqlx::query("CREATE EXTENSION IF NOT EXISTS vector")
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query(&format!("DROP SCHEMA IF EXISTS {schema} CASCADE"))
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {schema}"))
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let config = format!(
+            "{{ url: '{url}{separator}options=-c%20search_path%3D{schema},public', \
+             embedder: {{ type: hashing, dimensions: {dimensions} }} }}"
+        );
+        Some(
+            PostgresStore::async_try_from(serde_yaml::from_str(&config).unwrap())
+                .await
+                .unwrap(),
+        )
+    }
+
+    fn document(id: &str, source: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            name: id.to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Provenance {
+                source: Some(source.to_string()),
+                ..Default::default()
+            },
+            chunk: None,
+        }
+    }
+
+    fn ids(documents: &[ScoredDocument]) -> Vec<&str> {
+        documents
+            .iter()
+            .map(|document| document.document.id.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn stores_queries_and_deletes_documents() {
+        let Some(store) = store("savoir_test_documents", 64).await else {
+            return;
+        };
+
+        let documents = [
+            document("onboarding", "docs", "Read the handbook on your first day."),
+            document("deploy", "docs", "Run the pipeline to deploy the service."),
+            document("holidays", "hr", "Book your holidays early."),
+        ];
+        assert!(store.store_batch(&documents).await.unwrap().is_empty());
+        store.store(&documents[1]).await.unwrap();
+
+        let found = store
+            .query(&Query::new("deploy the service"))
+            .await
+            .unwrap();
+        assert_eq!(ids(&found)[0], "deploy");
+        assert_eq!(found.len(), 3);
+
+        let mut query = Query::new("holidays");
+        query.filters.sources = vec!["docs".to_string()];
+        assert!(!ids(&store.query(&query).await.unwrap()).contains(&"holidays"));
+
+        query.fields = vec!["source".to_string()];
+        query.filters.sources = vec![];
+        let found = store.query(&query).await.unwrap();
+        assert_eq!(found[0].document.id, "holidays");
+        assert_eq!(found[0].document.content, "");
+
+        let mut listed: Vec<_> = store
+            .list("docs")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|document| document.id)
+            .collect();
+        listed.sort();
+        assert_eq!(listed, ["deploy", "onboarding"]);
+
+        store.delete(&documents[0]).await.unwrap();
+        assert_eq!(store.list("docs").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn vector_search_scans_the_index() {
+        let Some(store) = store("savoir_test_index", 16).await else {
+            return;
+        };
+        store
+            .store(&document("onboarding", "docs", "Read the handbook."))
+            .await
+            .unwrap();
+
+        let mut connection = store.pool.acquire().await.unwrap();
+        sqlx::query("SET enable_seqscan = off")
+            .execute(&mut *connection)
+            .await
+            .unwrap();
+        let vector = store.embedder.embed_one("handbook").await.unwrap();
+        let plan: Vec<String> = sqlx::query_scalar(&format!("EXPLAIN {}", query_sql(16)))
+            .bind(vector_literal(&vector))
+            .bind("handbook")
+            .bind(20_i64)
+            .bind(Vec::<String>::new())
+            .bind(vec!["text/markdown".to_string()])
+            .bind(None::<chrono::DateTime<chrono::Utc>>)
+            .fetch_all(&mut *connection)
+            .await
+            .unwrap();
+
+        let plan = plan.join("\n");
+        assert!(
+            plan.contains("savoir_documents_embedding_16"),
+            "the index is not scanned:\n{plan}"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_vectors_of_another_embedder_until_migrated() {
+        let Some(first) = store("savoir_test_embedder", 8).await else {
+            return;
+        };
+        first
+            .store(&document("onboarding", "docs", "Read the handbook."))
+            .await
+            .unwrap();
+        assert_eq!(
+            first.indexed_embedder().await.unwrap().as_deref(),
+            Some("hashing/8")
+        );
+
+        let second = PostgresStore {
+            pool: first.pool.clone(),
+            embedder: <Box<dyn Embedder>>::try_from(
+                serde_yaml::from_str::<embedder::Config>("{ type: hashing, dimensions: 16 }")
+                    .unwrap(),
+            )
+            .unwrap(),
+        };
+        let documents = [document("deploy", "docs", "Run the pipeline.")];
+        for error in [
+            second.store(&documents[0]).await.unwrap_err(),
+            second.store_batch(&documents).await.unwrap_err(),
+            second.query(&Query::new("pipeline")).await.unwrap_err(),
+        ] {
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(embedder::Error::ModelMismatch { .. })
+            ));
+        }
+
+        second.migrate().await.unwrap();
+        second.store_batch(&documents).await.unwrap();
+        assert_eq!(second.list("docs").await.unwrap().len(), 2);
+        assert_eq!(
+            second.indexed_embedder().await.unwrap().as_deref(),
+            Some("hashing/16")
+        );
+        assert_eq!(
+            ids(&second.query(&Query::new("pipeline")).await.unwrap())[0],
+            "deploy"
+        );
+    }
+}
@@ -0,0 +1,819 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use log::{info, warn};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    document::Document,
+    embedder::{self, Embedder},
+    interals::AsyncTryFrom,
+};
+
+use super::{
+    document_from_properties, embedding_text, highlight, hybrid, send, DocumentStore, Filters,
+    Query, ScoredDocument, REQUIRED_PROPERTIES,
+};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot reach qdrant: {0}")]
+    Request(String),
+    #[error("cannot create embedder: {0}")]
+    CreateEmbedder(String),
+    #[error("cannot create qdrant collection: {0}")]
+    CreateCollection(String),
+    #[error("cannot store qdrant points: {0}")]
+    StoreDocument(String),
+    #[error("cannot query qdrant points: {0}")]
+    QueryDocument(String),
+    #[error("cannot delete qdrant point: {0}")]
+    DeleteDocument(String),
+    #[error("cannot list qdrant points: {0}")]
+    ListDocuments(String),
+}
+
+fn default_collection() -> String {
+    "savoir".to_string()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    host: String,
+    #[serde(default = "default_collection")]
+    collection: String,
+    #[serde(default)]
+    api_key: Option<String>,
+    #[serde(default)]
+    pub embedder: embedder::Config,
+}
+
+#[derive(Debug)]
+pub struct QdrantStore {
+    http: reqwest::Client,
+    host: String,
+    collection: String,
+    api_key: Option<String>,
+    embedder: Box<dyn Embedder>,
+}
+
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for QdrantStore {
+    type Error = Error;
+
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
+        let store = Self {
+            http: reqwest::Client::new(),
+            host: value.host.trim_end_matches('/').to_string(),
+            collection: value.collection,
+            api_key: value.api_key,
+            embedder: <Box<dyn Embedder>>::try_from(value.embedder)
+                .map_err(|e| Error::CreateEmbedder(e.to_string()))?,
+        };
+        store.bootstrap().await?;
+
+        Ok(store)
+    }
+}
+
+const PAGE_SIZE: usize = 100;
+
+// Payload field recording the embedder that computed the vector of a point, and key
+// of the collection metadata recording the one of the collection.
+const EMBEDDER_FIELD: &str = "embedder";
+
+// Payload fields used by the filters, indexed when the collection is created.
+const INDEXES: [(&str, &str); 3] = [
+    ("source", "keyword"),
+    ("mime_type", "keyword"),
+    ("modified_at", "datetime"),
+];
+
+fn any_of(key: &str, values: &[String]) -> Option<Value> {
+    match values.is_empty() {
+        true => None,
+        false => Some(json!({ "key": key, "match": { "any": values } })),
+    }
+}
+
+fn filter(filters: &Filters) -> Option<Value> {
+    let mut must: Vec<Value> = [
+        any_of("source", &filters.sources),
+        any_of("mime_type", &filters.mime_types),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if let Some(modified_after) = filters.modified_after {
+        must.push(json!({
+            "key": "modified_at",
+            "range": { "gt": modified_after.to_rfc3339() }
+        }));
+    }
+
+    match must.is_empty() {
+        true => None,
+        false => Some(json!({ "must": must })),
+    }
+}
+
+impl QdrantStore {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.http.request(
+            method,
+            format!("{}/collections/{}{path}", self.host, self.collection),
+        );
+
+        match &self.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
+        }
+    }
+
+    /// Creates the collection and its payload indexes when it does not exist, and
+    /// reports a vector size that differs from the configured one otherwise.
+    async fn bootstrap(&self) -> Result<bool, Error> {
+        let res = self
+            .request(Method::GET, "")
+            .send()
+            .await
+            .map_err(|e| Error::Request(e.to_string()))?;
+
+        match res.status() {
+            StatusCode::NOT_FOUND => {}
+            status if status.is_success() => {
+                let collection: Value = res
+                    .json()
+                    .await
+                    .map_err(|e| Error::Request(e.to_string()))?;
+                let size = collection
+                    .pointer("/result/config/params/vectors/size")
+                    .and_then(Value::as_u64);
+
+                if size.is_some_and(|size| size as usize != self.embedder.dimensions()) {
+                    warn!(
+                        "Qdrant collection {} has vectors of size {} instead of {}, recreate it to change it",
+                        self.collection,
+                        size.unwrap_or_default(),
+                        self.embedder.dimensions()
+                    );
+                }
+                return Ok(false);
+            }
+            status => return Err(Error::Request(format!("status {status} received"))),
+        }
+
+        info!("Creating qdrant collection {}", self.collection);
+
+        send(self.request(Method::PUT, "").json(&json!({
+            "vectors": { "size": self.embedder.dimensions(), "distance": "Cosine" }
+        })))
+        .await
+        .map_err(Error::CreateCollection)?;
+
+        for (field_name, field_schema) in INDEXES {
+            send(
+                self.request(Method::PUT, "/index?wait=true")
+                    .json(&json!({ "field_name": field_name, "field_schema": field_schema })),
+            )
+            .await
+            .map_err(Error::CreateCollection)?;
+        }
+
+        Ok(true)
+    }
+
+    /// The embedder that computed the stored vectors, from the metadata of the
+    /// collection.
+    async fn indexed_embedder(&self) -> Result<Option<String>> {
+        let res = send(self.request(Method::GET, ""))
+            .await
+            .map_err(Error::Request)?;
+
+        Ok(res
+            .pointer(&format!("/result/config/metadata/{EMBEDDER_FIELD}"))
+            .and_then(Value::as_str)
+            .map(str::to_string))
+    }
+
+    /// The embedder that computed the stored vectors, failing when it is not the
+    /// configured one until the store is migrated.
+    async fn check_indexed_embedder(&self) -> Result<Option<String>> {
+        let indexed = self.indexed_embedder().await?;
+        if let Some(indexed) = &indexed {
+            self.embedder.check(indexed)?;
+        }
+
+        Ok(indexed)
+    }
+
+    async fn set_indexed_embedder(&self) -> Result<()> {
+        send(
+            self.request(Method::PATCH, "")
+                .json(&json!({ "metadata": { EMBEDDER_FIELD: self.embedder.id() } })),
+        )
+        .await
+        .map_err(Error::Request)?;
+
+        Ok(())
+    }
+
+    /// Embeds the documents and replaces their points.
+    async fn upsert(&self, documents: &[Document]) -> Result<()> {
+        let texts: Vec<String> = documents.iter().map(embedding_text).collect();
+        let vectors = self.embedder.embed(&texts).await?;
+
+        let points = documents
+            .iter()
+            .zip(vectors)
+            .map(|(document, vector)| {
+                let mut payload = serde_json::to_value(document)?;
+                payload[EMBEDDER_FIELD] = json!(self.embedder.id());
+
+                Ok(json!({ "id": document.uuid(), "vector": vector, "payload": payload }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Points with an existing id are replaced.
+        send(
+            self.request(Method::PUT, "/points?wait=true")
+                .json(&json!({ "points": points })),
+        )
+        .await
+        .map_err(Error::StoreDocument)?;
+
+        Ok(())
+    }
+
+    /// Returns every point matching the filter.
+    async fn scroll(&self, filter: Value) -> Result<Vec<Document>> {
+        let mut documents = vec![];
+        let mut offset = Value::Null;
+
+        loop {
+            let mut body = json!({
+                "limit": PAGE_SIZE,
+                "with_payload": true,
+                "with_vector": false,
+                "filter": filter,
+            });
+
+            if !offset.is_null() {
+                body["offset"] = offset;
+            }
+
+            let res = send(self.request(Method::POST, "/points/scroll").json(&body))
+                .await
+                .map_err(Error::ListDocuments)?;
+
+            let points = res
+                .pointer("/result/points")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+
+            for point in points {
+                documents.push(document_from_properties(point["payload"].clone(), &[])?);
+            }
+
+            offset = res
+                .pointer("/result/next_page_offset")
+                .cloned()
+                .unwrap_or_default();
+
+            if offset.is_null() {
+                break;
+            }
+        }
+
+        Ok(documents)
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for QdrantStore {
+    async fn store(&self, document: &Document) -> Result<()> {
+        self.store_batch(std::slice::from_ref(document)).await?;
+
+        Ok(())
+    }
+
+    /// Qdrant applies a batch as a whole, so there are no failures of single documents.
+    async fn store_batch(&self, documents: &[Document]) -> Result<HashMap<String, String>> {
+        let indexed = self.check_indexed_embedder().await?;
+        self.upsert(documents).await?;
+
+        if indexed.is_none() {
+            self.set_indexed_embedder().await?;
+        }
+
+        Ok(HashMap::new())
+    }
+
+    /// Qdrant has no keyword index, hybrid queries rank the candidates of the vector
+    /// search with BM25 on their name and content to emulate one. Documents matching
+    /// the keywords only are not found.
+    async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
+        self.check_indexed_embedder().await?;
+
+        let vector = self.embedder.embed_one(&query.text).await?;
+
+        let limit = match query.hybrid {
+            Some(_) => hybrid::candidates(query),
+            None => query.limit,
+        };
+        let mut body = json!({
+            "vector": vector,
+            "limit": limit,
+            "with_payload": true,
+        });
+
+        if !query.fields.is_empty() {
+            // The content is needed to rank the documents by keywords.
+            let hybrid_fields = query.hybrid.as_ref().map(|_| "content");
+            let fields: Vec<&str> = REQUIRED_PROPERTIES
+                .into_iter()
+                .chain(query.fields.iter().map(String::as_str))
+                .chain([EMBEDDER_FIELD])
+                .chain(hybrid_fields)
+                .collect();
+            body["with_payload"] = json!(fields);
+        }
+
+        // Scores are mapped from the cosine similarity to 0..1, like Weaviate's, and
+        // hybrid queries only apply the minimum to the fused scores.
+        if let (Some(min_score), None) = (query.min_score, &query.hybrid) {
+            body["score_threshold"] = json!(2.0 * min_score - 1.0);
+        }
+
+        if let Some(filter) = filter(&query.filters) {
+            body["filter"] = filter;
+        }
+
+        let res = send(self.request(Method::POST, "/points/search").json(&body))
+            .await
+            .map_err(Error::QueryDocument)?;
+
+        let points = match res.get("result") {
+            Some(Value::Array(points)) => points.clone(),
+            _ => return Err(Error::QueryDocument(format!("unexpected response: {res}")).into()),
+        };
+
+        let documents = points
+            .into_iter()
+            .map(|point| {
+                if let Some(indexed) = point["payload"][EMBEDDER_FIELD].as_str() {
+                    self.embedder.check(indexed)?;
+                }
+
+                let similarity = point["score"].as_f64().unwrap_or_default();
+                let document = document_from_properties(point["payload"].clone(), &[])?;
+
+                Ok(ScoredDocument {
+                    highlight: highlight(&document.content, &query.text),
+                    score: (1.0 + similarity) / 2.0,
+                    distance: Some(1.0 - similarity),
+                    document,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        match &query.hybrid {
+            Some(config) => {
+                let keyword = hybrid::keyword_search(&documents, &query.text);
+                Ok(hybrid::fuse(config, query, keyword, documents))
+            }
+            None => Ok(documents),
+        }
+    }
+
+    async fn delete(&self, document: &Document) -> Result<()> {
+        send(
+            self.request(Method::POST, "/points/delete?wait=true")
+                .json(&json!({ "points": [document.uuid()] })),
+        )
+        .await
+        .map_err(Error::DeleteDocument)?;
+
+        Ok(())
+    }
+
+    async fn list(&self, source: &str) -> Result<Vec<Document>> {
+        self.scroll(json!({ "must": [{ "key": "source", "match": { "value": source } }] }))
+            .await
+    }
+
+    /// Creates the missing payload indexes, and embeds again the points whose vectors
+    /// were computed by another embedder.
+    async fn migrate(&self) -> Result<Vec<String>> {
+        if self.bootstrap().await? {
+            return Ok(vec![format!("created collection {}", self.collection)]);
+        }
+
+        let res = send(self.request(Method::GET, ""))
+            .await
+            .map_err(Error::Request)?;
+
+        let mut changes = vec![];
+        for (field_name, field_schema) in INDEXES {
+            if res
+                .pointer(&format!("/result/payload_schema/{field_name}"))
+                .is_some()
+            {
+                continue;
+            }
+
+            send(
+                self.request(Method::PUT, "/index?wait=true")
+                    .json(&json!({ "field_name": field_name, "field_schema": field_schema })),
+            )
+            .await
+            .map_err(Error::CreateCollection)?;
+            changes.push(format!("indexed payload field {field_name}"));
+        }
+
+        let stale = self
+            .scroll(json!({
+                "must_not": [{ "key": EMBEDDER_FIELD, "match": { "value": self.embedder.id() } }]
+            }))
+            .await?;
+
+        if !stale.is_empty() {
+            for batch in stale.chunks(PAGE_SIZE) {
+                self.upsert(batch).await?;
+            }
+            changes.push(format!(
+                "embedded {} documents with {}",
+                stale.len(),
+                self.embedder.id()
+            ));
+        }
+
+        if self.indexed_embedder().await? != Some(self.embedder.id()) {
+            self.set_indexed_embedder().await?;
+        }
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{Method, StatusCode, Uri},
+        Json, Router,
+    };
+
+    use crate::document::Provenance;
+
+    use super::*;
+
+    /// The collection of the stand-in server, and the searches it received.
+    #[derive(Default)]
+    struct Qdrant {
+        collection: Option<Value>,
+        points: HashMap<String, (Vec<f64>, Value)>,
+        searches: Vec<Value>,
+    }
+
+    type Shared = Arc<Mutex<Qdrant>>;
+
+    fn cosine(a: &[f64], b: &[f64]) -> f64 {
+        let dot: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+        let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        dot / (norm(a) * norm(b)).max(f64::MIN_POSITIVE)
+    }
+
+    /// Evaluates the `must` and `must_not` conditions the store sends.
+    fn matches(payload: &Value, filter: &Value) -> bool {
+        let condition = |condition: &Value| {
+            let value = &payload[condition["key"].as_str().unwrap()];
+            match &condition["match"] {
+                Value::Null => true,
+                m if m.get("any").is_some() => m["any"].as_array().unwrap().contains(value),
+                m => m["value"] == *value,
+            }
+        };
+
+        let conditions = |key: &str| filter[key].as_array().cloned().unwrap_or_default();
+        conditions("must").iter().all(condition) && !conditions("must_not").iter().any(condition)
+    }
+
+    fn select(payload: &Value, with_payload: &Value) -> Value {
+        let mut payload = payload.clone();
+        if let Some(fields) = with_payload.as_array() {
+            payload
+                .as_object_mut()
+                .unwrap()
+                .retain(|name, _| fields.contains(&Value::from(name.as_str())));
+        }
+        payload
+    }
+
+    async fn handle(
+        State(state): State<Shared>,
+        method: Method,
+        uri: Uri,
+        body: Bytes,
+    ) -> (StatusCode, Json<Value>) {
+        let mut state = state.lock().unwrap();
+        let path = uri
+            .path()
+            .trim_start_matches("/collections/savoir")
+            .to_string();
+        let body: Value = serde_json::from_slice(&body).unwrap_or_default();
+
+        let ok = |result: Value| (StatusCode::OK, Json(json!({ "result": result })));
+
+        match (method.as_str(), path.as_str()) {
+            ("GET", "") => match &state.collection {
+                Some(collection) => ok(collection.clone()),
+                None => (
+                    StatusCode::NOT_FOUND,
+                    Json(json!({ "status": "not found" })),
+                ),
+            },
+            ("PUT", "") => {
+                state.collection = Some(json!({
+                    "config": { "params": body },
+                    "payload_schema": {},
+                }));
+                ok(json!(true))
+            }
+            ("PATCH", "") => {
+                state.collection.as_mut().unwrap()["config"]["metadata"] = body["metadata"].clone();
+                ok(json!(true))
+            }
+            ("PUT", "/index") => {
+                let field = body["field_name"].as_str().unwrap();
+                state.collection.as_mut().unwrap()["payload_schema"][field] =
+                    json!({ "data_type": body["field_schema"] });
+                ok(json!({ "status": "completed" }))
+            }
+            ("PUT", "/points") => {
+                for point in body["points"].as_array().unwrap() {
+                    let vector = serde_json::from_value(point["vector"].clone()).unwrap();
+                    let id = point["id"].as_str().unwrap().to_string();
+                    state.points.insert(id, (vector, point["payload"].clone()));
+                }
+                ok(json!({ "status": "completed" }))
+            }
+            ("POST", "/points/delete") => {
+                for id in body["points"].as_array().unwrap() {
+                    state.points.remove(id.as_str().unwrap());
+                }
+                ok(json!({ "status": "completed" }))
+            }
+            ("POST", "/points/scroll") => {
+                let mut ids: Vec<&String> = state
+                    .points
+                    .iter()
+                    .filter(|(_, (_, payload))| matches(payload, &body["filter"]))
+                    .map(|(id, _)| id)
+                    .collect();
+                ids.sort();
+                if let Some(offset) = body["offset"].as_str() {
+                    ids.retain(|id| id.as_str() >= offset);
+                }
+
+                let limit = body["limit"].as_u64().unwrap() as usize;
+                let next = ids.get(limit).map(|id| json!(id));
+                let points: Vec<_> = ids
+                    .into_iter()
+                    .take(limit)
+                    .map(|id| json!({ "id": id, "payload": state.points[id].1 }))
+                    .collect();
+                ok(json!({ "points": points, "next_page_offset": next }))
+            }
+            ("POST", "/points/search") => {
+                let vector: Vec<f64> = serde_json::from_value(body["vector"].clone()).unwrap();
+                let threshold = body["score_threshold"].as_f64().unwrap_or(-1.0);
+                let mut points: Vec<(f64, Value)> = state
+                    .points
+                    .iter()
+                    .filter(|(_, (_, payload))| matches(payload, &body["filter"]))
+                    .map(|(id, (point, payload))| {
+                        let score = cosine(&vector, point);
+                        (
+                            score,
+                            json!({
+                                "id": id,
+                                "score": score,
+                                "payload": select(payload, &body["with_payload"]),
+                            }),
+                        )
+                    })
+                    .filter(|(score, _)| *score >= threshold)
+                    .collect();
+                points.sort_by(|a, b| b.0.total_cmp(&a.0));
+                points.truncate(body["limit"].as_u64().unwrap() as usize);
+
+                state.searches.push(body);
+                ok(points.into_iter().map(|(_, point)| point).collect())
+            }
+            _ => (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "status": "not found" })),
+            ),
+        }
+    }
+
+    fn serve() -> (String, Shared) {
+        let state = Shared::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new().fallback(handle).with_state(state.clone());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (host, state)
+    }
+
+    async fn store(host: &str, dimensions: usize) -> QdrantStore {
+        let config = format!(
+            "{{ host: '{host}', embedder: {{ type: hashing, dimensions: {dimensions} }} }}"
+        );
+        QdrantStore::async_try_from(serde_yaml::from_str(&config).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn document(id: &str, source: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            name: id.to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Provenance {
+                source: Some(source.to_string()),
+                ..Default::default()
+            },
+            chunk: None,
+        }
+    }
+
+    fn ids(documents: Vec<Document>) -> Vec<String> {
+        let mut ids: Vec<_> = documents.into_iter().map(|document| document.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn creates_the_collection_and_stores_documents() {
+        let (host, state) = serve();
+        let store = store(&host, 32).await;
+
+        let collection = state.lock().unwrap().collection.clone().unwrap();
+        assert_eq!(collection["config"]["params"]["vectors"]["size"], 32);
+        assert_eq!(
+            collection["payload_schema"]["modified_at"]["data_type"],
+            "datetime"
+        );
+
+        let onboarding = document("onboarding", "docs", "Read the handbook.");
+        store.store(&onboarding).await.unwrap();
+        let documents = [
+            document("deploy", "docs", "Run the pipeline."),
+            document("holidays", "hr", "Book them early."),
+        ];
+        assert!(store.store_batch(&documents).await.unwrap().is_empty());
+
+        assert_eq!(
+            store.indexed_embedder().await.unwrap().as_deref(),
+            Some("hashing/32")
+        );
+        assert_eq!(
+            ids(store.list("docs").await.unwrap()),
+            ["deploy", "onboarding"]
+        );
+
+        store.delete(&onboarding).await.unwrap();
+        assert_eq!(ids(store.list("docs").await.unwrap()), ["deploy"]);
+    }
+
+    #[tokio::test]
+    async fn lists_every_page() {
+        let (host, _) = serve();
+        let store = store(&host, 8).await;
+        let documents: Vec<_> = (0..PAGE_SIZE + 20)
+            .map(|i| document(&format!("{i:03}"), "docs", "text"))
+            .collect();
+        store.store_batch(&documents).await.unwrap();
+
+        assert_eq!(ids(store.list("docs").await.unwrap()), ids(documents));
+    }
+
+    #[tokio::test]
+    async fn queries_with_filters_and_scores_between_0_and_1() {
+        let (host, state) = serve();
+        let store = store(&host, 64).await;
+        store
+            .store_batch(&[
+                document("deploy", "docs", "deploy the service"),
+                document("onboarding", "docs", "read our handbook"),
+                document("release", "ops", "deploy the service"),
+            ])
+            .await
+            .unwrap();
+
+        let mut query = Query::new("deploy the service");
+        query.filters.sources = vec!["docs".to_string()];
+        query.min_score = Some(0.6);
+        query.fields = vec!["source".to_string()];
+        let found = store.query(&query).await.unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].document.id, "deploy");
+        assert!(found[0].score > 0.6 && found[0].score <= 1.0);
+        assert_eq!(found[0].document.content, "");
+
+        let search = state.lock().unwrap().searches.pop().unwrap();
+        assert_eq!(search["score_threshold"].as_f64(), Some(2.0 * 0.6 - 1.0));
+        assert_eq!(
+            search["filter"],
+            json!({ "must": [{ "key": "source", "match": { "any": ["docs"] } }] })
+        );
+        assert_eq!(
+            search["with_payload"],
+            json!(["external_id", "name", "source", EMBEDDER_FIELD])
+        );
+    }
+
+    #[tokio::test]
+    async fn hybrid_queries_rank_the_vector_candidates_by_keywords() {
+        let (host, state) = serve();
+        let store = store(&host, 64).await;
+        store
+            .store_batch(&[
+                document("ticket", "docs", "incident INC-4521 was resolved"),
+                document("incidents", "docs", "incidents are resolved by the on-call"),
+            ])
+            .await
+            .unwrap();
+
+        let mut query = Query::new("INC-4521");
+        query.hybrid = Some(hybrid::Config::default());
+        let found = store.query(&query).await.unwrap();
+
+        assert_eq!(found[0].document.id, "ticket");
+        let search = state.lock().unwrap().searches.pop().unwrap();
+        assert_eq!(search["limit"], hybrid::candidates(&query));
+        assert!(search.get("score_threshold").is_none());
+    }
+
+    #[tokio::test]
+    async fn refuses_vectors_of_another_embedder_until_migrated() {
+        let (host, state) = serve();
+        let first = store(&host, 8).await;
+        first
+            .store(&document("onboarding", "docs", "Read the handbook."))
+            .await
+            .unwrap();
+
+        let second = store(&host, 16).await;
+        let documents = [document("deploy", "docs", "Run the pipeline.")];
+        for error in [
+            second.store(&documents[0]).await.unwrap_err(),
+            second.store_batch(&documents).await.unwrap_err(),
+            second.query(&Query::new("pipeline")).await.unwrap_err(),
+        ] {
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(embedder::Error::ModelMismatch { .. })
+            ));
+        }
+        assert_eq!(state.lock().unwrap().points.len(), 1);
+
+        let changes = second.migrate().await.unwrap();
+        assert_eq!(changes, ["embedded 1 documents with hashing/16"]);
+        second.store_batch(&documents).await.unwrap();
+
+        assert_eq!(
+            second.indexed_embedder().await.unwrap().as_deref(),
+            Some("hashing/16")
+        );
+        let state = state.lock().unwrap();
+        assert!(
+            state
+                .points
+                .values()
+                .all(|(vector, payload)| vector.len() == 16
+                    && payload[EMBEDDER_FIELD] == "hashing/16")
+        );
+    }
+}
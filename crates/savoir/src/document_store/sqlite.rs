@@ -0,0 +1,523 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use anyhow::Result;
+use chrono::SecondsFormat;
+use log::warn;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::Deserialize;
+
+use crate::{
+    document::Document,
+    embedder::{
+        self,
+        cache::{from_blob, to_blob},
+        Embedder,
+    },
+};
+
+use super::{
+    document_from_properties, embedding_text, highlight, hybrid, DocumentStore, Filters, Query,
+    ScoredDocument,
+};
+
+fn default_path() -> PathBuf {
+    PathBuf::from(".savoir/store.sqlite")
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "default_path")]
+    pub path: PathBuf,
+    #[serde(default)]
+    pub embedder: embedder::Config,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: default_path(),
+            embedder: embedder::Config::default(),
+        }
+    }
+}
+
+/// A document store in a single SQLite file, with FTS5 for keyword search and a flat
+/// vector index scanned on every query.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    embedder: Box<dyn Embedder>,
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(a, b)| (a * b) as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| (x * x) as f64).sum::<f64>().sqrt();
+
+    match norm(a) * norm(b) {
+        0.0 => 0.0,
+        norms => dot / norms,
+    }
+}
+
+/// Builds an FTS5 query matching any word of the text, each word being quoted so that
+/// the FTS5 syntax in questions is not interpreted.
+fn match_expression(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{term}\""))
+        .collect();
+
+    match terms.is_empty() {
+        true => None,
+        false => Some(terms.join(" OR ")),
+    }
+}
+
+/// Dates are stored with a fixed precision so that they can be compared as text.
+fn date(date: &chrono::DateTime<chrono::Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn where_clause(filters: &Filters) -> (String, Vec<String>) {
+    let mut conditions = vec![];
+    let mut values = vec![];
+
+    for (column, accepted) in [
+        ("source", &filters.sources),
+        ("mime_type", &filters.mime_types),
+    ] {
+        if !accepted.is_empty() {
+            let placeholders = vec!["?"; accepted.len()].join(", ");
+            conditions.push(format!("{column} IN ({placeholders})"));
+            values.extend(accepted.iter().cloned());
+        }
+    }
+
+    if let Some(modified_after) = &filters.modified_after {
+        conditions.push("modified_at > ?".to_string());
+        values.push(date(modified_after));
+    }
+
+    match conditions.is_empty() {
+        true => (String::new(), values),
+        false => (format!("WHERE {}", conditions.join(" AND ")), values),
+    }
+}
+
+fn select_fields(document: Document, fields: &[String]) -> Result<Document> {
+    if fields.is_empty() {
+        return Ok(document);
+    }
+
+    document_from_properties(serde_json::to_value(document)?, fields)
+}
+
+impl SqliteStore {
+    pub fn open(config: Config) -> Result<Self> {
+        if let Some(parent) = config.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(config.path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS documents (
+                uuid TEXT PRIMARY KEY,
+                source TEXT,
+                mime_type TEXT,
+                modified_at TEXT,
+                document TEXT NOT NULL,
+                vector BLOB NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS documents_source ON documents (source);
+            CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5 (
+                uuid UNINDEXED,
+                name,
+                content
+            );
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );",
+        )?;
+
+        let store = Self {
+            connection: Mutex::new(connection),
+            embedder: Box::try_from(config.embedder)?,
+        };
+
+        if let Some(indexed) = store.indexed_embedder()? {
+            if let Err(e) = store.embedder.check(&indexed) {
+                warn!("{e}");
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// The embedder that computed the stored vectors.
+    fn indexed_embedder(&self) -> Result<Option<String>> {
+        let connection = self.connection.lock().unwrap();
+        let embedder = connection
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'embedder'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(embedder)
+    }
+
+    /// The embedder that computed the stored vectors, failing when it is not the
+    /// configured one until the store is migrated.
+    fn check_indexed_embedder(&self) -> Result<Option<String>> {
+        let indexed = self.indexed_embedder()?;
+        if let Some(indexed) = &indexed {
+            self.embedder.check(indexed)?;
+        }
+
+        Ok(indexed)
+    }
+
+    fn insert(&self, connection: &Connection, document: &Document, vector: &[f32]) -> Result<()> {
+        let uuid = document.uuid().to_string();
+
+        connection.execute(
+            "INSERT INTO documents (uuid, source, mime_type, modified_at, document, vector)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (uuid) DO UPDATE SET
+                source = excluded.source,
+                mime_type = excluded.mime_type,
+                modified_at = excluded.modified_at,
+                document = excluded.document,
+                vector = excluded.vector",
+            params![
+                uuid,
+                document.provenance.source,
+                document.provenance.mime_type,
+                document.provenance.modified_at.as_ref().map(date),
+                serde_json::to_string(document)?,
+                to_blob(vector),
+            ],
+        )?;
+
+        connection.execute("DELETE FROM documents_fts WHERE uuid = ?1", params![uuid])?;
+        connection.execute(
+            "INSERT INTO documents_fts (uuid, name, content) VALUES (?1, ?2, ?3)",
+            params![uuid, document.name, document.content],
+        )?;
+
+        Ok(())
+    }
+
+    fn set_indexed_embedder(&self, connection: &Connection) -> Result<()> {
+        connection.execute(
+            "INSERT INTO settings (key, value) VALUES ('embedder', ?1)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![self.embedder.id()],
+        )?;
+
+        Ok(())
+    }
+
+    /// BM25 scores of the documents matching any word of the text, by uuid.
+    fn keyword_scores(&self, connection: &Connection, text: &str) -> Result<HashMap<String, f64>> {
+        let Some(expression) = match_expression(text) else {
+            return Ok(HashMap::new());
+        };
+
+        let mut statement = connection.prepare(
+            "SELECT uuid, bm25(documents_fts, 2.0, 1.0) FROM documents_fts
+             WHERE documents_fts MATCH ?1",
+        )?;
+
+        let scores = statement
+            .query_map(params![expression], |row| {
+                // FTS5 returns negated BM25 scores, lower being better.
+                let score: f64 = row.get(1)?;
+                Ok((row.get(0)?, -score))
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(scores)
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for SqliteStore {
+    async fn store(&self, document: &Document) -> Result<()> {
+        let indexed = self.check_indexed_embedder()?;
+        let vectors = self.embedder.embed(&[embedding_text(document)]).await?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        self.insert(&transaction, document, &vectors[0])?;
+        if indexed.is_none() {
+            self.set_indexed_embedder(&transaction)?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn store_batch(&self, documents: &[Document]) -> Result<HashMap<String, String>> {
+        let indexed = self.check_indexed_embedder()?;
+        let texts: Vec<String> = documents.iter().map(embedding_text).collect();
+        let vectors = self.embedder.embed(&texts).await?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let mut failures = HashMap::new();
+        for (document, vector) in documents.iter().zip(&vectors) {
+            if let Err(e) = self.insert(&transaction, document, vector) {
+                failures.insert(document.id.clone(), e.to_string());
+            }
+        }
+
+        if indexed.is_none() {
+            self.set_indexed_embedder(&transaction)?;
+        }
+        transaction.commit()?;
+
+        Ok(failures)
+    }
+
+    /// Scores documents with the mean of their cosine similarity with the query and of
+    /// their BM25 score mapped to 0..1.
+    async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
+        self.check_indexed_embedder()?;
+
+        let query_vector = self.embedder.embed_one(&query.text).await?;
+
+        let connection = self.connection.lock().unwrap();
+        let keyword_scores = self.keyword_scores(&connection, &query.text)?;
+
+        let (clause, values) = where_clause(&query.filters);
+        let mut statement =
+            connection.prepare(&format!("SELECT uuid, vector FROM documents {clause}"))?;
+
+        let candidates: Vec<(String, f64, f64)> = statement
+            .query_map(params_from_iter(values), |row| {
+                let uuid: String = row.get(0)?;
+                let vector: Vec<u8> = row.get(1)?;
+                Ok((uuid, from_blob(&vector)))
+            })?
+            .filter_map(|row| {
+                let (uuid, vector) = row.ok()?;
+
+                let similarity = cosine(&vector, &query_vector);
+                let keyword = keyword_scores.get(&uuid).copied().unwrap_or_default();
+                Some((uuid, similarity, keyword / (1.0 + keyword)))
+            })
+            .collect();
+
+        let scores: Vec<(String, f64, f64)> = match &query.hybrid {
+            Some(config) => {
+                let ranking = |score: fn(&(String, f64, f64)) -> f64| {
+                    let mut ranking: Vec<(String, f64)> = candidates
+                        .iter()
+                        .map(|candidate| (candidate.0.clone(), score(candidate)))
+                        .collect();
+                    ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+                    ranking.truncate(hybrid::candidates(query));
+                    ranking
+                };
+
+                let mut keyword = ranking(|(_, _, keyword)| *keyword);
+                keyword.retain(|(_, score)| *score > 0.0);
+                let vector = ranking(|(_, similarity, _)| *similarity);
+
+                let similarities: HashMap<&str, f64> = candidates
+                    .iter()
+                    .map(|(uuid, similarity, _)| (uuid.as_str(), *similarity))
+                    .collect();
+
+                hybrid::fuse_scores(config, &keyword, &vector)
+                    .into_iter()
+                    .filter(|(_, score)| query.min_score.is_none_or(|min| *score >= min))
+                    .take(query.limit)
+                    .map(|(uuid, score)| {
+                        let distance = 1.0 - similarities[uuid.as_str()];
+                        (uuid, score, distance)
+                    })
+                    .collect()
+            }
+            None => {
+                let mut scores: Vec<(String, f64, f64)> = candidates
+                    .into_iter()
+                    .map(|(uuid, similarity, keyword)| {
+                        let score = (similarity.max(0.0) + keyword) / 2.0;
+                        (uuid, score, 1.0 - similarity)
+                    })
+                    .filter(|(_, score, _)| {
+                        query.min_score.map_or(*score > 0.0, |min| *score >= min)
+                    })
+                    .collect();
+
+                scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+                scores.truncate(query.limit);
+                scores
+            }
+        };
+
+        let mut documents = vec![];
+        for (uuid, score, distance) in scores {
+            let content: String = connection.query_row(
+                "SELECT document FROM documents WHERE uuid = ?1",
+                params![uuid],
+                |row| row.get(0),
+            )?;
+
+            let document: Document = serde_json::from_str(&content)?;
+            documents.push(ScoredDocument {
+                highlight: highlight(&document.content, &query.text),
+                document: select_fields(document, &query.fields)?,
+                score,
+                distance: Some(distance),
+            });
+        }
+
+        Ok(documents)
+    }
+
+    async fn delete(&self, document: &Document) -> Result<()> {
+        let uuid = document.uuid().to_string();
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM documents WHERE uuid = ?1", params![uuid])?;
+        transaction.execute("DELETE FROM documents_fts WHERE uuid = ?1", params![uuid])?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn list(&self, source: &str) -> Result<Vec<Document>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT document FROM documents WHERE source = ?1")?;
+
+        let documents = statement
+            .query_map(params![source], |row| row.get::<_, String>(0))?
+            .map(|content| Ok(serde_json::from_str(&content?)?))
+            .collect::<Result<_>>()?;
+
+        Ok(documents)
+    }
+
+    /// Embeds the stored documents again when the embedder changed.
+    async fn migrate(&self) -> Result<Vec<String>> {
+        let indexed = self.indexed_embedder()?;
+        if indexed.is_none() || indexed == Some(self.embedder.id()) {
+            return Ok(vec![]);
+        }
+
+        let documents: Vec<(String, String)> = self
+            .connection
+            .lock()
+            .unwrap()
+            .prepare("SELECT uuid, document FROM documents")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let texts = documents
+            .iter()
+            .map(|(_, content)| Ok(embedding_text(&serde_json::from_str(content)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let vectors = self.embedder.embed(&texts).await?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for ((uuid, _), vector) in documents.iter().zip(&vectors) {
+            transaction.execute(
+                "UPDATE documents SET vector = ?1 WHERE uuid = ?2",
+                params![to_blob(vector), uuid],
+            )?;
+        }
+
+        self.set_indexed_embedder(&transaction)?;
+        transaction.commit()?;
+
+        Ok(vec![format!(
+            "embedded {} documents with {}",
+            documents.len(),
+            self.embedder.id()
+        )])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::document::Provenance;
+
+    use super::*;
+
+    fn store(path: &Path, dimensions: usize) -> SqliteStore {
+        let config = format!(
+            "{{ path: {}, embedder: {{ type: hashing, dimensions: {dimensions} }} }}",
+            path.display()
+        );
+        SqliteStore::open(serde_yaml::from_str(&config).unwrap()).unwrap()
+    }
+
+    fn document(id: &str, content: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            name: id.to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Provenance {
+                source: Some("docs".to_string()),
+                ..Default::default()
+            },
+            chunk: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_vectors_of_another_embedder_until_migrated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.sqlite");
+
+        let first = store(&path, 8);
+        first
+            .store(&document("onboarding", "Read the handbook."))
+            .await
+            .unwrap();
+        assert_eq!(
+            first.indexed_embedder().unwrap().as_deref(),
+            Some("hashing/8")
+        );
+        drop(first);
+
+        let second = store(&path, 16);
+        let documents = [document("deploy", "Run the pipeline.")];
+        for error in [
+            second.store(&documents[0]).await.unwrap_err(),
+            second.store_batch(&documents).await.unwrap_err(),
+        ] {
+            assert!(matches!(
+                error.downcast_ref(),
+                Some(embedder::Error::ModelMismatch { .. })
+            ));
+        }
+        assert_eq!(second.list("docs").await.unwrap().len(), 1);
+        assert_eq!(
+            second.indexed_embedder().unwrap().as_deref(),
+            Some("hashing/8")
+        );
+
+        second.migrate().await.unwrap();
+        second.store_batch(&documents).await.unwrap();
+        assert_eq!(second.list("docs").await.unwrap().len(), 2);
+        assert_eq!(
+            second.indexed_embedder().unwrap().as_deref(),
+            Some("hashing/16")
+        );
+    }
+}
@@ -1,97 +1,1281 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
 use anyhow::Result;
+use log::{info, warn};
+use reqwest::StatusCode;
 use serde::Deserialize;
+use serde_json::{json, Value};
 use thiserror::Error;
 use weaviate_community::{
-    collections::{objects::Object, query::GetQuery},
+    collections::{objects::Object, query::GetQuery, schema::Class},
     WeaviateClient,
 };
 
-use crate::document::Document;
+use crate::{document::Document, interals::AsyncTryFrom};
+
+use super::{
+    document_from_properties, highlight,
+    hybrid::{self, Fusion, RRF_K},
+    DocumentStore, Filters, Query, ScoredDocument, REQUIRED_PROPERTIES,
+};
 
-use super::DocumentStore;
+mod schema;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("cannot create weaviate client: {0}")]
     CreateClient(String),
-    #[error("cannot create weaviate document: {0}")]
-    CreateDocument(String),
-    #[error("cannot create weaviate document: {0}")]
-    UpdateDocument(String),
-    #[error("cannot create weviate document: {0}")]
+    #[error("cannot store weaviate document: {0}")]
+    StoreDocument(String),
+    #[error("cannot query weaviate documents: {0}")]
     QueryDocument(String),
+    #[error("unexpected weaviate query response: {0}")]
+    UnexpectedResponse(String),
+    #[error("cannot delete weaviate document: {0}")]
+    DeleteDocument(String),
+    #[error("cannot list weaviate documents: {0}")]
+    ListDocuments(String),
+    #[error("cannot get weaviate class: {0}")]
+    GetClass(String),
+    #[error("cannot create weaviate class: {0}")]
+    CreateClass(String),
+    #[error("cannot migrate weaviate class: {0}")]
+    MigrateClass(String),
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     host: String,
+    #[serde(default)]
+    schema: schema::Config,
+}
+
+#[derive(Debug)]
+pub struct WeaviateStore {
+    client: WeaviateClient,
+    // The batch endpoint is called directly, the client drops the per-object errors.
+    http: reqwest::Client,
+    host: String,
+    schema: schema::Config,
+    /// Properties of the class that can be queried, forgotten when a write may add some.
+    properties: Mutex<Option<Vec<&'static str>>>,
+}
+
+#[derive(Deserialize)]
+struct BatchObject {
+    #[serde(default)]
+    result: BatchResult,
+}
+
+#[derive(Deserialize, Default)]
+struct BatchResult {
+    #[serde(default)]
+    errors: Option<BatchErrors>,
+}
+
+#[derive(Deserialize)]
+struct BatchErrors {
+    error: Vec<BatchError>,
+}
+
+#[derive(Deserialize)]
+struct BatchError {
+    message: String,
 }
 
-impl TryFrom<Config> for WeaviateClient {
+#[async_trait::async_trait]
+impl AsyncTryFrom<Config> for WeaviateStore {
     type Error = Error;
 
-    fn try_from(value: Config) -> Result<Self, Self::Error> {
+    async fn async_try_from(value: Config) -> Result<Self, Self::Error> {
         let client = WeaviateClient::builder(&value.host)
             .build()
             .map_err(|e| Error::CreateClient(e.to_string()))?;
 
-        Ok(client)
+        let store = Self {
+            client,
+            http: reqwest::Client::new(),
+            host: value.host.trim_end_matches('/').to_string(),
+            schema: value.schema,
+            properties: Mutex::new(None),
+        };
+        store.bootstrap().await?;
+
+        Ok(store)
     }
 }
 
 const CLASS_NAME: &str = "Document";
 
-#[async_trait::async_trait]
-impl DocumentStore for WeaviateClient {
-    async fn store(&self, document: &Document) -> Result<()> {
-        let exists = self
-            .objects
-            .exists(CLASS_NAME, &document.uuid(), None, None)
-            .await
-            .unwrap_or(false);
+const PAGE_SIZE: u64 = 100;
 
-        let value = serde_json::to_value(document)?;
+const PROPERTIES: [&str; 16] = [
+    "external_id",
+    "name",
+    "url",
+    "content",
+    "metadata",
+    "source",
+    "source_id",
+    "mime_type",
+    "author",
+    "created_at",
+    "modified_at",
+    "content_hash",
+    "parent_id",
+    "position",
+    "start_offset",
+    "end_offset",
+];
 
-        if exists {
-            let obj = Object::builder(CLASS_NAME, value).build();
+// Properties needed to find the stale documents of a source.
+const LIST_PROPERTIES: [&str; 7] = [
+    "external_id",
+    "source",
+    "source_id",
+    "parent_id",
+    "position",
+    "start_offset",
+    "end_offset",
+];
 
-            self.objects
-                .create(&obj, None)
-                .await
-                .map_err(|e| Error::CreateDocument(e.to_string()))?;
-        } else {
-            self.objects
-                .update(&value, CLASS_NAME, &document.uuid(), None)
+// Metadata keys are free-form, they are stored as a JSON string to keep them out of
+// the class schema.
+fn properties(document: &Document) -> Result<Value> {
+    let mut value = serde_json::to_value(document)?;
+    value["metadata"] = Value::from(serde_json::to_string(&document.metadata)?);
+
+    Ok(value)
+}
+
+fn from_properties(mut value: Value) -> Result<Document> {
+    if let Some(metadata) = value.get("metadata").and_then(Value::as_str) {
+        value["metadata"] = serde_json::from_str(metadata)?;
+    }
+
+    document_from_properties(value, &[])
+}
+
+// Weaviate rejects queries on properties that were never written, which happens when
+// no datasource filled an optional field of a class created by auto-schema.
+fn queryable_properties(class: &Class) -> Vec<&'static str> {
+    let existing: HashSet<&str> = class
+        .properties
+        .as_ref()
+        .map(|properties| properties.0.iter().map(|p| p.name.as_str()).collect())
+        .unwrap_or_default();
+
+    PROPERTIES
+        .into_iter()
+        .filter(|property| existing.contains(property))
+        .collect()
+}
+
+impl WeaviateStore {
+    /// Creates the class when it is missing, and reports how it drifted from the
+    /// expected schema otherwise.
+    async fn bootstrap(&self) -> Result<(), Error> {
+        let Some(class) = self.class().await? else {
+            info!("Creating weaviate class {CLASS_NAME}");
+            self.client
+                .schema
+                .create_class(&self.schema.class())
                 .await
-                .map_err(|e| Error::UpdateDocument(e.to_string()))?;
+                .map_err(|e| Error::CreateClass(e.to_string()))?;
+            *self.properties.lock().unwrap() = Some(PROPERTIES.to_vec());
+            return Ok(());
+        };
+        *self.properties.lock().unwrap() = Some(queryable_properties(&class));
+
+        let drift = self.schema.drift(&class);
+        for conflict in &drift.conflicts {
+            warn!("Weaviate class {CLASS_NAME} drifted from the expected schema: {conflict}");
+        }
+
+        if !drift.missing.is_empty() {
+            let names: Vec<_> = drift.missing.iter().map(|p| p.name.as_str()).collect();
+            warn!(
+                "Weaviate class {CLASS_NAME} is missing the properties {}, run `savoir store migrate` to add them",
+                names.join(", ")
+            );
         }
 
         Ok(())
     }
 
-    async fn query(&self, query: &str) -> Result<Vec<Document>> {
-        let query = GetQuery::builder(CLASS_NAME, vec!["external_id", "name", "url", "content"])
-            .with_limit(5)
-            .with_near_text(&format!("{{ concepts: [\"{query}\"] }}"))
-            .build();
+    /// The class stored in Weaviate, `None` when it does not exist.
+    async fn class(&self) -> Result<Option<Class>, Error> {
+        let res = self
+            .http
+            .get(format!("{}/v1/schema/{CLASS_NAME}", self.host))
+            .send()
+            .await
+            .map_err(|e| Error::GetClass(e.to_string()))?;
+
+        match res.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => res
+                .json()
+                .await
+                .map(Some)
+                .map_err(|e| Error::GetClass(e.to_string())),
+            status => {
+                let body = res.text().await.unwrap_or_default();
+                Err(Error::GetClass(format!("status {status} received: {body}")))
+            }
+        }
+    }
+
+    async fn queryable_properties(&self) -> Result<Vec<&'static str>, Error> {
+        let cached = self.properties.lock().unwrap().clone();
+        if let Some(properties) = cached {
+            return Ok(properties);
+        }
+
+        let properties = match self.class().await? {
+            Some(class) => queryable_properties(&class),
+            None => PROPERTIES.to_vec(),
+        };
+        *self.properties.lock().unwrap() = Some(properties.clone());
+
+        Ok(properties)
+    }
+
+    /// Auto-schema adds the properties that writes fill for the first time.
+    fn forget_properties(&self) {
+        *self.properties.lock().unwrap() = None;
+    }
 
+    /// Runs a GraphQL `Get` query, returning the objects of the class.
+    async fn get(&self, query: GetQuery, error: fn(String) -> Error) -> Result<Vec<Value>, Error> {
         let res = self
+            .client
             .query
             .get(query)
             .await
-            .map_err(|e| Error::QueryDocument(e.to_string()))?;
+            .map_err(|e| error(e.to_string()))?;
+
+        if let Some(errors) = res.get("errors").and_then(Value::as_array) {
+            let messages: Vec<_> = errors
+                .iter()
+                .map(|error| error["message"].as_str().unwrap_or("unknown error"))
+                .collect();
+            return Err(error(messages.join(", ")));
+        }
+
+        match res.pointer(&format!("/data/Get/{CLASS_NAME}")) {
+            Some(Value::Array(documents)) => Ok(documents.clone()),
+            Some(Value::Null) => Ok(vec![]),
+            _ => Err(Error::UnexpectedResponse(res.to_string())),
+        }
+    }
+}
+
+/// Encodes a GraphQL string literal, JSON escaping is a subset of GraphQL's.
+fn graphql_string(value: &str) -> String {
+    Value::from(value).to_string()
+}
+
+fn near_text(text: &str, max_distance: Option<f64>) -> String {
+    match max_distance {
+        Some(max_distance) => format!(
+            "{{ concepts: [{}], distance: {max_distance} }}",
+            graphql_string(text)
+        ),
+        None => format!("{{ concepts: [{}] }}", graphql_string(text)),
+    }
+}
+
+/// Weaviate runs both searches and fuses them itself, its ranked fusion being a
+/// reciprocal rank fusion.
+fn hybrid(text: &str, config: &hybrid::Config) -> String {
+    let fusion = match config.fusion {
+        Fusion::Rrf => "rankedFusion",
+        Fusion::Weighted => "relativeScoreFusion",
+    };
+
+    format!(
+        "{{ query: {}, alpha: {}, fusionType: {fusion} }}",
+        graphql_string(text),
+        config.alpha.clamp(0.0, 1.0)
+    )
+}
+
+/// Scales the fused score to 0..1. Weaviate returns it as a string.
+fn hybrid_score(config: &hybrid::Config, additional: Option<&Value>) -> f64 {
+    let score = match additional.map(|additional| &additional["score"]) {
+        Some(Value::String(score)) => score.parse().unwrap_or_default(),
+        Some(score) => score.as_f64().unwrap_or_default(),
+        None => 0.0,
+    };
+
+    // Ranked fusion adds 1 / (60 + rank) from each search, with ranks starting at 1, the
+    // first document of both searches is scored 1 like with the local fusion.
+    match config.fusion {
+        Fusion::Rrf => score * (RRF_K + 1.0),
+        Fusion::Weighted => score,
+    }
+}
+
+fn any_of(path: &str, values: &[String]) -> Option<String> {
+    let mut operands: Vec<String> = values
+        .iter()
+        .map(|value| {
+            format!(
+                "{{ path: [\"{path}\"], operator: Equal, valueText: {} }}",
+                graphql_string(value)
+            )
+        })
+        .collect();
+
+    match operands.len() {
+        0 | 1 => operands.pop(),
+        _ => Some(format!(
+            "{{ operator: Or, operands: [{}] }}",
+            operands.join(", ")
+        )),
+    }
+}
+
+fn where_filter(filters: &Filters) -> Option<String> {
+    let mut operands: Vec<String> = [
+        any_of("source", &filters.sources),
+        any_of("mime_type", &filters.mime_types),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if let Some(modified_after) = filters.modified_after {
+        operands.push(format!(
+            "{{ path: [\"modified_at\"], operator: GreaterThan, valueDate: {} }}",
+            graphql_string(&modified_after.to_rfc3339())
+        ));
+    }
+
+    match operands.len() {
+        0 | 1 => operands.pop(),
+        _ => Some(format!(
+            "{{ operator: And, operands: [{}] }}",
+            operands.join(", ")
+        )),
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for WeaviateStore {
+    async fn store(&self, document: &Document) -> Result<()> {
+        let id = document.uuid();
+        let value = properties(document)?;
+        self.forget_properties();
+
+        // Replacing takes a single round-trip for documents that were already stored, and
+        // fails for new ones which are then created with the same deterministic id.
+        let replace_error = match self
+            .client
+            .objects
+            .replace(&value, CLASS_NAME, &id, None)
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(e) => e.to_string(),
+        };
+
+        let object = Object::builder(CLASS_NAME, value).with_id(id).build();
+        self.client
+            .objects
+            .create(&object, None)
+            .await
+            .map_err(|e| Error::StoreDocument(format!("{e} (replace: {replace_error})")))?;
+
+        Ok(())
+    }
+
+    async fn store_batch(&self, documents: &[Document]) -> Result<HashMap<String, String>> {
+        let objects = documents
+            .iter()
+            .map(|document| {
+                Ok(json!({
+                    "class": CLASS_NAME,
+                    "id": document.uuid(),
+                    "properties": properties(document)?,
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.forget_properties();
+
+        let res = self
+            .http
+            .post(format!("{}/v1/batch/objects", self.host))
+            .json(&json!({ "objects": objects }))
+            .send()
+            .await
+            .map_err(|e| Error::StoreDocument(e.to_string()))?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(Error::StoreDocument(format!("status {status} received: {body}")).into());
+        }
+
+        let results: Vec<BatchObject> = res
+            .json()
+            .await
+            .map_err(|e| Error::StoreDocument(e.to_string()))?;
+
+        if results.len() != documents.len() {
+            warn!(
+                "{} results returned for {} documents",
+                results.len(),
+                documents.len()
+            );
+        }
+
+        // Weaviate answers with the objects in the order they were sent, documents
+        // without one may not have been stored.
+        let mut results = results.into_iter();
+        let failures = documents
+            .iter()
+            .filter_map(|document| {
+                let reason = match results.next() {
+                    Some(object) => {
+                        let errors = object.result.errors?;
+                        let messages: Vec<_> =
+                            errors.error.into_iter().map(|e| e.message).collect();
+                        messages.join(", ")
+                    }
+                    None => "no result returned".to_string(),
+                };
+                Some((document.id.clone(), reason))
+            })
+            .collect();
+
+        Ok(failures)
+    }
+
+    async fn query(&self, query: &Query) -> Result<Vec<ScoredDocument>> {
+        let properties: Vec<_> = self
+            .queryable_properties()
+            .await?
+            .into_iter()
+            .filter(|property| {
+                query.fields.is_empty()
+                    || REQUIRED_PROPERTIES.contains(property)
+                    || query.fields.iter().any(|field| field == property)
+            })
+            .collect();
+
+        let distance = self.schema.distance();
+        let mut builder = GetQuery::builder(CLASS_NAME, properties).with_limit(query.limit as u32);
+
+        builder = match &query.hybrid {
+            Some(config) => builder
+                .with_hybrid(&hybrid(&query.text, config))
+                .with_additional(vec!["score"]),
+            None => builder
+                .with_near_text(&near_text(
+                    &query.text,
+                    query.min_score.map(|score| distance.max_distance(score)),
+                ))
+                .with_additional(vec!["distance"]),
+        };
+
+        if let Some(filter) = where_filter(&query.filters) {
+            builder = builder.with_where(&filter);
+        }
+
+        let documents = self.get(builder.build(), Error::QueryDocument).await?;
+
+        let documents = documents
+            .into_iter()
+            .map(|mut value| {
+                let additional = value
+                    .as_object_mut()
+                    .and_then(|properties| properties.remove("_additional"));
+                let object_distance = additional
+                    .as_ref()
+                    .and_then(|additional| additional["distance"].as_f64());
+
+                let score = match &query.hybrid {
+                    Some(config) => hybrid_score(config, additional.as_ref()),
+                    None => object_distance
+                        .map(|d| distance.score(d))
+                        .unwrap_or_default(),
+                };
+
+                let document = from_properties(value)?;
+                Ok(ScoredDocument {
+                    highlight: highlight(&document.content, &query.text),
+                    score,
+                    distance: object_distance,
+                    document,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Hybrid searches have no distance to limit, the minimum applies to the fused scores.
+        Ok(match (&query.hybrid, query.min_score) {
+            (Some(_), Some(min_score)) => documents
+                .into_iter()
+                .filter(|document| document.score >= min_score)
+                .collect(),
+            _ => documents,
+        })
+    }
+
+    async fn delete(&self, document: &Document) -> Result<()> {
+        self.client
+            .objects
+            .delete(CLASS_NAME, &document.uuid(), None, None)
+            .await
+            .map_err(|e| Error::DeleteDocument(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list(&self, source: &str) -> Result<Vec<Document>> {
+        let properties: Vec<_> = self
+            .queryable_properties()
+            .await?
+            .into_iter()
+            .filter(|property| LIST_PROPERTIES.contains(property))
+            .collect();
+
+        let same_source = any_of("source", &[source.to_string()]).unwrap_or_default();
+        let mut documents: Vec<Document> = vec![];
+        let mut last: Option<String> = None;
+
+        // Weaviate's cursor cannot be combined with a filter, pages follow the external ids
+        // instead.
+        loop {
+            let filter = match &last {
+                Some(last) => format!(
+                    "{{ operator: And, operands: [{same_source}, {{ path: [\"external_id\"], operator: GreaterThan, valueText: {} }}] }}",
+                    graphql_string(last)
+                ),
+                None => same_source.clone(),
+            };
+
+            let query = GetQuery::builder(CLASS_NAME, properties.clone())
+                .with_where(&filter)
+                .with_sort("[{ path: [\"external_id\"], order: asc }]")
+                .with_limit(PAGE_SIZE as u32)
+                .build();
+
+            let page = self.get(query, Error::ListDocuments).await?;
+            let count = page.len();
+
+            for mut value in page {
+                // Only the fields identifying the document are fetched.
+                if let Value::Object(properties) = &mut value {
+                    properties.entry("name").or_insert_with(|| Value::from(""));
+                }
+                documents.push(from_properties(value)?);
+            }
+
+            last = documents.last().map(|document| document.id.clone());
+            if (count as u64) < PAGE_SIZE || last.is_none() {
+                break;
+            }
+        }
 
-        let data = res
-            .get("data")
+        Ok(documents)
+    }
+
+    async fn migrate(&self) -> Result<Vec<String>> {
+        self.forget_properties();
+
+        let Some(class) = self.class().await? else {
+            self.bootstrap().await?;
+            return Ok(vec![format!("created class {CLASS_NAME}")]);
+        };
+
+        let drift = self.schema.drift(&class);
+        for conflict in &drift.conflicts {
+            warn!("Cannot migrate weaviate class {CLASS_NAME}: {conflict}");
+        }
+
+        let mut changes = vec![];
+        for property in drift.missing {
+            self.client
+                .schema
+                .add_property(CLASS_NAME, &property)
+                .await
+                .map_err(|e| Error::MigrateClass(e.to_string()))?;
+            changes.push(format!("added property {}", property.name));
+        }
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{Method, StatusCode, Uri},
+        Json, Router,
+    };
+
+    use weaviate_community::collections::schema::Tokenization;
+
+    use crate::{app::App, document::Provenance};
+
+    use super::*;
+
+    /// Objects and requests seen by the stand-in server.
+    #[derive(Default)]
+    struct Weaviate {
+        class: Option<Value>,
+        objects: HashMap<String, Value>,
+        requests: Vec<String>,
+        /// Whether reading the schema fails with a server error.
+        failing_schema: bool,
+        /// Results left out of the end of batch responses.
+        dropped_results: usize,
+    }
+
+    type Shared = Arc<Mutex<Weaviate>>;
+
+    async fn handle(
+        State(state): State<Shared>,
+        method: Method,
+        uri: Uri,
+        body: Bytes,
+    ) -> (StatusCode, Json<Value>) {
+        let mut state = state.lock().unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap_or_default();
+        let path = uri.path().trim_end_matches('/').to_string();
+        state.requests.push(format!("{method} {path}"));
+
+        let not_found = (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": [{ "message": "not found" }] })),
+        );
+
+        match (method.as_str(), path.as_str()) {
+            ("GET", "/v1/schema/Document") if state.failing_schema => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": [{ "message": "unavailable" }] })),
+            ),
+            ("GET", "/v1/schema/Document") => match &state.class {
+                Some(class) => (StatusCode::OK, Json(class.clone())),
+                None => not_found,
+            },
+            ("POST", "/v1/schema") => {
+                state.class = Some(body.clone());
+                (StatusCode::OK, Json(body))
+            }
+            ("PUT", path) => {
+                let id = path.trim_start_matches("/v1/objects/Document/");
+                if !state.objects.contains_key(id) {
+                    return not_found;
+                }
+                state
+                    .objects
+                    .insert(id.to_string(), body["properties"].clone());
+                (StatusCode::OK, Json(body))
+            }
+            ("POST", "/v1/objects") => {
+                let id = body["id"].as_str().unwrap().to_string();
+                state.objects.insert(id, body["properties"].clone());
+                (StatusCode::OK, Json(body))
+            }
+            ("POST", "/v1/batch/objects") => {
+                let objects = body["objects"].as_array().cloned().unwrap_or_default();
+                let mut results: Vec<_> = objects
+                    .iter()
+                    .map(|object| {
+                        let id = object["id"].as_str().unwrap().to_string();
+                        state.objects.insert(id, object["properties"].clone());
+                        json!({ "result": {} })
+                    })
+                    .collect();
+                results.truncate(results.len().saturating_sub(state.dropped_results));
+                (StatusCode::OK, Json(Value::from(results)))
+            }
+            ("POST", "/v1/graphql") => {
+                let query = body["query"].as_str().unwrap_or_default().to_string();
+                state.requests.push(query);
+                let objects: Vec<_> = state.objects.values().cloned().collect();
+                (
+                    StatusCode::OK,
+                    Json(json!({ "data": { "Get": { "Document": objects } } })),
+                )
+            }
+            _ => not_found,
+        }
+    }
+
+    fn serve() -> (String, Shared) {
+        let state = Shared::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+
+        let app = Router::new().fallback(handle).with_state(state.clone());
+        let server = axum::Server::from_tcp(listener)
             .unwrap()
-            .get("Get")
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (host, state)
+    }
+
+    async fn store(host: &str) -> WeaviateStore {
+        let config = Config {
+            host: host.to_string(),
+            schema: Default::default(),
+        };
+        WeaviateStore::async_try_from(config).await.unwrap()
+    }
+
+    fn document(content: &str) -> Document {
+        Document {
+            id: "guides/onboarding.md".to_string(),
+            name: "Onboarding".to_string(),
+            content: content.to_string(),
+            url: None,
+            metadata: Default::default(),
+            provenance: Provenance {
+                source: Some("docs".to_string()),
+                ..Default::default()
+            },
+            chunk: None,
+        }
+    }
+
+    fn object_requests(state: &Shared) -> Vec<String> {
+        state
+            .lock()
             .unwrap()
-            .get("Document")
+            .requests
+            .drain(..)
+            .filter(|request| request.starts_with("PUT") || request.starts_with("POST /v1/objects"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn creates_new_documents_after_replacing_them() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        let document = document("Read the handbook.");
+
+        store.store(&document).await.unwrap();
+
+        let uuid = document.uuid();
+        assert_eq!(
+            object_requests(&state),
+            [
+                format!("PUT /v1/objects/Document/{uuid}"),
+                "POST /v1/objects".to_string()
+            ]
+        );
+        assert_eq!(
+            state.lock().unwrap().objects[&uuid.to_string()]["content"],
+            "Read the handbook."
+        );
+    }
+
+    #[tokio::test]
+    async fn replaces_changed_documents_under_their_uuid() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        store.store(&document("Read the handbook.")).await.unwrap();
+        object_requests(&state);
+
+        let document = document("Read the handbook and meet the team.");
+        store.store(&document).await.unwrap();
+
+        let uuid = document.uuid();
+        assert_eq!(
+            object_requests(&state),
+            [format!("PUT /v1/objects/Document/{uuid}")]
+        );
+        let state = state.lock().unwrap();
+        assert_eq!(state.objects.len(), 1);
+        assert_eq!(
+            state.objects[&uuid.to_string()]["content"],
+            "Read the handbook and meet the team."
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_batch_failures_per_document() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        let documents = [document("Read the handbook.")];
+
+        let failures = store.store_batch(&documents).await.unwrap();
+
+        assert!(failures.is_empty());
+        assert!(state
+            .lock()
             .unwrap()
-            .to_owned();
+            .objects
+            .contains_key(&documents[0].uuid().to_string()));
+    }
 
-        let documents: Vec<Document> = serde_json::from_value(data)?;
+    #[tokio::test]
+    async fn reports_documents_without_batch_results() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        state.lock().unwrap().dropped_results = 1;
+        let mut documents = [document("Read the handbook."), document("Meet the team.")];
+        documents[1].id = "guides/team.md".to_string();
 
-        Ok(documents)
+        let failures = store.store_batch(&documents).await.unwrap();
+
+        assert_eq!(
+            failures,
+            HashMap::from([(
+                "guides/team.md".to_string(),
+                "no result returned".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn only_creates_the_class_when_it_is_missing() {
+        let (host, state) = serve();
+        state.lock().unwrap().failing_schema = true;
+
+        let config = Config {
+            host: host.clone(),
+            schema: Default::default(),
+        };
+        let error = WeaviateStore::async_try_from(config).await.unwrap_err();
+
+        assert!(matches!(error, Error::GetClass(_)), "{error}");
+        let state = state.lock().unwrap();
+        assert!(state.class.is_none());
+        assert!(!state.requests.contains(&"POST /v1/schema".to_string()));
+    }
+
+    #[tokio::test]
+    async fn reads_the_class_again_after_writes_only() {
+        let (host, state) = serve();
+        let store = store(&host).await;
+        let schema_reads = || {
+            let mut state = state.lock().unwrap();
+            let reads = state
+                .requests
+                .iter()
+                .filter(|request| *request == "GET /v1/schema/Document")
+                .count();
+            state.requests.clear();
+            reads
+        };
+        schema_reads();
+
+        let query = Query {
+            text: "handbook".to_string(),
+            ..Default::default()
+        };
+        store.query(&query).await.unwrap();
+        store.query(&query).await.unwrap();
+        assert_eq!(schema_reads(), 0);
+
+        store
+            .store_batch(&[document("Read the handbook.")])
+            .await
+            .unwrap();
+        store.query(&query).await.unwrap();
+        store.query(&query).await.unwrap();
+        assert_eq!(schema_reads(), 1);
+    }
+
+    #[test]
+    fn drift_reports_changed_tokenization() {
+        let config = schema::Config::default();
+        let mut class = config.class();
+        for property in &mut class.properties.as_mut().unwrap().0 {
+            if property.name == "external_id" {
+                property.tokenization = Some(Tokenization::WORD);
+            }
+        }
+
+        let drift = config.drift(&class);
+
+        assert!(drift.missing.is_empty());
+        assert_eq!(
+            drift.conflicts,
+            ["property external_id is tokenized by word instead of field"]
+        );
+    }
+
+    #[test]
+    fn rrf_scores_are_scaled_like_the_local_fusion() {
+        let config = hybrid::Config {
+            fusion: Fusion::Rrf,
+            alpha: 0.25,
+        };
+        let keyword = [("a".to_string(), 2.0), ("b".to_string(), 1.0)];
+        let vector = [("b".to_string(), 0.9), ("a".to_string(), 0.8)];
+
+        // Weaviate weighs 1 / (60 + rank) of each search, ranks starting at 1.
+        let weaviate = |keyword_rank: f64, vector_rank: f64| {
+            let score = 0.75 / (RRF_K + keyword_rank) + 0.25 / (RRF_K + vector_rank);
+            hybrid_score(&config, Some(&json!({ "score": score.to_string() })))
+        };
+
+        let local: HashMap<String, f64> = hybrid::fuse_scores(&config, &keyword, &vector)
+            .into_iter()
+            .collect();
+        assert!((weaviate(1.0, 2.0) - local["a"]).abs() < 1e-9);
+        assert!((weaviate(2.0, 1.0) - local["b"]).abs() < 1e-9);
+        assert!((weaviate(1.0, 1.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn synchronizing_unchanged_files_creates_no_objects() {
+        let (host, state) = serve();
+        let dir = tempfile::tempdir().unwrap();
+        let files = dir.path().join("docs");
+        std::fs::create_dir(&files).unwrap();
+        std::fs::write(
+            files.join("onboarding.md"),
+            "# Onboarding\n\nRead the handbook.",
+        )
+        .unwrap();
+        std::fs::write(files.join("deploy.md"), "# Deploy\n\nRun the pipeline.").unwrap();
+
+        let config = format!(
+            "
+datasources:
+  docs:
+    type: filesystem
+    paths: [{files}]
+llms: {{}}
+store:
+  type: weaviate
+  host: {host}
+agents: {{}}
+integrations: {{}}
+ledger:
+  type: json
+  path: {ledger}
+",
+            files = files.display(),
+            ledger = dir.path().join("ledger.json").display(),
+        );
+        let app = App::async_try_from(serde_yaml::from_str(&config).unwrap())
+            .await
+            .unwrap();
+
+        let report = app.synchronize("docs", false, false).await.unwrap();
+        assert_eq!(report.created, 2);
+        assert_eq!(state.lock().unwrap().objects.len(), 2);
+        state.lock().unwrap().requests.clear();
+
+        let report = app.synchronize("docs", false, false).await.unwrap();
+        assert_eq!(
+            (report.created, report.updated, report.unchanged),
+            (0, 0, 2)
+        );
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.objects.len(), 2);
+        // Stale documents are looked up among the objects of the datasource only.
+        assert!(state.requests.iter().any(|request| {
+            request.contains(r#"where: { path: ["source"], operator: Equal, valueText: "docs" }"#)
+        }));
+        assert!(!state.requests.iter().any(|request| {
+            request.starts_with("PUT")
+                || request.starts_with("POST /v1/objects")
+                || request.starts_with("POST /v1/batch")
+        }));
+    }
+
+    /// GraphQL input values, as parsed from the arguments built for Weaviate.
+    #[derive(Debug, PartialEq)]
+    enum Input {
+        String(String),
+        Number(f64),
+        Enum(String),
+        List(Vec<Input>),
+        Object(Vec<(String, Input)>),
+    }
+
+    impl Input {
+        fn field(&self, name: &str) -> &Input {
+            match self {
+                Input::Object(fields) => fields
+                    .iter()
+                    .find(|(field, _)| field == name)
+                    .map(|(_, value)| value)
+                    .unwrap_or_else(|| panic!("missing field {name} in {self:?}")),
+                _ => panic!("{self:?} is not an object"),
+            }
+        }
+
+        fn strings(&self) -> Vec<&str> {
+            match self {
+                Input::String(s) => vec![s],
+                Input::List(values) => values.iter().flat_map(Input::strings).collect(),
+                Input::Object(fields) => fields.iter().flat_map(|(_, v)| v.strings()).collect(),
+                Input::Number(_) | Input::Enum(_) => vec![],
+            }
+        }
+    }
+
+    /// Parses a single GraphQL input value, failing unless it spans the whole text.
+    fn parse(text: &str) -> Result<Input, String> {
+        let mut parser = Parser { text, position: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.position == text.len() {
+            true => Ok(value),
+            false => Err(format!("trailing text: {}", &text[parser.position..])),
+        }
+    }
+
+    struct Parser<'a> {
+        text: &'a str,
+        position: usize,
+    }
+
+    impl Parser<'_> {
+        fn peek(&self) -> Option<char> {
+            self.text[self.position..].chars().next()
+        }
+
+        fn skip_whitespace(&mut self) {
+            while let Some(c) = self.peek().filter(|c| matches!(c, ' ' | '\t' | '\n' | ',')) {
+                self.position += c.len_utf8();
+            }
+        }
+
+        fn expect(&mut self, expected: char) -> Result<(), String> {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(c) if c == expected => {
+                    self.position += 1;
+                    Ok(())
+                }
+                c => Err(format!(
+                    "expected {expected} at {}, got {c:?}",
+                    self.position
+                )),
+            }
+        }
+
+        fn name(&mut self) -> Result<String, String> {
+            self.skip_whitespace();
+            let start = self.position;
+            while let Some(c) = self
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                self.position += c.len_utf8();
+            }
+            match self.position > start {
+                true => Ok(self.text[start..self.position].to_string()),
+                false => Err(format!("expected a name at {start}")),
+            }
+        }
+
+        fn value(&mut self) -> Result<Input, String> {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('"') => self.string(),
+                Some('[') => {
+                    self.expect('[')?;
+                    let mut values = vec![];
+                    loop {
+                        self.skip_whitespace();
+                        if self.peek() == Some(']') {
+                            self.position += 1;
+                            return Ok(Input::List(values));
+                        }
+                        values.push(self.value()?);
+                    }
+                }
+                Some('{') => {
+                    self.expect('{')?;
+                    let mut fields = vec![];
+                    loop {
+                        self.skip_whitespace();
+                        if self.peek() == Some('}') {
+                            self.position += 1;
+                            return Ok(Input::Object(fields));
+                        }
+                        let name = self.name()?;
+                        self.expect(':')?;
+                        fields.push((name, self.value()?));
+                    }
+                }
+                Some(c) if c == '-' || c.is_ascii_digit() => {
+                    let start = self.position;
+                    while let Some(c) = self
+                        .peek()
+                        .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+                    {
+                        self.position += c.len_utf8();
+                    }
+                    self.text[start..self.position]
+                        .parse()
+                        .map(Input::Number)
+                        .map_err(|e| e.to_string())
+                }
+                _ => self.name().map(Input::Enum),
+            }
+        }
+
+        // GraphQL strings cannot hold raw line terminators or control characters, and
+        // their escapes are JSON's, which validates them.
+        fn string(&mut self) -> Result<Input, String> {
+            let start = self.position;
+            self.position += 1;
+            loop {
+                match self.peek() {
+                    None => return Err(format!("unterminated string at {start}")),
+                    Some('"') => break,
+                    Some('\\') => self.position += 1,
+                    Some(c) if c < ' ' && c != '\t' => {
+                        return Err(format!("raw control character at {}", self.position))
+                    }
+                    _ => {}
+                }
+                self.position += self.peek().map_or(1, char::len_utf8);
+            }
+            self.position += 1;
+            serde_json::from_str(&self.text[start..self.position])
+                .map(Input::String)
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    /// Hand-picked injection attempts, then strings drawn from their characters.
+    fn adversarial_strings() -> Vec<String> {
+        let mut strings: Vec<String> = [
+            "",
+            "\"",
+            "\\",
+            "\\\"",
+            "}",
+            "\"] } ) { __schema { types { name } } } #",
+            "\" }) { Get { Document { content } } } #",
+            "line\nbreak\r\nand\ttab",
+            "\u{0}\u{1}\u{1f}\u{7f}",
+            "\u{2028}\u{2029}",
+            "\\u0022\\n",
+            "emoji 😀 and clef 𝄞",
+            "'single' ${x} `tick`",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let alphabet: Vec<char> = "\"\\{}[]():,#$ \n\r\t\u{0}\u{8}\u{1b}a_Zé😀𝄞\u{2028}"
+            .chars()
+            .collect();
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..500 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let length = (seed % 24) as usize;
+            let string = (0..length)
+                .map(|i| alphabet[((seed >> (i % 48)) as usize + i * 7) % alphabet.len()])
+                .collect();
+            strings.push(string);
+        }
+
+        strings
+    }
+
+    #[test]
+    fn graphql_strings_round_trip() {
+        for string in adversarial_strings() {
+            let literal = graphql_string(&string);
+            assert_eq!(
+                parse(&literal),
+                Ok(Input::String(string.clone())),
+                "{literal}"
+            );
+        }
+
+        // The parser catches what naive quoting lets through.
+        assert!(parse("\"\"] } ) { __schema\"").is_err());
+        assert!(parse("\"line\nbreak\"").is_err());
+    }
+
+    #[test]
+    fn near_text_keeps_the_text_in_one_concept() {
+        for string in adversarial_strings() {
+            let argument = near_text(&string, Some(0.25));
+            let value = parse(&argument).unwrap_or_else(|e| panic!("{e}: {argument}"));
+
+            assert_eq!(
+                value.field("concepts"),
+                &Input::List(vec![Input::String(string.clone())])
+            );
+            assert_eq!(value.field("distance"), &Input::Number(0.25));
+        }
+    }
+
+    #[test]
+    fn hybrid_keeps_the_text_in_the_query() {
+        let config = hybrid::Config {
+            fusion: Fusion::Weighted,
+            alpha: 0.3,
+        };
+
+        for string in adversarial_strings() {
+            let argument = hybrid(&string, &config);
+            let value = parse(&argument).unwrap_or_else(|e| panic!("{e}: {argument}"));
+
+            assert_eq!(value.field("query"), &Input::String(string.clone()));
+            assert_eq!(value.field("alpha"), &Input::Number(0.3));
+            assert_eq!(
+                value.field("fusionType"),
+                &Input::Enum("relativeScoreFusion".to_string())
+            );
+        }
+    }
+
+    /// The path and text of each comparison, whatever operators combine them.
+    fn comparisons(value: &Input) -> Vec<(String, String)> {
+        match value.field("operator") {
+            Input::Enum(operator) if operator == "And" || operator == "Or" => {
+                match value.field("operands") {
+                    Input::List(operands) => operands.iter().flat_map(comparisons).collect(),
+                    operands => panic!("{operands:?} are not a list"),
+                }
+            }
+            _ => vec![(
+                value.field("path").strings().concat(),
+                value.field("valueText").strings().concat(),
+            )],
+        }
+    }
+
+    #[test]
+    fn where_filter_keeps_each_value_in_its_operand() {
+        for values in adversarial_strings().chunks(3) {
+            let filters = Filters {
+                sources: values.to_vec(),
+                mime_types: values[..1].to_vec(),
+                modified_after: None,
+            };
+            let argument = where_filter(&filters).unwrap();
+            let value = parse(&argument).unwrap_or_else(|e| panic!("{e}: {argument}"));
+
+            let mut expected: Vec<_> = values
+                .iter()
+                .map(|value| ("source".to_string(), value.clone()))
+                .collect();
+            expected.push(("mime_type".to_string(), values[0].clone()));
+            assert_eq!(comparisons(&value), expected, "{argument}");
+        }
     }
 }
@@ -0,0 +1,241 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use weaviate_community::collections::schema::{
+    Bm25, Class, DistanceMetric, InvertedIndexConfig, Properties, Property, Tokenization,
+    VectorIndexConfig,
+};
+
+use super::{CLASS_NAME, PROPERTIES};
+
+fn default_vectorizer() -> String {
+    "text2vec-transformers".to_string()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "default_vectorizer")]
+    vectorizer: String,
+    #[serde(default)]
+    module_config: Option<Value>,
+    #[serde(default)]
+    vector_index: VectorIndex,
+    #[serde(default)]
+    bm25: Option<Bm25Config>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            vectorizer: default_vectorizer(),
+            module_config: None,
+            vector_index: VectorIndex::default(),
+            bm25: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct VectorIndex {
+    distance: Option<Distance>,
+    ef: Option<i64>,
+    ef_construction: Option<u64>,
+    max_connections: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Distance {
+    Cosine,
+    Dot,
+    L2Squared,
+    Hamming,
+    Manhattan,
+}
+
+impl Distance {
+    /// Converts a distance to a similarity where higher is better, between 0 and 1 for
+    /// every metric but the dot product.
+    pub fn score(self, distance: f64) -> f64 {
+        match self {
+            Distance::Cosine => 1.0 - distance / 2.0,
+            Distance::Dot => -distance,
+            Distance::L2Squared | Distance::Hamming | Distance::Manhattan => 1.0 / (1.0 + distance),
+        }
+    }
+
+    pub fn max_distance(self, score: f64) -> f64 {
+        match self {
+            Distance::Cosine => 2.0 * (1.0 - score),
+            Distance::Dot => -score,
+            Distance::L2Squared | Distance::Hamming | Distance::Manhattan => {
+                1.0 / score.max(f64::EPSILON) - 1.0
+            }
+        }
+    }
+}
+
+impl From<Distance> for DistanceMetric {
+    fn from(value: Distance) -> Self {
+        match value {
+            Distance::Cosine => DistanceMetric::COSINE,
+            Distance::Dot => DistanceMetric::DOT,
+            Distance::L2Squared => DistanceMetric::L2SQUARED,
+            Distance::Hamming => DistanceMetric::HAMMING,
+            Distance::Manhattan => DistanceMetric::MANHATTAN,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Bm25Config {
+    b: f64,
+    k1: f64,
+}
+
+/// Differences between the class stored in Weaviate and the expected one.
+#[derive(Debug, Default)]
+pub struct Drift {
+    /// Properties that can be added to the class.
+    pub missing: Vec<Property>,
+    /// Differences that cannot be applied without recreating the class.
+    pub conflicts: Vec<String>,
+}
+
+impl Config {
+    pub fn distance(&self) -> Distance {
+        self.vector_index.distance.unwrap_or(Distance::Cosine)
+    }
+
+    fn property(&self, name: &str) -> Property {
+        let (data_type, tokenization, vectorize) = match name {
+            "name" | "content" => ("text", Some(Tokenization::WORD), true),
+            "author" | "metadata" => ("text", Some(Tokenization::WORD), false),
+            "created_at" | "modified_at" => ("date", None, false),
+            "position" | "start_offset" | "end_offset" => ("int", None, false),
+            _ => ("text", Some(Tokenization::FIELD), false),
+        };
+
+        let mut builder = Property::builder(name, vec![data_type]);
+
+        if let Some(tokenization) = tokenization {
+            builder = builder.with_tokenization(tokenization);
+        }
+
+        if self.vectorizer != "none" {
+            builder = builder.with_module_config(json!({
+                self.vectorizer.as_str(): {
+                    "skip": !vectorize,
+                    "vectorizePropertyName": false,
+                }
+            }));
+        }
+
+        builder.build()
+    }
+
+    pub fn properties(&self) -> Vec<Property> {
+        PROPERTIES.iter().map(|name| self.property(name)).collect()
+    }
+
+    pub fn class(&self) -> Class {
+        let mut vector_index = VectorIndexConfig::builder();
+        if let Some(distance) = self.vector_index.distance {
+            vector_index = vector_index.with_distance(distance.into());
+        }
+        if let Some(ef) = self.vector_index.ef {
+            vector_index = vector_index.with_ef(ef);
+        }
+        if let Some(ef_construction) = self.vector_index.ef_construction {
+            vector_index = vector_index.with_ef_construction(ef_construction);
+        }
+        if let Some(max_connections) = self.vector_index.max_connections {
+            vector_index = vector_index.with_max_connections(max_connections);
+        }
+
+        let mut inverted_index = InvertedIndexConfig::builder().with_index_timestamps(true);
+        if let Some(Bm25Config { b, k1 }) = self.bm25 {
+            inverted_index = inverted_index.with_bm25(Bm25 { b, k1 });
+        }
+
+        let mut class = Class::builder(CLASS_NAME)
+            .with_properties(Properties::new(self.properties()))
+            .with_vectorizer(&self.vectorizer)
+            .with_vector_index_config(vector_index.build())
+            .with_inverted_index_config(inverted_index.build());
+
+        if let Some(module_config) = &self.module_config {
+            class = class.with_module_config(module_config.clone());
+        }
+
+        class.build()
+    }
+
+    pub fn drift(&self, class: &Class) -> Drift {
+        let mut drift = Drift::default();
+
+        if class.vectorizer.as_deref() != Some(self.vectorizer.as_str()) {
+            drift.conflicts.push(format!(
+                "vectorizer is {} instead of {}",
+                class.vectorizer.as_deref().unwrap_or("unset"),
+                self.vectorizer
+            ));
+        }
+
+        if let Some(distance) = self.vector_index.distance {
+            let expected = serde_json::to_value(DistanceMetric::from(distance)).ok();
+            let actual = class
+                .vector_index_config
+                .as_ref()
+                .and_then(|config| config.distance.as_ref())
+                .and_then(|distance| serde_json::to_value(distance).ok());
+
+            if expected != actual {
+                drift.conflicts.push(format!(
+                    "vector index distance is {} instead of {}",
+                    actual.unwrap_or(Value::Null),
+                    expected.unwrap_or(Value::Null)
+                ));
+            }
+        }
+
+        let existing = class
+            .properties
+            .as_ref()
+            .map(|properties| properties.0.as_slice())
+            .unwrap_or_default();
+
+        for property in self.properties() {
+            match existing.iter().find(|p| p.name == property.name) {
+                None => drift.missing.push(property),
+                Some(actual) if actual.data_type != property.data_type => {
+                    drift.conflicts.push(format!(
+                        "property {} is {} instead of {}",
+                        property.name,
+                        actual.data_type.join(", "),
+                        property.data_type.join(", ")
+                    ))
+                }
+                Some(actual) if actual.tokenization != property.tokenization => {
+                    drift.conflicts.push(format!(
+                        "property {} is tokenized by {} instead of {}",
+                        property.name,
+                        tokenization(actual),
+                        tokenization(&property)
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        drift
+    }
+}
+
+fn tokenization(property: &Property) -> String {
+    property
+        .tokenization
+        .as_ref()
+        .and_then(|tokenization| serde_json::to_value(tokenization).ok())
+        .and_then(|tokenization| tokenization.as_str().map(str::to_string))
+        .unwrap_or_else(|| "unset".to_string())
+}
@@ -0,0 +1,103 @@
+use std::fmt::Debug;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use thiserror::Error;
+
+use self::{
+    bert::Bert,
+    cache::{CachedEmbedder, EmbeddingCache},
+    hashing::Hashing,
+    openai::OpenAi,
+};
+
+pub mod bert;
+pub mod cache;
+pub mod hashing;
+pub mod openai;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("documents were embedded with {indexed} but the configured embedder is {configured}, run `savoir store migrate` to embed them again")]
+    ModelMismatch { indexed: String, configured: String },
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Config {
+    #[serde(flatten)]
+    pub provider: Provider,
+    #[serde(default)]
+    pub cache: cache::Config,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Provider {
+    #[serde(rename = "openai")]
+    OpenAi(openai::Config),
+    #[serde(rename = "openai_compatible")]
+    OpenAiCompatible(openai::CompatibleConfig),
+    Bert(bert::Config),
+    Hashing(hashing::Config),
+}
+
+impl Default for Provider {
+    fn default() -> Self {
+        Self::Hashing(hashing::Config::default())
+    }
+}
+
+#[async_trait::async_trait]
+pub trait Embedder: Send + Sync + Debug {
+    fn model(&self) -> &str;
+    fn dimensions(&self) -> usize;
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// Whether computing the vectors costs more than reading them from the cache.
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    async fn embed_one(&self, text: &str) -> Result<Vec<f32>> {
+        let mut vectors = self.embed(&[text.to_string()]).await?;
+        vectors.pop().ok_or(anyhow!("No embedding returned"))
+    }
+
+    /// Identifies the vectors of the embedder, stored with them to detect a change of
+    /// model between indexing and querying.
+    fn id(&self) -> String {
+        format!("{}/{}", self.model(), self.dimensions())
+    }
+
+    /// Fails when the vectors of a store were computed by another embedder.
+    fn check(&self, indexed: &str) -> Result<(), Error> {
+        match indexed == self.id() {
+            true => Ok(()),
+            false => Err(Error::ModelMismatch {
+                indexed: indexed.to_string(),
+                configured: self.id(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Config> for Box<dyn Embedder> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Config) -> Result<Self, Self::Error> {
+        let embedder: Box<dyn Embedder> = match value.provider {
+            Provider::OpenAi(config) => Box::new(OpenAi::try_from(config)?),
+            Provider::OpenAiCompatible(config) => Box::new(OpenAi::from(config)),
+            Provider::Bert(config) => Box::new(Bert::try_from(config)?),
+            Provider::Hashing(config) => Box::new(Hashing::from(config)),
+        };
+
+        match value.cache.enabled && embedder.cacheable() {
+            true => {
+                let cache = EmbeddingCache::open(&value.cache.path)?;
+                Ok(Box::new(CachedEmbedder::new(embedder, cache)))
+            }
+            false => Ok(embedder),
+        }
+    }
+}
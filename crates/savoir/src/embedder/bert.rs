@@ -0,0 +1,244 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use candle_core::DType;
+use candle_transformers::models::bert::BertModel;
+use rayon::ThreadPool;
+use serde::Deserialize;
+use thiserror::Error;
+use tokenizers::Tokenizer;
+
+use crate::bert::{self, Checkpoint};
+
+use super::Embedder;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot load embedding model: {0}")]
+    LoadModel(String),
+    #[error("cannot embed texts: {0}")]
+    Embed(String),
+}
+
+fn default_batch_size() -> usize {
+    32
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Directory of a sentence-transformers BERT model, like all-MiniLM-L6-v2, with its
+    /// `config.json`, `tokenizer.json` and `model.safetensors` files.
+    path: PathBuf,
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    #[serde(default)]
+    threads: Option<usize>,
+}
+
+struct Model {
+    bert: BertModel,
+    tokenizer: Tokenizer,
+}
+
+/// Runs a BERT model on the CPU and mean-pools its token embeddings.
+pub struct Bert {
+    model: Arc<Model>,
+    pool: Arc<ThreadPool>,
+    name: String,
+    revision: String,
+    dimensions: usize,
+    batch_size: usize,
+}
+
+impl std::fmt::Debug for Bert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bert").field("name", &self.name).finish()
+    }
+}
+
+impl TryFrom<Config> for Bert {
+    type Error = Error;
+
+    fn try_from(value: Config) -> Result<Self, Self::Error> {
+        let load =
+            |e: &dyn std::fmt::Display| Error::LoadModel(format!("{}: {e}", value.path.display()));
+
+        let checkpoint = Checkpoint::load(&value.path).map_err(|e| load(&e))?;
+        let bert = BertModel::load(checkpoint.weights, &checkpoint.config).map_err(|e| load(&e))?;
+        let pool = bert::thread_pool("embedder", value.threads).map_err(|e| load(&e))?;
+
+        Ok(Self {
+            model: Arc::new(Model {
+                bert,
+                tokenizer: checkpoint.tokenizer,
+            }),
+            pool: Arc::new(pool),
+            name: checkpoint.name,
+            revision: checkpoint.revision,
+            dimensions: checkpoint.config.hidden_size,
+            batch_size: value.batch_size.max(1),
+        })
+    }
+}
+
+impl Model {
+    fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, Error> {
+        let embed = |e: &dyn std::fmt::Display| Error::Embed(e.to_string());
+
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts, true)
+            .map_err(|e| embed(&e))?;
+
+        let (ids, type_ids, mask) = bert::batch(&encodings).map_err(|e| embed(&e))?;
+
+        let output = self
+            .bert
+            .forward(&ids, &type_ids, Some(&mask))
+            .map_err(|e| embed(&e))?;
+
+        // Averages the embeddings of the tokens, leaving the padding out.
+        let pooled = (|| {
+            let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+            let sum = output.broadcast_mul(&mask)?.sum(1)?;
+            let count = mask.sum(1)?.clamp(1e-9, f64::MAX)?;
+            let mean = sum.broadcast_div(&count)?;
+
+            let norm = mean.sqr()?.sum_keepdim(1)?.sqrt()?.clamp(1e-12, f64::MAX)?;
+            mean.broadcast_div(&norm)?.to_vec2::<f32>()
+        })()
+        .map_err(|e| embed(&e))?;
+
+        Ok(pooled)
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for Bert {
+    fn model(&self) -> &str {
+        &self.name
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Includes the revision of the model, whose vectors change with its files.
+    fn id(&self) -> String {
+        format!("{}@{}/{}", self.name, self.revision, self.dimensions)
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+
+        for batch in texts.chunks(self.batch_size) {
+            let model = self.model.clone();
+            let pool = self.pool.clone();
+            let batch = batch.to_vec();
+
+            let embeddings =
+                tokio::task::spawn_blocking(move || pool.install(|| model.embed(batch))).await??;
+            vectors.extend(embeddings);
+        }
+
+        Ok(vectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texts and the vectors a reference implementation embeds them to, written with
+    /// the tiny random model by `generate.py`.
+    #[derive(Deserialize)]
+    struct Expected {
+        texts: Vec<String>,
+        vectors: Vec<Vec<f32>>,
+    }
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tinybert")
+    }
+
+    fn expected() -> Expected {
+        let expected = std::fs::read_to_string(fixture().join("expected.json")).unwrap();
+        serde_json::from_str(&expected).unwrap()
+    }
+
+    fn bert(batch_size: usize) -> Bert {
+        load(fixture(), batch_size)
+    }
+
+    fn load(path: PathBuf, batch_size: usize) -> Bert {
+        Bert::try_from(Config {
+            path,
+            batch_size,
+            threads: Some(1),
+        })
+        .unwrap()
+    }
+
+    fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        1.0 - dot / (norm(a) * norm(b))
+    }
+
+    #[tokio::test]
+    async fn embeds_like_the_reference_implementation() {
+        let expected = expected();
+        let bert = bert(32);
+
+        let vectors = bert.embed(&expected.texts).await.unwrap();
+
+        assert_eq!(bert.dimensions(), 8);
+        assert_eq!(bert.model(), "tinybert");
+        for ((text, vector), reference) in expected.texts.iter().zip(vectors).zip(&expected.vectors)
+        {
+            let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-5, "{text}: norm {norm}");
+            let distance = cosine_distance(&vector, reference);
+            assert!(distance < 1e-5, "{text}: distance {distance}");
+        }
+    }
+
+    #[tokio::test]
+    async fn padding_does_not_change_vectors() {
+        let texts = expected().texts;
+
+        // Texts embedded one by one have no padding, batched ones are padded to the
+        // longest of the batch.
+        let unpadded = bert(1).embed(&texts).await.unwrap();
+        let padded = bert(texts.len()).embed(&texts).await.unwrap();
+
+        for ((text, unpadded), padded) in texts.iter().zip(unpadded).zip(padded) {
+            let distance = cosine_distance(&unpadded, &padded);
+            assert!(distance < 1e-6, "{text}: distance {distance}");
+        }
+    }
+
+    #[test]
+    fn identifies_the_revision_of_the_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tinybert");
+        std::fs::create_dir(&path).unwrap();
+        for file in ["config.json", "tokenizer.json", "model.safetensors"] {
+            std::fs::copy(fixture().join(file), path.join(file)).unwrap();
+        }
+
+        let id = load(path.clone(), 32).id();
+        assert!(id.starts_with("tinybert@"), "{id}");
+        assert!(id.ends_with("/8"), "{id}");
+        assert_eq!(id, bert(32).id());
+
+        // Another revision of the model in the same directory.
+        let mut config: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path.join("config.json")).unwrap())
+                .unwrap();
+        config["layer_norm_eps"] = serde_json::json!(1e-6);
+        std::fs::write(path.join("config.json"), config.to_string()).unwrap();
+
+        assert_ne!(load(path, 32).id(), id);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::debug;
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::Embedder;
+
+fn default_path() -> PathBuf {
+    PathBuf::from(".savoir/embeddings.sqlite")
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    #[serde(default = "default_path")]
+    pub path: PathBuf,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: default_path(),
+            enabled: default_enabled(),
+        }
+    }
+}
+
+/// Usage of the cache for the vectors of an embedder.
+#[derive(Debug)]
+pub struct Stats {
+    pub model: String,
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+pub fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+fn hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Vectors already computed by an embedder, keyed by the hash of the embedded text and
+/// the id of the embedder.
+#[derive(Debug)]
+pub struct EmbeddingCache {
+    connection: Mutex<Connection>,
+}
+
+impl EmbeddingCache {
+    pub fn open(path: &PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS embeddings (
+                model TEXT NOT NULL,
+                hash TEXT NOT NULL,
+                vector BLOB NOT NULL,
+                used_at TEXT NOT NULL,
+                PRIMARY KEY (model, hash)
+            );
+            CREATE TABLE IF NOT EXISTS stats (
+                model TEXT PRIMARY KEY,
+                hits INTEGER NOT NULL,
+                misses INTEGER NOT NULL
+            );",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Returns the cached vectors of the hashes, by hash, and marks them as used.
+    fn get(&self, model: &str, hashes: &[String]) -> Result<HashMap<String, Vec<f32>>> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        let mut vectors = HashMap::new();
+        {
+            let mut select = transaction
+                .prepare_cached("SELECT vector FROM embeddings WHERE model = ?1 AND hash = ?2")?;
+            let mut touch = transaction.prepare_cached(
+                "UPDATE embeddings SET used_at = ?1 WHERE model = ?2 AND hash = ?3",
+            )?;
+
+            let now = now();
+            for hash in hashes {
+                let mut rows = select.query(params![model, hash])?;
+                if let Some(row) = rows.next()? {
+                    let vector: Vec<u8> = row.get(0)?;
+                    vectors.insert(hash.clone(), from_blob(&vector));
+                    touch.execute(params![now, model, hash])?;
+                }
+            }
+        }
+
+        transaction.commit()?;
+
+        Ok(vectors)
+    }
+
+    fn put(&self, model: &str, vectors: &[(String, Vec<f32>)], hits: usize) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        {
+            let mut insert = transaction.prepare_cached(
+                "INSERT INTO embeddings (model, hash, vector, used_at) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (model, hash) DO UPDATE SET
+                    vector = excluded.vector,
+                    used_at = excluded.used_at",
+            )?;
+
+            let now = now();
+            for (hash, vector) in vectors {
+                insert.execute(params![model, hash, to_blob(vector), now])?;
+            }
+        }
+
+        transaction.execute(
+            "INSERT INTO stats (model, hits, misses) VALUES (?1, ?2, ?3)
+             ON CONFLICT (model) DO UPDATE SET
+                hits = hits + excluded.hits,
+                misses = misses + excluded.misses",
+            params![model, hits, vectors.len()],
+        )?;
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<Vec<Stats>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT stats.model, count(embeddings.hash), coalesce(sum(length(embeddings.vector)), 0),
+                stats.hits, stats.misses
+             FROM stats LEFT JOIN embeddings ON embeddings.model = stats.model
+             GROUP BY stats.model
+             ORDER BY stats.model",
+        )?;
+
+        let stats = statement
+            .query_map([], |row| {
+                Ok(Stats {
+                    model: row.get(0)?,
+                    entries: row.get(1)?,
+                    bytes: row.get(2)?,
+                    hits: row.get(3)?,
+                    misses: row.get(4)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        Ok(stats)
+    }
+
+    /// Removes the vectors that were not used since the date, or all the vectors of a
+    /// model, returning the number of removed vectors.
+    pub fn prune(&self, unused_since: Option<DateTime<Utc>>, model: Option<&str>) -> Result<usize> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut removed = 0;
+        if let Some(unused_since) = unused_since {
+            removed += connection.execute(
+                "DELETE FROM embeddings WHERE used_at < ?1",
+                params![unused_since.to_rfc3339_opts(SecondsFormat::Secs, true)],
+            )?;
+        }
+
+        if let Some(model) = model {
+            removed +=
+                connection.execute("DELETE FROM embeddings WHERE model = ?1", params![model])?;
+            connection.execute("DELETE FROM stats WHERE model = ?1", params![model])?;
+        }
+
+        connection.execute("VACUUM", [])?;
+
+        Ok(removed)
+    }
+}
+
+/// Embeds only the texts whose vectors are not in the cache yet.
+#[derive(Debug)]
+pub struct CachedEmbedder {
+    embedder: Box<dyn Embedder>,
+    cache: EmbeddingCache,
+}
+
+impl CachedEmbedder {
+    pub fn new(embedder: Box<dyn Embedder>, cache: EmbeddingCache) -> Self {
+        Self { embedder, cache }
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for CachedEmbedder {
+    fn model(&self) -> &str {
+        self.embedder.model()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.embedder.dimensions()
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let model = self.embedder.id();
+        let hashes: Vec<String> = texts.iter().map(|text| hash(text)).collect();
+        let mut vectors = self.cache.get(&model, &hashes)?;
+
+        // Identical texts in the same call are embedded once.
+        let mut missing: Vec<(String, String)> = vec![];
+        for (hash, text) in hashes.iter().zip(texts) {
+            if !vectors.contains_key(hash) && !missing.iter().any(|(h, _)| h == hash) {
+                missing.push((hash.clone(), text.clone()));
+            }
+        }
+
+        let hits = vectors.len();
+        let mut computed = vec![];
+        if !missing.is_empty() {
+            let missing_texts: Vec<String> = missing.iter().map(|(_, text)| text.clone()).collect();
+            let embeddings = self.embedder.embed(&missing_texts).await?;
+            computed = missing
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .zip(embeddings)
+                .collect();
+        }
+
+        self.cache.put(&model, &computed, hits)?;
+        debug!(
+            "Embedded {} texts with {model}, {hits} from the cache",
+            texts.len()
+        );
+
+        vectors.extend(computed);
+        Ok(hashes.iter().map(|hash| vectors[hash].clone()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    /// Embeds a text as its length.
+    #[derive(Debug, Default)]
+    struct Lengths;
+
+    #[async_trait::async_trait]
+    impl Embedder for Lengths {
+        fn model(&self) -> &str {
+            "lengths"
+        }
+
+        fn dimensions(&self) -> usize {
+            1
+        }
+
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|text| vec![text.len() as f32]).collect())
+        }
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    fn cache(dir: &tempfile::TempDir) -> EmbeddingCache {
+        EmbeddingCache::open(&dir.path().join("embeddings.sqlite")).unwrap()
+    }
+
+    /// Marks the vector of a text as last used at a date.
+    fn used_at(cache: &EmbeddingCache, text: &str, date: DateTime<Utc>) {
+        cache
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE embeddings SET used_at = ?1 WHERE hash = ?2",
+                params![date.to_rfc3339_opts(SecondsFormat::Secs, true), hash(text)],
+            )
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn embeds_only_the_texts_missing_from_the_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let embedder = CachedEmbedder::new(Box::<Lengths>::default(), cache(&dir));
+
+        let vectors = embedder.embed(&texts(&["a", "bb", "a"])).await.unwrap();
+        assert_eq!(vectors, [vec![1.0], vec![2.0], vec![1.0]]);
+
+        let vectors = embedder.embed(&texts(&["bb", "ccc"])).await.unwrap();
+        assert_eq!(vectors, [vec![2.0], vec![3.0]]);
+
+        let stats = embedder.cache.stats().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].model, "lengths/1");
+        assert_eq!(stats[0].entries, 3);
+        assert_eq!(stats[0].bytes, 12);
+        assert_eq!(stats[0].hits, 1);
+        assert_eq!(stats[0].misses, 3);
+    }
+
+    #[tokio::test]
+    async fn prunes_the_vectors_unused_since_a_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let embedder = CachedEmbedder::new(Box::<Lengths>::default(), cache(&dir));
+        embedder.embed(&texts(&["old", "recent"])).await.unwrap();
+
+        let now = Utc::now();
+        used_at(&embedder.cache, "old", now - Duration::days(40));
+        used_at(&embedder.cache, "recent", now - Duration::days(10));
+
+        let removed = embedder
+            .cache
+            .prune(Some(now - Duration::days(30)), None)
+            .unwrap();
+        assert_eq!(removed, 1);
+
+        // Only the pruned vector is embedded again.
+        embedder.embed(&texts(&["old", "recent"])).await.unwrap();
+        let stats = embedder.cache.stats().unwrap();
+        assert_eq!((stats[0].hits, stats[0].misses), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn using_a_vector_postpones_its_pruning() {
+        let dir = tempfile::tempdir().unwrap();
+        let embedder = CachedEmbedder::new(Box::<Lengths>::default(), cache(&dir));
+        embedder.embed(&texts(&["text"])).await.unwrap();
+
+        let now = Utc::now();
+        used_at(&embedder.cache, "text", now - Duration::days(40));
+        embedder.embed(&texts(&["text"])).await.unwrap();
+
+        let removed = embedder
+            .cache
+            .prune(Some(now - Duration::days(30)), None)
+            .unwrap();
+        assert_eq!(removed, 0);
+        assert_eq!(embedder.cache.stats().unwrap()[0].entries, 1);
+    }
+
+    #[tokio::test]
+    async fn prunes_every_vector_of_a_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = cache(&dir);
+        cache
+            .put("a/1", &[(hash("x"), vec![1.0]), (hash("y"), vec![2.0])], 0)
+            .unwrap();
+        cache.put("b/1", &[(hash("x"), vec![3.0])], 0).unwrap();
+
+        assert_eq!(cache.prune(None, Some("a/1")).unwrap(), 2);
+
+        let stats = cache.stats().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].model, "b/1");
+        assert_eq!(stats[0].entries, 1);
+    }
+}
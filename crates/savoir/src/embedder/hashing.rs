@@ -0,0 +1,85 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::Embedder;
+
+fn default_dimensions() -> usize {
+    256
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "default_dimensions")]
+    dimensions: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dimensions: default_dimensions(),
+        }
+    }
+}
+
+/// Embeds a text by hashing its words into a fixed number of dimensions. It needs no
+/// model and captures shared vocabulary only, standing in for a real embedding model.
+#[derive(Debug)]
+pub struct Hashing {
+    dimensions: usize,
+}
+
+impl From<Config> for Hashing {
+    fn from(value: Config) -> Self {
+        Self {
+            dimensions: value.dimensions.max(1),
+        }
+    }
+}
+
+impl Hashing {
+    fn vectorize(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimensions];
+
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+        {
+            // FNV-1a, stable across runs and platforms unlike the standard hasher.
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |hash, byte| {
+                    (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+                });
+
+            let index = (hash % vector.len() as u64) as usize;
+            vector[index] += if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        }
+
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+
+        vector
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for Hashing {
+    fn model(&self) -> &str {
+        "hashing"
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.vectorize(text)).collect())
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+use super::Embedder;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot embed texts: {0}")]
+    Embed(String),
+    #[error("the embedding model returned {0} dimensions instead of {1}")]
+    Dimensions(usize, usize),
+    #[error("the dimensions of {0} are unknown, configure them")]
+    UnknownDimensions(String),
+}
+
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+
+// Number of texts sent in a single request.
+const BATCH_SIZE: usize = 100;
+
+// Size of the vectors of the OpenAI models when they are not shortened.
+fn native_dimensions(model: &str) -> Option<usize> {
+    match model {
+        "text-embedding-ada-002" | "text-embedding-3-small" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    api_key: String,
+    model: String,
+    /// Size the text-embedding-3 models shorten their vectors to. Older models, like
+    /// text-embedding-ada-002, reject it.
+    #[serde(default)]
+    dimensions: Option<usize>,
+}
+
+/// An endpoint implementing the embeddings API of OpenAI, like vLLM, Ollama or LocalAI.
+#[derive(Deserialize, Debug)]
+pub struct CompatibleConfig {
+    url: String,
+    #[serde(default)]
+    api_key: Option<String>,
+    model: String,
+    /// Size of the vectors of the model, which is not asked to shorten them.
+    dimensions: usize,
+}
+
+pub struct OpenAi {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+    dimensions: usize,
+    /// Whether the endpoint is asked for vectors of `dimensions`.
+    shorten: bool,
+}
+
+impl std::fmt::Debug for OpenAi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAi")
+            .field("url", &self.url)
+            .field("model", &self.model)
+            .finish()
+    }
+}
+
+impl TryFrom<Config> for OpenAi {
+    type Error = Error;
+
+    fn try_from(value: Config) -> Result<Self, Self::Error> {
+        let dimensions = value
+            .dimensions
+            .or_else(|| native_dimensions(&value.model))
+            .ok_or_else(|| Error::UnknownDimensions(value.model.clone()))?;
+
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url: OPENAI_URL.to_string(),
+            api_key: Some(value.api_key),
+            model: value.model,
+            dimensions,
+            shorten: value.dimensions.is_some(),
+        })
+    }
+}
+
+impl From<CompatibleConfig> for OpenAi {
+    fn from(value: CompatibleConfig) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: value.url.trim_end_matches('/').to_string(),
+            api_key: value.api_key,
+            model: value.model,
+            dimensions: value.dimensions,
+            shorten: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Embeddings {
+    data: Vec<Embedding>,
+}
+
+#[derive(Deserialize)]
+struct Embedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+impl OpenAi {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, Error> {
+        let mut body = json!({ "model": self.model, "input": texts });
+        if self.shorten {
+            body["dimensions"] = json!(self.dimensions);
+        }
+
+        let mut request = self
+            .http
+            .post(format!("{}/embeddings", self.url))
+            .json(&body);
+
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let res = request
+            .send()
+            .await
+            .map_err(|e| Error::Embed(e.to_string()))?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(Error::Embed(format!("status {status} received: {body}")));
+        }
+
+        let mut embeddings: Embeddings =
+            res.json().await.map_err(|e| Error::Embed(e.to_string()))?;
+        embeddings.data.sort_by_key(|embedding| embedding.index);
+
+        if embeddings.data.len() != texts.len() {
+            return Err(Error::Embed(format!(
+                "{} embeddings returned for {} texts",
+                embeddings.data.len(),
+                texts.len()
+            )));
+        }
+
+        embeddings
+            .data
+            .into_iter()
+            .map(|embedding| match embedding.embedding.len() {
+                len if len == self.dimensions => Ok(embedding.embedding),
+                len => Err(Error::Dimensions(len, self.dimensions)),
+            })
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Embedder for OpenAi {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(BATCH_SIZE) {
+            vectors.extend(self.embed_batch(batch).await?);
+        }
+
+        Ok(vectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::Value;
+
+    use super::*;
+
+    type Requests = Arc<Mutex<Vec<Value>>>;
+
+    /// Embeds every text to a vector of the dimensions asked, 3 by default, recording
+    /// the requests.
+    async fn embeddings(State(requests): State<Requests>, Json(body): Json<Value>) -> Json<Value> {
+        let dimensions = body["dimensions"].as_u64().unwrap_or(3) as usize;
+        let data: Vec<_> = body["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(index, _)| json!({ "index": index, "embedding": vec![0.5; dimensions] }))
+            .collect();
+        requests.lock().unwrap().push(body);
+
+        Json(json!({ "data": data }))
+    }
+
+    fn serve() -> (String, Requests) {
+        let requests = Requests::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+        let app = Router::new()
+            .route("/v1/embeddings", post(embeddings))
+            .with_state(requests.clone());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn asks_openai_for_the_configured_dimensions() {
+        let (url, requests) = serve();
+        let openai = OpenAi {
+            url,
+            ..OpenAi::try_from(Config {
+                api_key: "key".to_string(),
+                model: "text-embedding-3-small".to_string(),
+                dimensions: Some(256),
+            })
+            .unwrap()
+        };
+
+        let vectors = openai.embed(&["a".to_string()]).await.unwrap();
+
+        assert_eq!(vectors[0].len(), 256);
+        assert_eq!(requests.lock().unwrap()[0]["dimensions"], 256);
+    }
+
+    #[test]
+    fn defaults_to_the_dimensions_of_the_model() {
+        let openai = |model: &str, dimensions| {
+            OpenAi::try_from(Config {
+                api_key: "key".to_string(),
+                model: model.to_string(),
+                dimensions,
+            })
+        };
+
+        let ada = openai("text-embedding-ada-002", None).unwrap();
+        assert_eq!((ada.dimensions(), ada.shorten), (1536, false));
+        let large = openai("text-embedding-3-large", None).unwrap();
+        assert_eq!((large.dimensions(), large.shorten), (3072, false));
+        assert!(matches!(
+            openai("text-embedding-4", None),
+            Err(Error::UnknownDimensions(_))
+        ));
+        assert_eq!(
+            openai("text-embedding-4", Some(512)).unwrap().dimensions(),
+            512
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_ask_for_dimensions_unless_configured() {
+        let (url, requests) = serve();
+        let openai = OpenAi {
+            url,
+            // Vectors of the stand-in server have 3 dimensions unless asked otherwise.
+            dimensions: 3,
+            ..OpenAi::try_from(Config {
+                api_key: "key".to_string(),
+                model: "text-embedding-ada-002".to_string(),
+                dimensions: None,
+            })
+            .unwrap()
+        };
+
+        openai.embed(&["a".to_string()]).await.unwrap();
+
+        assert!(requests.lock().unwrap()[0].get("dimensions").is_none());
+    }
+
+    #[tokio::test]
+    async fn compatible_endpoints_keep_their_dimensions() {
+        let (url, requests) = serve();
+        let compatible = |dimensions| {
+            OpenAi::from(CompatibleConfig {
+                url: url.clone(),
+                api_key: None,
+                model: "nomic-embed-text".to_string(),
+                dimensions,
+            })
+        };
+
+        let vectors = compatible(3).embed(&["a".to_string()]).await.unwrap();
+        assert_eq!(vectors[0].len(), 3);
+        assert!(requests.lock().unwrap()[0].get("dimensions").is_none());
+
+        let error = compatible(256).embed(&["a".to_string()]).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::Dimensions(3, 256))
+        ));
+    }
+}
@@ -0,0 +1,62 @@
+use std::{collections::HashMap, fmt::Debug, path::PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use self::{json::JsonLedger, sqlite::SqliteLedger};
+
+pub mod json;
+pub mod sqlite;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Config {
+    Json { path: PathBuf },
+    Sqlite { path: PathBuf },
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::Json {
+            path: PathBuf::from(".savoir/ledger.json"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub modified_at: Option<DateTime<Utc>>,
+    pub content_hash: String,
+    /// Id of the chunker that split the document.
+    pub chunking: String,
+}
+
+pub type Entries = HashMap<String, Entry>;
+
+#[async_trait::async_trait]
+pub trait Ledger: Send + Sync + Debug {
+    async fn entries(&self, source: &str) -> Result<Entries>;
+    async fn put(&self, source: &str, id: &str, entry: Entry) -> Result<()>;
+    async fn remove(&self, source: &str, id: &str) -> Result<()>;
+    async fn cursor(&self, source: &str) -> Result<Option<String>>;
+    async fn set_cursor(&self, source: &str, cursor: &str) -> Result<()>;
+    /// Documents the last synchronization could not store, fetched again by the next
+    /// one whatever the cursor.
+    async fn failed(&self, source: &str) -> Result<Vec<String>>;
+    async fn set_failed(&self, source: &str, ids: &[String]) -> Result<()>;
+    async fn flush(&self) -> Result<()>;
+}
+
+impl TryFrom<Config> for Box<dyn Ledger> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Config) -> Result<Self, Self::Error> {
+        let ledger: Box<dyn Ledger> = match value {
+            Config::Json { path } => Box::new(JsonLedger::open(path)?),
+            Config::Sqlite { path } => Box::new(SqliteLedger::open(path)?),
+        };
+
+        Ok(ledger)
+    }
+}
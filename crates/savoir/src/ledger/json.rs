@@ -0,0 +1,136 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{Entries, Entry, Ledger};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    sources: HashMap<String, Entries>,
+    #[serde(default)]
+    cursors: HashMap<String, String>,
+    #[serde(default)]
+    failed: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug)]
+pub struct JsonLedger {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl JsonLedger {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let state = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => State::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Ledger for JsonLedger {
+    async fn entries(&self, source: &str) -> Result<Entries> {
+        let state = self.state.lock().unwrap();
+        Ok(state.sources.get(source).cloned().unwrap_or_default())
+    }
+
+    async fn put(&self, source: &str, id: &str, entry: Entry) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .sources
+            .entry(source.to_string())
+            .or_default()
+            .insert(id.to_string(), entry);
+
+        Ok(())
+    }
+
+    async fn remove(&self, source: &str, id: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entries) = state.sources.get_mut(source) {
+            entries.remove(id);
+        }
+
+        Ok(())
+    }
+
+    async fn cursor(&self, source: &str) -> Result<Option<String>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.cursors.get(source).cloned())
+    }
+
+    async fn set_cursor(&self, source: &str, cursor: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.cursors.insert(source.to_string(), cursor.to_string());
+
+        Ok(())
+    }
+
+    async fn failed(&self, source: &str) -> Result<Vec<String>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.failed.get(source).cloned().unwrap_or_default())
+    }
+
+    async fn set_failed(&self, source: &str, ids: &[String]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match ids.is_empty() {
+            true => state.failed.remove(source),
+            false => state.failed.insert(source.to_string(), ids.to_vec()),
+        };
+
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let content = serde_json::to_string(&*self.state.lock().unwrap())?;
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let tmp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn persists_entries_cursors_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.json");
+        let entry = Entry {
+            modified_at: None,
+            content_hash: "abc".to_string(),
+            chunking: "none".to_string(),
+        };
+
+        let ledger = JsonLedger::open(path.clone()).unwrap();
+        ledger.put("docs", "guide.md", entry.clone()).await.unwrap();
+        ledger.set_cursor("docs", "42").await.unwrap();
+        ledger
+            .set_failed("docs", &["faq.md".to_string()])
+            .await
+            .unwrap();
+        ledger.flush().await.unwrap();
+
+        let ledger = JsonLedger::open(path).unwrap();
+        assert_eq!(ledger.entries("docs").await.unwrap()["guide.md"], entry);
+        assert_eq!(ledger.cursor("docs").await.unwrap().as_deref(), Some("42"));
+        assert_eq!(ledger.failed("docs").await.unwrap(), ["faq.md"]);
+        assert!(ledger.entries("other").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{Entries, Entry, Ledger};
+
+#[derive(Debug)]
+pub struct SqliteLedger {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteLedger {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS ledger (
+                source TEXT NOT NULL,
+                external_id TEXT NOT NULL,
+                modified_at TEXT,
+                content_hash TEXT NOT NULL,
+                chunking TEXT NOT NULL,
+                PRIMARY KEY (source, external_id)
+            );
+            CREATE TABLE IF NOT EXISTS cursors (
+                source TEXT PRIMARY KEY,
+                cursor TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS failures (
+                source TEXT NOT NULL,
+                external_id TEXT NOT NULL,
+                PRIMARY KEY (source, external_id)
+            );",
+        )?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Ledger for SqliteLedger {
+    async fn entries(&self, source: &str) -> Result<Entries> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT external_id, modified_at, content_hash, chunking FROM ledger WHERE source = ?1",
+        )?;
+
+        let rows = statement.query_map(params![source], |row| {
+            let modified_at: Option<String> = row.get(1)?;
+            let entry = Entry {
+                modified_at: modified_at
+                    .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                    .map(|date| date.with_timezone(&Utc)),
+                content_hash: row.get(2)?,
+                chunking: row.get(3)?,
+            };
+
+            Ok((row.get(0)?, entry))
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    async fn put(&self, source: &str, id: &str, entry: Entry) -> Result<()> {
+        let modified_at = entry
+            .modified_at
+            .map(|date| date.to_rfc3339_opts(SecondsFormat::AutoSi, true));
+
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO ledger (source, external_id, modified_at, content_hash, chunking)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (source, external_id)
+             DO UPDATE SET modified_at = excluded.modified_at,
+                content_hash = excluded.content_hash, chunking = excluded.chunking",
+            params![source, id, modified_at, entry.content_hash, entry.chunking],
+        )?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, source: &str, id: &str) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "DELETE FROM ledger WHERE source = ?1 AND external_id = ?2",
+            params![source, id],
+        )?;
+
+        Ok(())
+    }
+
+    async fn cursor(&self, source: &str) -> Result<Option<String>> {
+        let connection = self.connection.lock().unwrap();
+        let cursor = connection
+            .query_row(
+                "SELECT cursor FROM cursors WHERE source = ?1",
+                params![source],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(cursor)
+    }
+
+    async fn set_cursor(&self, source: &str, cursor: &str) -> Result<()> {
+        self.connection.lock().unwrap().execute(
+            "INSERT INTO cursors (source, cursor) VALUES (?1, ?2)
+             ON CONFLICT (source) DO UPDATE SET cursor = excluded.cursor",
+            params![source, cursor],
+        )?;
+
+        Ok(())
+    }
+
+    async fn failed(&self, source: &str) -> Result<Vec<String>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT external_id FROM failures WHERE source = ?1")?;
+
+        let ids = statement
+            .query_map(params![source], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(ids)
+    }
+
+    async fn set_failed(&self, source: &str, ids: &[String]) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM failures WHERE source = ?1", params![source])?;
+        for id in ids {
+            transaction.execute(
+                "INSERT OR IGNORE INTO failures (source, external_id) VALUES (?1, ?2)",
+                params![source, id],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
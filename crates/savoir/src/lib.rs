@@ -1,11 +1,16 @@
 mod agent;
 pub mod app;
+mod bert;
+mod chunking;
 mod conversation;
 mod conversation_store;
 mod datasource;
 mod document;
-mod document_store;
+pub mod document_store;
+pub mod embedder;
 mod integration;
 pub mod interals;
+mod ledger;
 mod llm;
 mod message;
+mod reranker;
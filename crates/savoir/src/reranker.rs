@@ -0,0 +1,67 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use anyhow::Result;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{document_store::ScoredDocument, llm::Llm};
+
+use self::{cross_encoder::CrossEncoder, judge::Judge, none::NoReranker};
+
+pub mod cross_encoder;
+pub mod judge;
+pub mod none;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("The llm {0} of the reranker does not exist in the configuration")]
+    LlmNotFound(String),
+}
+
+/// Orders the documents retrieved for an agent before they are given to its LLM.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    #[serde(flatten)]
+    pub provider: Provider,
+    /// Number of documents kept after re-ranking, all of them by default.
+    #[serde(default)]
+    pub top: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Provider {
+    #[default]
+    None,
+    CrossEncoder(cross_encoder::Config),
+    Llm(judge::Config),
+}
+
+#[async_trait::async_trait]
+pub trait Reranker: Send + Sync + Debug {
+    /// Scores the documents on their relevance to the question, between 0 and 1, and
+    /// returns them best first.
+    async fn rerank(
+        &self,
+        question: &str,
+        documents: Vec<ScoredDocument>,
+    ) -> Result<Vec<ScoredDocument>>;
+}
+
+impl Provider {
+    /// Creates the reranker, LLM judges using one of the configured LLMs.
+    pub fn build(self, llms: &HashMap<String, Arc<Box<dyn Llm>>>) -> Result<Box<dyn Reranker>> {
+        let reranker: Box<dyn Reranker> = match self {
+            Provider::None => Box::new(NoReranker),
+            Provider::CrossEncoder(config) => Box::new(CrossEncoder::try_from(config)?),
+            Provider::Llm(config) => {
+                let llm = llms
+                    .get(&config.llm)
+                    .ok_or_else(|| Error::LlmNotFound(config.llm.clone()))?;
+                Box::new(Judge::new(config, llm.clone()))
+            }
+        };
+
+        Ok(reranker)
+    }
+}
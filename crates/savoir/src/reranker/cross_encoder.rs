@@ -0,0 +1,257 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use candle_core::IndexOp;
+use candle_nn::{linear, ops, Linear, Module};
+use candle_transformers::models::bert::BertModel;
+use rayon::ThreadPool;
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+use tokenizers::Tokenizer;
+
+use crate::{
+    bert::{self, Checkpoint},
+    document_store::{embedding_text, ScoredDocument},
+};
+
+use super::Reranker;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot load reranking model: {0}")]
+    LoadModel(String),
+    #[error("cannot rerank documents: {0}")]
+    Rerank(String),
+}
+
+fn default_batch_size() -> usize {
+    16
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Directory of a BERT cross-encoder, like ms-marco-MiniLM-L-6-v2, with its
+    /// `config.json`, `tokenizer.json` and `model.safetensors` files.
+    path: PathBuf,
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    #[serde(default)]
+    threads: Option<usize>,
+}
+
+struct Model {
+    bert: BertModel,
+    pooler: Linear,
+    classifier: Linear,
+    tokenizer: Tokenizer,
+}
+
+/// Runs a BERT sequence classifier on the CPU, reading the question and each document
+/// together.
+pub struct CrossEncoder {
+    model: Arc<Model>,
+    pool: Arc<ThreadPool>,
+    name: String,
+    batch_size: usize,
+}
+
+impl std::fmt::Debug for CrossEncoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CrossEncoder")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl TryFrom<Config> for CrossEncoder {
+    type Error = Error;
+
+    fn try_from(value: Config) -> Result<Self, Self::Error> {
+        let load =
+            |e: &dyn std::fmt::Display| Error::LoadModel(format!("{}: {e}", value.path.display()));
+
+        let checkpoint = Checkpoint::load(&value.path).map_err(|e| load(&e))?;
+        let (config, weights) = (&checkpoint.config, &checkpoint.weights);
+        let labels = checkpoint
+            .raw_config
+            .get("id2label")
+            .and_then(Value::as_object)
+            .map_or(1, |labels| labels.len());
+
+        let bert = BertModel::load(weights.pp("bert"), config).map_err(|e| load(&e))?;
+        let pooler = linear(
+            config.hidden_size,
+            config.hidden_size,
+            weights.pp("bert.pooler.dense"),
+        )
+        .map_err(|e| load(&e))?;
+        let classifier =
+            linear(config.hidden_size, labels, weights.pp("classifier")).map_err(|e| load(&e))?;
+        let pool = bert::thread_pool("reranker", value.threads).map_err(|e| load(&e))?;
+
+        Ok(Self {
+            model: Arc::new(Model {
+                bert,
+                pooler,
+                classifier,
+                tokenizer: checkpoint.tokenizer,
+            }),
+            pool: Arc::new(pool),
+            name: checkpoint.name,
+            batch_size: value.batch_size.max(1),
+        })
+    }
+}
+
+impl Model {
+    fn score(&self, pairs: Vec<(String, String)>) -> Result<Vec<f64>, Error> {
+        let rerank = |e: &dyn std::fmt::Display| Error::Rerank(e.to_string());
+
+        let encodings = self
+            .tokenizer
+            .encode_batch(pairs, true)
+            .map_err(|e| rerank(&e))?;
+
+        let (ids, type_ids, mask) = bert::batch(&encodings).map_err(|e| rerank(&e))?;
+
+        let output = self
+            .bert
+            .forward(&ids, &type_ids, Some(&mask))
+            .map_err(|e| rerank(&e))?;
+
+        // Classifies the first token, with the probability of the last label, the
+        // relevant one, as score.
+        let scores = (|| {
+            let pooled = self.pooler.forward(&output.i((.., 0))?)?.tanh()?;
+            let logits = self.classifier.forward(&pooled)?;
+
+            let probabilities = match logits.dim(1)? {
+                1 => ops::sigmoid(&logits)?,
+                _ => ops::softmax(&logits, 1)?,
+            };
+            let last = probabilities.dim(1)? - 1;
+            probabilities.i((.., last))?.to_vec1::<f32>()
+        })()
+        .map_err(|e| rerank(&e))?;
+
+        Ok(scores.into_iter().map(f64::from).collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl Reranker for CrossEncoder {
+    async fn rerank(
+        &self,
+        question: &str,
+        mut documents: Vec<ScoredDocument>,
+    ) -> Result<Vec<ScoredDocument>> {
+        let mut scores = Vec::with_capacity(documents.len());
+
+        for batch in documents.chunks(self.batch_size) {
+            let model = self.model.clone();
+            let pool = self.pool.clone();
+            let pairs = batch
+                .iter()
+                .map(|document| (question.to_string(), embedding_text(&document.document)))
+                .collect();
+
+            let batch_scores =
+                tokio::task::spawn_blocking(move || pool.install(|| model.score(pairs))).await??;
+            scores.extend(batch_scores);
+        }
+
+        for (document, score) in documents.iter_mut().zip(scores) {
+            document.score = score;
+        }
+        documents.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::Document;
+
+    use super::*;
+
+    /// A question, documents as name and content, and the scores a reference
+    /// implementation gives them, written with the tiny random model by `generate.py`.
+    #[derive(Deserialize)]
+    struct Expected {
+        question: String,
+        documents: Vec<(String, String)>,
+        scores: Vec<f64>,
+    }
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tinybert-cross-encoder")
+    }
+
+    fn expected() -> Expected {
+        let expected = std::fs::read_to_string(fixture().join("expected.json")).unwrap();
+        serde_json::from_str(&expected).unwrap()
+    }
+
+    fn cross_encoder(batch_size: usize) -> CrossEncoder {
+        CrossEncoder::try_from(Config {
+            path: fixture(),
+            batch_size,
+            threads: Some(1),
+        })
+        .unwrap()
+    }
+
+    fn documents(expected: &Expected) -> Vec<ScoredDocument> {
+        expected
+            .documents
+            .iter()
+            .map(|(name, content)| ScoredDocument {
+                document: Document {
+                    id: name.clone(),
+                    name: name.clone(),
+                    content: content.clone(),
+                    url: None,
+                    metadata: Default::default(),
+                    provenance: Default::default(),
+                    chunk: None,
+                },
+                score: 0.0,
+                distance: None,
+                highlight: None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn scores_like_the_reference_implementation() {
+        let expected = expected();
+
+        // Batches of 2 pad the shorter pair of the first one.
+        for batch_size in [1, 2] {
+            let documents = cross_encoder(batch_size)
+                .rerank(&expected.question, documents(&expected))
+                .await
+                .unwrap();
+
+            let mut reference: Vec<(&str, f64)> = expected
+                .documents
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .zip(expected.scores.iter().copied())
+                .collect();
+            reference.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+            assert_eq!(documents.len(), reference.len());
+            for (document, (name, score)) in documents.iter().zip(reference) {
+                assert_eq!(document.document.name, name);
+                assert!(
+                    (document.score - score).abs() < 1e-5,
+                    "{name}: score {} instead of {score}",
+                    document.score
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use log::warn;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    conversation::Conversation,
+    document_store::ScoredDocument,
+    llm::Llm,
+    message::{Message, Role},
+};
+
+use super::Reranker;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot read the relevance judgements of the llm: {0}")]
+    Answer(String),
+}
+
+fn default_batch_size() -> usize {
+    10
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Name of the LLM judging the documents, from the `llms` section.
+    pub llm: String,
+    /// Number of documents judged in a single request.
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+}
+
+// Characters of the content of each document given to the LLM.
+const CONTENT_LENGTH: usize = 1000;
+
+// Relevance given by the LLM to the best documents.
+const MAX_RELEVANCE: f64 = 10.0;
+
+const PROMPT: &str = "You judge how relevant documents are to a question. Give each document a score from 0, for a document unrelated to the question, to 10, for a document that answers it. Answer only with a JSON array with one object per document, like [{\"id\": 1, \"score\": 7}].";
+
+#[derive(Deserialize)]
+struct Judgement {
+    id: usize,
+    score: f64,
+}
+
+/// Reads the first array of judgements of the answer. LLMs tend to wrap it in Markdown
+/// or to write references like [1] before it.
+fn judgements(answer: &str) -> Option<Vec<Judgement>> {
+    answer.match_indices('[').find_map(|(start, _)| {
+        serde_json::Deserializer::from_str(&answer[start..])
+            .into_iter::<Vec<Judgement>>()
+            .next()?
+            .ok()
+    })
+}
+
+/// Asks an LLM to score the relevance of the documents to the question.
+#[derive(Debug)]
+pub struct Judge {
+    llm: Arc<Box<dyn Llm>>,
+    batch_size: usize,
+}
+
+impl Judge {
+    pub fn new(config: Config, llm: Arc<Box<dyn Llm>>) -> Self {
+        Self {
+            llm,
+            batch_size: config.batch_size.max(1),
+        }
+    }
+
+    async fn judge(&self, question: &str, documents: &[ScoredDocument]) -> Result<Vec<f64>> {
+        let documents_text: Vec<String> = documents
+            .iter()
+            .enumerate()
+            .map(|(i, document)| {
+                let content: String = document
+                    .document
+                    .content
+                    .chars()
+                    .take(CONTENT_LENGTH)
+                    .collect();
+                format!("[{}] {}\n{content}", i + 1, document.document.name)
+            })
+            .collect();
+
+        let conversation = Conversation(vec![
+            Message::new(Role::System, PROMPT),
+            Message::new(
+                Role::User,
+                &format!(
+                    "Question: {question}\n\nDocuments:\n\n{}",
+                    documents_text.join("\n\n")
+                ),
+            ),
+        ]);
+        let answer = self.llm.chat(conversation).await?;
+        let judgements = judgements(&answer).ok_or(Error::Answer(answer))?;
+
+        let scores: HashMap<usize, f64> = judgements
+            .into_iter()
+            .map(|judgement| (judgement.id, judgement.score))
+            .collect();
+
+        Ok((1..=documents.len())
+            .map(|id| match scores.get(&id) {
+                Some(score) => score.clamp(0.0, MAX_RELEVANCE) / MAX_RELEVANCE,
+                None => {
+                    warn!("The llm did not judge the document {id}, it is ranked last");
+                    0.0
+                }
+            })
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl Reranker for Judge {
+    async fn rerank(
+        &self,
+        question: &str,
+        mut documents: Vec<ScoredDocument>,
+    ) -> Result<Vec<ScoredDocument>> {
+        let mut scores = Vec::with_capacity(documents.len());
+        for batch in documents.chunks(self.batch_size) {
+            match self.judge(question, batch).await {
+                Ok(batch_scores) => scores.extend(batch_scores),
+                Err(e) => {
+                    warn!("Cannot judge the documents, they keep the order of the store: {e}");
+                    return Ok(documents);
+                }
+            }
+        }
+
+        for (document, score) in documents.iter_mut().zip(scores) {
+            document.score = score;
+        }
+        // Documents judged equally keep the order of the document store.
+        documents.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::document::Document;
+
+    use super::*;
+
+    /// Answers with the queued replies, failing once they run out.
+    #[derive(Debug)]
+    struct Replies(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl Llm for Replies {
+        async fn chat(&self, _conversation: Conversation) -> Result<String> {
+            let mut replies = self.0.lock().unwrap();
+            if replies.is_empty() {
+                anyhow::bail!("the llm is unavailable");
+            }
+            Ok(replies.remove(0))
+        }
+    }
+
+    fn judge(replies: &[&str], batch_size: usize) -> Judge {
+        let replies = replies.iter().map(|reply| reply.to_string()).collect();
+        Judge::new(
+            Config {
+                llm: "judge".to_string(),
+                batch_size,
+            },
+            Arc::new(Box::new(Replies(Mutex::new(replies)))),
+        )
+    }
+
+    fn documents(ids: &[&str]) -> Vec<ScoredDocument> {
+        ids.iter()
+            .map(|id| ScoredDocument {
+                document: Document {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    content: String::new(),
+                    url: None,
+                    metadata: Default::default(),
+                    provenance: Default::default(),
+                    chunk: None,
+                },
+                score: 0.5,
+                distance: None,
+                highlight: None,
+            })
+            .collect()
+    }
+
+    fn ids(documents: &[ScoredDocument]) -> Vec<&str> {
+        documents.iter().map(|d| d.document.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn ranks_documents_by_judgement() {
+        let judge = judge(&[r#"[{"id": 1, "score": 2}, {"id": 2, "score": 9}]"#], 10);
+
+        let documents = judge
+            .rerank("question", documents(&["a", "b", "c"]))
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&documents), ["b", "a", "c"]);
+        assert_eq!(documents[0].score, 0.9);
+        assert_eq!(documents[2].score, 0.0);
+    }
+
+    #[tokio::test]
+    async fn judges_documents_in_batches() {
+        let judge = judge(
+            &[
+                r#"[{"id": 1, "score": 3}, {"id": 2, "score": 4}]"#,
+                r#"[{"id": 1, "score": 8}]"#,
+            ],
+            2,
+        );
+
+        let documents = judge
+            .rerank("question", documents(&["a", "b", "c"]))
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&documents), ["c", "b", "a"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_store_order_when_the_llm_fails() {
+        let judge = judge(&[r#"[{"id": 1, "score": 3}, {"id": 2, "score": 4}]"#], 2);
+
+        let documents = judge
+            .rerank("question", documents(&["a", "b", "c"]))
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&documents), ["a", "b", "c"]);
+        assert!(documents.iter().all(|d| d.score == 0.5));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_store_order_when_the_answer_has_no_judgements() {
+        let judge = judge(&["Both documents are relevant [1][2]."], 10);
+
+        let documents = judge
+            .rerank("question", documents(&["a", "b"]))
+            .await
+            .unwrap();
+
+        assert_eq!(ids(&documents), ["a", "b"]);
+    }
+
+    #[test]
+    fn reads_judgements_after_references_and_in_markdown() {
+        let answer = "Document [2] answers the question, [1] does not:\n\n```json\n[{\"id\": 1, \"score\": 1}, {\"id\": 2, \"score\": 10}]\n```\n\nSee [2].";
+
+        let judgements = judgements(answer).unwrap();
+
+        let scores: Vec<(usize, f64)> = judgements.iter().map(|j| (j.id, j.score)).collect();
+        assert_eq!(scores, [(1, 1.0), (2, 10.0)]);
+    }
+}
@@ -0,0 +1,20 @@
+use anyhow::Result;
+
+use crate::document_store::ScoredDocument;
+
+use super::Reranker;
+
+/// Keeps the order and the scores of the document store.
+#[derive(Debug)]
+pub struct NoReranker;
+
+#[async_trait::async_trait]
+impl Reranker for NoReranker {
+    async fn rerank(
+        &self,
+        _question: &str,
+        documents: Vec<ScoredDocument>,
+    ) -> Result<Vec<ScoredDocument>> {
+        Ok(documents)
+    }
+}
@@ -0,0 +1,297 @@
+"""Generates a tiny random BERT model and the vectors it should embed texts to, and a
+tiny random cross-encoder in ../tinybert-cross-encoder with the scores it should give.
+
+The reference forward pass only uses the standard library, independently of the
+candle implementation under test. Run from this directory with `python3 generate.py`.
+"""
+
+import json
+import math
+import os
+import random
+import struct
+
+CONFIG = {
+    "vocab_size": 30,
+    "hidden_size": 8,
+    "num_hidden_layers": 2,
+    "num_attention_heads": 2,
+    "intermediate_size": 16,
+    "hidden_act": "gelu",
+    "hidden_dropout_prob": 0.1,
+    "max_position_embeddings": 32,
+    "type_vocab_size": 2,
+    "initializer_range": 0.02,
+    "layer_norm_eps": 1e-12,
+    "pad_token_id": 0,
+}
+
+VOCAB = [
+    "[PAD]", "[UNK]", "[CLS]", "[SEP]", "the", "onboarding", "checklist", "for",
+    "new", "hires", "deploy", "kubernetes", "cluster", "a", "b", "##s", "lunch",
+    "menu", ",", ".",
+]
+
+TEXTS = [
+    "The onboarding checklist for new hires",
+    "Deploy the Kubernetes cluster.",
+    "lunch menu",
+    "deploys a cluster, b",
+    "the zebra menu",
+]
+
+QUESTION = "deploy the cluster"
+
+DOCUMENTS = [
+    ("deploy", "deploy the kubernetes cluster ."),
+    ("lunch", "the lunch menu , a b"),
+    ("onboarding", "the onboarding checklist for new hires"),
+]
+
+CROSS_ENCODER = "../tinybert-cross-encoder"
+
+random.seed(20231218)
+
+
+def matrix(rows, columns, scale=0.5):
+    return [[random.gauss(0, scale) for _ in range(columns)] for _ in range(rows)]
+
+
+def vector(size, mean=0.0, scale=0.1):
+    return [mean + random.gauss(0, scale) for _ in range(size)]
+
+
+def f32(value):
+    """Rounds values to f32, as stored."""
+    if isinstance(value, list):
+        return [f32(v) for v in value]
+    return struct.unpack("<f", struct.pack("<f", value))[0]
+
+
+def weights():
+    hidden = CONFIG["hidden_size"]
+    intermediate = CONFIG["intermediate_size"]
+    tensors = {
+        "embeddings.word_embeddings.weight": matrix(CONFIG["vocab_size"], hidden),
+        "embeddings.position_embeddings.weight": matrix(CONFIG["max_position_embeddings"], hidden),
+        "embeddings.token_type_embeddings.weight": matrix(CONFIG["type_vocab_size"], hidden),
+        "embeddings.LayerNorm.weight": vector(hidden, 1.0),
+        "embeddings.LayerNorm.bias": vector(hidden),
+    }
+    for layer in range(CONFIG["num_hidden_layers"]):
+        prefix = f"encoder.layer.{layer}."
+        for name in ["query", "key", "value"]:
+            tensors[f"{prefix}attention.self.{name}.weight"] = matrix(hidden, hidden)
+            tensors[f"{prefix}attention.self.{name}.bias"] = vector(hidden)
+        tensors[f"{prefix}attention.output.dense.weight"] = matrix(hidden, hidden)
+        tensors[f"{prefix}attention.output.dense.bias"] = vector(hidden)
+        tensors[f"{prefix}attention.output.LayerNorm.weight"] = vector(hidden, 1.0)
+        tensors[f"{prefix}attention.output.LayerNorm.bias"] = vector(hidden)
+        tensors[f"{prefix}intermediate.dense.weight"] = matrix(intermediate, hidden)
+        tensors[f"{prefix}intermediate.dense.bias"] = vector(intermediate)
+        tensors[f"{prefix}output.dense.weight"] = matrix(hidden, intermediate)
+        tensors[f"{prefix}output.dense.bias"] = vector(hidden)
+        tensors[f"{prefix}output.LayerNorm.weight"] = vector(hidden, 1.0)
+        tensors[f"{prefix}output.LayerNorm.bias"] = vector(hidden)
+
+    return {name: f32(tensor) for name, tensor in tensors.items()}
+
+
+def cross_encoder_weights():
+    """A BERT model under the `bert.` prefix, with a pooler and a single label."""
+    hidden = CONFIG["hidden_size"]
+    tensors = {f"bert.{name}": tensor for name, tensor in weights().items()}
+    tensors["bert.pooler.dense.weight"] = f32(matrix(hidden, hidden))
+    tensors["bert.pooler.dense.bias"] = f32(vector(hidden))
+    tensors["classifier.weight"] = f32(matrix(1, hidden))
+    tensors["classifier.bias"] = f32(vector(1))
+    return tensors
+
+
+def save_safetensors(path, tensors):
+    header, data = {}, b""
+    for name, tensor in tensors.items():
+        flat = [v for row in tensor for v in row] if isinstance(tensor[0], list) else tensor
+        shape = [len(tensor), len(tensor[0])] if isinstance(tensor[0], list) else [len(tensor)]
+        raw = struct.pack(f"<{len(flat)}f", *flat)
+        header[name] = {"dtype": "F32", "shape": shape, "data_offsets": [len(data), len(data) + len(raw)]}
+        data += raw
+    encoded = json.dumps(header).encode()
+    encoded += b" " * (-len(encoded) % 8)
+    with open(path, "wb") as file:
+        file.write(struct.pack("<Q", len(encoded)) + encoded + data)
+
+
+def save_tokenizer(path):
+    special = [
+        {"id": i, "content": token, "single_word": False, "lstrip": False, "rstrip": False,
+         "normalized": False, "special": True}
+        for i, token in enumerate(VOCAB[:4])
+    ]
+    tokenizer = {
+        "version": "1.0",
+        "truncation": None,
+        "padding": None,
+        "added_tokens": special,
+        "normalizer": {"type": "BertNormalizer", "clean_text": True, "handle_chinese_chars": True,
+                       "strip_accents": None, "lowercase": True},
+        "pre_tokenizer": {"type": "BertPreTokenizer"},
+        "post_processor": {
+            "type": "TemplateProcessing",
+            "single": [{"SpecialToken": {"id": "[CLS]", "type_id": 0}},
+                       {"Sequence": {"id": "A", "type_id": 0}},
+                       {"SpecialToken": {"id": "[SEP]", "type_id": 0}}],
+            "pair": [{"SpecialToken": {"id": "[CLS]", "type_id": 0}},
+                     {"Sequence": {"id": "A", "type_id": 0}},
+                     {"SpecialToken": {"id": "[SEP]", "type_id": 0}},
+                     {"Sequence": {"id": "B", "type_id": 1}},
+                     {"SpecialToken": {"id": "[SEP]", "type_id": 1}}],
+            "special_tokens": {"[CLS]": {"id": "[CLS]", "ids": [2], "tokens": ["[CLS]"]},
+                               "[SEP]": {"id": "[SEP]", "ids": [3], "tokens": ["[SEP]"]}},
+        },
+        "decoder": {"type": "WordPiece", "prefix": "##", "cleanup": True},
+        "model": {"type": "WordPiece", "unk_token": "[UNK]", "continuing_subword_prefix": "##",
+                  "max_input_chars_per_word": 100, "vocab": {t: i for i, t in enumerate(VOCAB)}},
+    }
+    with open(path, "w") as file:
+        json.dump(tokenizer, file)
+
+
+def tokenize(text):
+    return [VOCAB.index("[CLS]")] + pieces(text) + [VOCAB.index("[SEP]")]
+
+
+def tokenize_pair(first, second):
+    """The ids and token types of a question and a document read together."""
+    first = [VOCAB.index("[CLS]")] + pieces(first) + [VOCAB.index("[SEP]")]
+    second = pieces(second) + [VOCAB.index("[SEP]")]
+    return first + second, [0] * len(first) + [1] * len(second)
+
+
+def pieces(text):
+    words = []
+    for word in text.lower().split():
+        words.extend(piece for piece in word.replace(",", " , ").replace(".", " . ").split())
+
+    ids = []
+    for word in words:
+        pieces, start = [], 0
+        while start < len(word):
+            end = len(word)
+            while end > start:
+                piece = word[start:end] if start == 0 else "##" + word[start:end]
+                if piece in VOCAB:
+                    break
+                end -= 1
+            if end == start:
+                pieces = [VOCAB.index("[UNK]")]
+                break
+            pieces.append(VOCAB.index(piece))
+            start = end
+        ids.extend(pieces)
+    return ids
+
+
+def linear(x, w, b):
+    return [sum(wi * xi for wi, xi in zip(row, x)) + bi for row, bi in zip(w, b)]
+
+
+def layer_norm(x, w, b):
+    mean = sum(x) / len(x)
+    variance = sum((v - mean) ** 2 for v in x) / len(x)
+    return [(v - mean) / math.sqrt(variance + CONFIG["layer_norm_eps"]) * wi + bi
+            for v, wi, bi in zip(x, w, b)]
+
+
+def gelu(x):
+    return 0.5 * x * (1 + math.erf(x / math.sqrt(2)))
+
+
+def encode(tensors, ids, types):
+    t = lambda name: tensors[name]
+    states = [
+        layer_norm(
+            [w + p + s for w, p, s in zip(t("embeddings.word_embeddings.weight")[token],
+                                          t("embeddings.position_embeddings.weight")[position],
+                                          t("embeddings.token_type_embeddings.weight")[kind])],
+            t("embeddings.LayerNorm.weight"), t("embeddings.LayerNorm.bias"))
+        for position, (token, kind) in enumerate(zip(ids, types))
+    ]
+
+    heads = CONFIG["num_attention_heads"]
+    size = CONFIG["hidden_size"] // heads
+    for layer in range(CONFIG["num_hidden_layers"]):
+        p = f"encoder.layer.{layer}."
+        q, k, v = ([linear(x, t(f"{p}attention.self.{n}.weight"), t(f"{p}attention.self.{n}.bias"))
+                    for x in states] for n in ["query", "key", "value"])
+        context = [[0.0] * CONFIG["hidden_size"] for _ in states]
+        for h in range(heads):
+            part = slice(h * size, (h + 1) * size)
+            for i in range(len(states)):
+                scores = [sum(a * b for a, b in zip(q[i][part], k[j][part])) / math.sqrt(size)
+                          for j in range(len(states))]
+                top = max(scores)
+                exp = [math.exp(s - top) for s in scores]
+                probabilities = [e / sum(exp) for e in exp]
+                for d in range(size):
+                    context[i][h * size + d] = sum(pr * v[j][h * size + d]
+                                                   for j, pr in enumerate(probabilities))
+        attention = [
+            layer_norm([a + x for a, x in zip(linear(c, t(f"{p}attention.output.dense.weight"),
+                                                     t(f"{p}attention.output.dense.bias")), s)],
+                       t(f"{p}attention.output.LayerNorm.weight"), t(f"{p}attention.output.LayerNorm.bias"))
+            for c, s in zip(context, states)
+        ]
+        states = [
+            layer_norm([o + a for o, a in zip(
+                linear([gelu(i) for i in linear(a, t(f"{p}intermediate.dense.weight"),
+                                                t(f"{p}intermediate.dense.bias"))],
+                       t(f"{p}output.dense.weight"), t(f"{p}output.dense.bias")), a)],
+                t(f"{p}output.LayerNorm.weight"), t(f"{p}output.LayerNorm.bias"))
+            for a in attention
+        ]
+
+    return states
+
+
+def embed(tensors, text):
+    ids = tokenize(text)
+    states = encode(tensors, ids, [0] * len(ids))
+    mean = [sum(column) / len(states) for column in zip(*states)]
+    norm = math.sqrt(sum(v * v for v in mean))
+    return [v / norm for v in mean]
+
+
+def score(tensors, question, document):
+    """The probability of the single label, from the first token."""
+    bert = {name[len("bert."):]: tensor for name, tensor in tensors.items()}
+    states = encode(bert, *tokenize_pair(question, document))
+    pooled = [math.tanh(v) for v in linear(states[0], bert["pooler.dense.weight"],
+                                           bert["pooler.dense.bias"])]
+    logit = linear(pooled, tensors["classifier.weight"], tensors["classifier.bias"])[0]
+    return 1 / (1 + math.exp(-logit))
+
+
+def main():
+    tensors = weights()
+    with open("config.json", "w") as file:
+        json.dump(CONFIG, file)
+    save_tokenizer("tokenizer.json")
+    save_safetensors("model.safetensors", tensors)
+    with open("expected.json", "w") as file:
+        json.dump({"texts": TEXTS, "vectors": [embed(tensors, text) for text in TEXTS]}, file, indent=2)
+
+    tensors = cross_encoder_weights()
+    os.makedirs(CROSS_ENCODER, exist_ok=True)
+    with open(f"{CROSS_ENCODER}/config.json", "w") as file:
+        json.dump({**CONFIG, "id2label": {"0": "LABEL_0"}}, file)
+    save_tokenizer(f"{CROSS_ENCODER}/tokenizer.json")
+    save_safetensors(f"{CROSS_ENCODER}/model.safetensors", tensors)
+    # Documents are read with their name, like the cross-encoder does.
+    scores = [score(tensors, QUESTION, f"{name}\n{content}") for name, content in DOCUMENTS]
+    with open(f"{CROSS_ENCODER}/expected.json", "w") as file:
+        json.dump({"question": QUESTION, "documents": DOCUMENTS, "scores": scores}, file, indent=2)
+
+
+if __name__ == "__main__":
+    main()
@@ -1,9 +1,10 @@
 use std::fs;
 
 use anyhow::{anyhow, Result};
+use chrono::Duration;
 use clap::{Parser, Subcommand};
 use log::LevelFilter;
-use savoir::{app::App, interals::AsyncTryFrom};
+use savoir::{app::App, document_store::Query, interals::AsyncTryFrom};
 
 #[derive(Parser, Debug)]
 #[command(author, version)]
@@ -14,12 +15,70 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Command {
-    Synchronize { datasource: String },
-    Ask { agent: String, query: String },
-    Search { query: String },
-    Serve { integration: String },
+    Synchronize {
+        datasource: String,
+        /// Only print the documents that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+        /// List every document again instead of the changes since the last run
+        #[arg(long)]
+        full: bool,
+    },
+    Ask {
+        agent: String,
+        query: String,
+    },
+    Search {
+        query: String,
+        /// Number of documents to return
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Minimum similarity, between 0 and 1, of the documents
+        #[arg(long)]
+        min_score: Option<f64>,
+        /// Only search the documents of these datasources
+        #[arg(long)]
+        source: Vec<String>,
+        /// Fuse a keyword search with the vector search
+        #[arg(long)]
+        hybrid: bool,
+    },
+    Serve {
+        integration: String,
+    },
+    Store {
+        #[command(subcommand)]
+        command: StoreCommand,
+    },
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
 }
 
+#[derive(Subcommand, Debug)]
+enum StoreCommand {
+    /// Apply the additive schema changes to the document store
+    Migrate,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Print the size and the hit rate of the embedding cache
+    Stats,
+    /// Remove vectors from the embedding cache
+    Prune {
+        /// Remove the vectors unused for this number of days, 30 unless a model is given
+        #[arg(long)]
+        unused_for: Option<i64>,
+        /// Remove every vector of this embedder, like `text-embedding-3-small/1536`
+        #[arg(long)]
+        model: Option<String>,
+    },
+}
+
+const DEFAULT_UNUSED_DAYS: i64 = 30;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let config = fs::read_to_string("savoir.yaml").unwrap();
@@ -34,19 +93,72 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Command::Synchronize { datasource } => app.synchronize(&datasource).await,
-        Command::Search { query } => match app.query(&query).await {
-            Ok(documents) => {
-                println!("---------------------");
-                for document in documents {
-                    println!("Name: {}", document.name);
-                    println!("---------------------");
+        Command::Synchronize {
+            datasource,
+            dry_run,
+            full,
+        } => {
+            let report = app.synchronize(&datasource, dry_run, full).await?;
+            for id in &report.deleted {
+                match dry_run {
+                    true => println!("Would delete {id}"),
+                    false => println!("Deleted {id}"),
                 }
+            }
+            for failure in &report.failed {
+                println!("Failed {}: {}", failure.id, failure.reason);
+            }
+            println!("Synchronized {datasource}: {report}");
+            Ok(())
+        }
+        Command::Search {
+            query,
+            limit,
+            min_score,
+            source,
+            hybrid,
+        } => {
+            let mut query = Query::new(&query);
+            query.limit = limit.unwrap_or(query.limit);
+            query.min_score = min_score;
+            query.filters.sources = source;
+            query.hybrid = hybrid.then(Default::default);
 
-                Ok(())
+            match app.query(&query).await {
+                Ok(documents) => {
+                    println!("---------------------");
+                    for result in documents {
+                        let document = result.document;
+                        println!("Name: {}", document.name);
+                        match result.distance {
+                            Some(distance) => {
+                                println!("Score: {:.3} (distance {distance:.3})", result.score)
+                            }
+                            None => println!("Score: {:.3}", result.score),
+                        }
+                        if let Some(url) = document.url {
+                            println!("Url: {url}");
+                        }
+                        if let Some(source) = document.provenance.source {
+                            println!("Source: {source}");
+                        }
+                        if let Some(modified_at) = document.provenance.modified_at {
+                            println!("Last updated: {modified_at}");
+                        }
+                        if let Some(chunk) = document.chunk {
+                            println!("Chunk: {} of {}", chunk.position, chunk.parent_id);
+                        }
+                        if let Some(highlight) = result.highlight {
+                            println!("Highlight: {highlight}");
+                        }
+                        println!("---------------------");
+                    }
+
+                    Ok(())
+                }
+                Err(e) => Err(anyhow!("Something wrong happened {e}")),
             }
-            Err(e) => Err(anyhow!("Something wrong happened {e}")),
-        },
+        }
         Command::Ask { agent, query } => match app.ask(&agent, "cli", &query).await {
             Ok(res) => {
                 println!("Answer: {res}");
@@ -55,5 +167,54 @@ async fn main() -> Result<()> {
             Err(e) => Err(e),
         },
         Command::Serve { integration } => app.run_integration(&integration).await,
+        Command::Store {
+            command: StoreCommand::Migrate,
+        } => {
+            let changes = app.migrate_store().await?;
+            for change in &changes {
+                println!("Migrated: {change}");
+            }
+            if changes.is_empty() {
+                println!("The document store is up to date");
+            }
+            Ok(())
+        }
+        Command::Cache {
+            command: CacheCommand::Stats,
+        } => {
+            let stats = app.cache_stats()?;
+            for model in &stats {
+                let lookups = model.hits + model.misses;
+                let hit_rate = match lookups {
+                    0 => 0.0,
+                    lookups => model.hits as f64 / lookups as f64 * 100.0,
+                };
+                println!(
+                    "{}: {} vectors ({} bytes), {} hits, {} misses ({hit_rate:.1}% hit rate)",
+                    model.model, model.entries, model.bytes, model.hits, model.misses
+                );
+            }
+            if stats.is_empty() {
+                println!("The embedding cache is empty");
+            }
+            Ok(())
+        }
+        Command::Cache {
+            command: CacheCommand::Prune { unused_for, model },
+        } => {
+            let unused_for = match (unused_for, &model) {
+                (None, None) => Some(DEFAULT_UNUSED_DAYS),
+                (unused_for, _) => unused_for,
+            };
+            let unused_for = unused_for
+                .map(|days| {
+                    Duration::try_days(days)
+                        .ok_or_else(|| anyhow!("{days} days is out of the supported range"))
+                })
+                .transpose()?;
+            let removed = app.prune_cache(unused_for, model.as_deref())?;
+            println!("Removed {removed} vectors from the embedding cache");
+            Ok(())
+        }
     }
 }